@@ -0,0 +1,24 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sn_messaging::{client::Message, WireMsg};
+
+// Serializes well-formed client messages, which must be deserialized back to the same message.
+fuzz_target!(|msg: Message| {
+    if let Ok(wire_msg) = WireMsg::new_client_msg(&msg) {
+        let bytes = wire_msg.serialize().expect("failed to serialize the wire message");
+        let deserialized = WireMsg::deserialize(bytes)
+            .and_then(|wire_msg| wire_msg.into_client_msg())
+            .expect("failed to deserialize the client message");
+        assert_eq!(deserialized, msg);
+    }
+});
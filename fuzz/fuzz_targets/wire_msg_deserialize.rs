@@ -0,0 +1,21 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+#![no_main]
+
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+use sn_messaging::WireMsg;
+
+// Deserializes arbitrary frames, as could be received from a malicious peer.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(wire_msg) = WireMsg::deserialize(Bytes::copy_from_slice(data)) {
+        let _ = wire_msg.to_message();
+    }
+});
@@ -0,0 +1,141 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::{Message, MessageId};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// Remembers the outcome of the client cmds applied within a time window, so that Elders
+/// can tell whether a cmd depending on other cmds, see `Message::with_depends_on`, can be
+/// applied. A cmd is applied only once all its dependencies were applied successfully;
+/// it is held while any of them is pending, and rejected with `Error::DependencyFailed`
+/// as soon as one of them failed.
+#[derive(Debug)]
+pub struct DependencyBarrier {
+    ttl: Duration,
+    outcomes: BTreeMap<MessageId, bool>,
+    // Ids in the order their outcome was recorded, to expire them.
+    expiry: VecDeque<(Instant, MessageId)>,
+}
+
+/// Whether a cmd can be applied, as per its dependencies, see `DependencyBarrier`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Readiness {
+    /// All dependencies were applied successfully, the cmd can be applied.
+    Ready,
+    /// The cmd is to be held until the outcome of these dependencies is known.
+    Pending(Vec<MessageId>),
+    /// This dependency failed, the cmd is to be rejected with `Error::DependencyFailed`.
+    Failed(MessageId),
+}
+
+impl DependencyBarrier {
+    /// Creates a new instance, remembering outcomes for the given time.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            outcomes: BTreeMap::new(),
+            expiry: VecDeque::new(),
+        }
+    }
+
+    /// Records whether the cmd with the given id was applied successfully.
+    pub fn record(&mut self, msg_id: MessageId, succeeded: bool) {
+        self.record_at(msg_id, succeeded, Instant::now())
+    }
+
+    /// Records whether the cmd was applied successfully, as of the given instant.
+    pub fn record_at(&mut self, msg_id: MessageId, succeeded: bool, now: Instant) {
+        self.prune_at(now);
+        if self.outcomes.insert(msg_id, succeeded).is_none() {
+            self.expiry.push_back((now + self.ttl, msg_id));
+        }
+    }
+
+    /// Returns whether the message can be applied, as per the outcomes recorded so far.
+    /// Messages without dependencies are always ready.
+    pub fn readiness(&self, msg: &Message) -> Readiness {
+        let mut pending = vec![];
+        for id in msg.depends_on() {
+            match self.outcomes.get(id) {
+                Some(true) => (),
+                Some(false) => return Readiness::Failed(*id),
+                None => pending.push(*id),
+            }
+        }
+        if pending.is_empty() {
+            Readiness::Ready
+        } else {
+            Readiness::Pending(pending)
+        }
+    }
+
+    /// Forgets the outcomes recorded longer ago than the time to live, as of the given instant.
+    pub fn prune_at(&mut self, now: Instant) {
+        while let Some((expires_at, msg_id)) = self.expiry.front().copied() {
+            if expires_at > now {
+                break;
+            }
+            let _ = self.expiry.pop_front();
+            let _ = self.outcomes.remove(&msg_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Cmd;
+    use anyhow::Result;
+    use xor_name::XorName;
+
+    #[test]
+    fn dependent_cmds() -> Result<()> {
+        let echo = || Message::Cmd {
+            cmd: Cmd::Echo {
+                client: XorName::default(),
+                payload: vec![],
+            },
+            id: MessageId::new(),
+            app_id: None,
+            idempotency_key: None,
+            depends_on: vec![],
+        };
+        let (create, other) = (MessageId::new(), MessageId::new());
+        let edit = echo().with_depends_on(vec![create, other]);
+        assert_eq!(Message::from(edit.serialize()?)?, edit);
+        assert_eq!(edit.depends_on(), &[create, other]);
+
+        let now = Instant::now();
+        let mut barrier = DependencyBarrier::new(Duration::from_secs(60));
+        assert_eq!(barrier.readiness(&echo()), Readiness::Ready);
+        assert_eq!(
+            barrier.readiness(&edit),
+            Readiness::Pending(vec![create, other])
+        );
+
+        barrier.record_at(create, true, now);
+        assert_eq!(barrier.readiness(&edit), Readiness::Pending(vec![other]));
+        barrier.record_at(other, true, now);
+        assert_eq!(barrier.readiness(&edit), Readiness::Ready);
+
+        barrier.record_at(other, false, now);
+        assert_eq!(barrier.readiness(&edit), Readiness::Failed(other));
+
+        barrier.prune_at(now + Duration::from_secs(61));
+        assert_eq!(
+            barrier.readiness(&edit),
+            Readiness::Pending(vec![create, other])
+        );
+
+        Ok(())
+    }
+}
@@ -7,10 +7,12 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use super::{AuthorisationKind, CmdError, DataAuthKind, Error, QueryResponse};
+use super::{
+    AuthorisationKind, CmdError, DataAddress, DataAuthKind, DataOpKind, Error, QueryResponse,
+};
 use serde::{Deserialize, Serialize};
 use sn_data_types::{Blob, BlobAddress, PublicKey};
-use std::fmt;
+use std::{collections::BTreeMap, fmt};
 use xor_name::XorName;
 
 /// TODO: docs
@@ -18,6 +20,14 @@ use xor_name::XorName;
 pub enum BlobRead {
     /// TODO: docs
     Get(BlobAddress),
+    /// Get a batch of Blobs in a single query, e.g. the handful of chunks of a small file.
+    /// The query is routed to the section of the first address, so all the addresses are
+    /// expected to be handled by that section, see `BlobWrite::DeleteBatch`.
+    /// The batch can't be empty, see `Message::check_limits`.
+    GetChunks(Vec<BlobAddress>),
+    /// Get the metadata a Blob was stored with, see `BlobWrite::NewWithMetadata`,
+    /// e.g. for a gateway to serve the Blob over HTTP with the right headers.
+    GetMetadata(BlobAddress),
 }
 
 /// TODO: docs
@@ -28,6 +38,40 @@ pub enum BlobWrite {
     New(Blob),
     /// TODO: docs
     DeletePrivate(BlobAddress),
+    /// Delete a batch of private Blobs, e.g. all the chunks of a private file.
+    /// The cmd is routed to the section of the first address, so all the addresses are
+    /// expected to be handled by that section, which the sender is to check against the
+    /// section prefixes it knows of; the section returns an error for the addresses it
+    /// doesn't hold. The batch can't be empty, see `Message::check_limits`.
+    DeleteBatch(Vec<BlobAddress>),
+    /// Store a new Blob along with its metadata, which can be read back with `BlobRead::GetMetadata`.
+    NewWithMetadata {
+        /// The new Blob.
+        blob: Blob,
+        /// Its metadata, of at most `MAX_BLOB_METADATA_SIZE` bytes.
+        metadata: BlobMetadata,
+    },
+}
+
+/// Metadata stored along with a Blob, see `BlobWrite::NewWithMetadata`.
+#[derive(Debug, Default, Hash, Eq, PartialEq, PartialOrd, Clone, Serialize, Deserialize)]
+pub struct BlobMetadata {
+    /// MIME type of the content of the Blob, e.g. "text/html".
+    pub content_type: Option<String>,
+    /// Metadata set by the user, by name.
+    pub user_meta: BTreeMap<String, Vec<u8>>,
+}
+
+impl BlobMetadata {
+    /// Returns the size of the metadata, in bytes, as checked against `MAX_BLOB_METADATA_SIZE`.
+    pub fn size(&self) -> usize {
+        self.content_type.as_ref().map_or(0, String::len)
+            + self
+                .user_meta
+                .iter()
+                .map(|(name, value)| name.len() + value.len())
+                .sum::<usize>()
+    }
 }
 
 impl BlobRead {
@@ -43,32 +87,79 @@ impl BlobRead {
     /// Creates a Response containing an error, with the Response variant corresponding to the
     /// Request variant.
     pub fn error(&self, error: Error) -> QueryResponse {
-        QueryResponse::GetBlob(Err(error))
+        use BlobRead::*;
+        match self {
+            Get(_) => QueryResponse::GetBlob(Err(error.into())),
+            GetChunks(_) => QueryResponse::GetChunks(Err(error.into())),
+            GetMetadata(_) => QueryResponse::GetBlobMetadata(Err(error.into())),
+        }
     }
 
     /// Returns the type of authorisation needed for the request.
     pub fn authorisation_kind(&self) -> AuthorisationKind {
         use BlobRead::*;
         match self {
-            Get(BlobAddress::Public(_)) => AuthorisationKind::Data(DataAuthKind::PublicRead),
-            Get(BlobAddress::Private(_)) => AuthorisationKind::Data(DataAuthKind::PrivateRead),
+            Get(BlobAddress::Public(_)) | GetMetadata(BlobAddress::Public(_)) => {
+                AuthorisationKind::Data(DataAuthKind::PublicRead)
+            }
+            Get(BlobAddress::Private(_)) | GetMetadata(BlobAddress::Private(_)) => {
+                AuthorisationKind::Data(DataAuthKind::PrivateRead)
+            }
+            GetChunks(addresses) => {
+                if addresses.iter().all(|address| address.is_pub()) {
+                    AuthorisationKind::Data(DataAuthKind::PublicRead)
+                } else {
+                    AuthorisationKind::Data(DataAuthKind::PrivateRead)
+                }
+            }
         }
     }
 
-    /// Returns the address of the destination for `request`.
+    /// Returns the address of the destination for `request`, i.e. that of the first
+    /// address of a batch, see `GetChunks`.
     pub fn dst_address(&self) -> XorName {
         use BlobRead::*;
         match self {
-            Get(ref address) => *address.name(),
+            Get(ref address) | GetMetadata(ref address) => *address.name(),
+            GetChunks(ref addresses) => addresses
+                .first()
+                .map(|address| *address.name())
+                .unwrap_or_default(),
         }
     }
 }
 
 impl BlobWrite {
-    /// Creates a Response containing an error, with the Response variant corresponding to the
-    /// Request variant.
+    /// Creates a Response containing an error, attributed to the address of the Blob written,
+    /// or to each of the addresses of a `DeleteBatch`, see `CmdError::DataBatch`.
     pub fn error(&self, error: Error) -> CmdError {
-        CmdError::Data(error)
+        use BlobWrite::*;
+        let address = match self {
+            New(ref data) | NewWithMetadata { blob: ref data, .. } => *data.address(),
+            DeletePrivate(ref address) => *address,
+            DeleteBatch(ref addresses) => {
+                return CmdError::DataBatch(
+                    addresses
+                        .iter()
+                        .map(|address| (*address, error.clone()))
+                        .collect(),
+                )
+            }
+        };
+        CmdError::Data {
+            address: DataAddress::Blob(address),
+            op: self.op(),
+            error: error.into(),
+        }
+    }
+
+    /// Returns the kind of operation of the request.
+    pub fn op(&self) -> DataOpKind {
+        use BlobWrite::*;
+        match self {
+            New(_) | NewWithMetadata { .. } => DataOpKind::New,
+            DeletePrivate(_) | DeleteBatch(_) => DataOpKind::Delete,
+        }
     }
 
     /// Returns the type of authorisation needed for the request.
@@ -76,20 +167,25 @@ impl BlobWrite {
         AuthorisationKind::Data(DataAuthKind::Write)
     }
 
-    /// Returns the address of the destination for `request`.
+    /// Returns the address of the destination for `request`, i.e. that of the first
+    /// address of a batch, see `DeleteBatch`.
     pub fn dst_address(&self) -> XorName {
         use BlobWrite::*;
         match self {
-            New(ref data) => *data.name(),
+            New(ref data) | NewWithMetadata { blob: ref data, .. } => *data.name(),
             DeletePrivate(ref address) => *address.name(),
+            DeleteBatch(ref addresses) => addresses
+                .first()
+                .map(|address| *address.name())
+                .unwrap_or_default(),
         }
     }
 
     /// Returns the owner of the data on a New Blob write.
     pub fn owner(&self) -> Option<PublicKey> {
         match self {
-            Self::New(data) => data.owner().cloned(),
-            Self::DeletePrivate(_) => None,
+            Self::New(data) | Self::NewWithMetadata { blob: data, .. } => data.owner().cloned(),
+            Self::DeletePrivate(_) | Self::DeleteBatch(_) => None,
         }
     }
 }
@@ -99,6 +195,8 @@ impl fmt::Debug for BlobRead {
         use BlobRead::*;
         match self {
             Get(req) => write!(formatter, "{:?}", req),
+            GetChunks(addresses) => write!(formatter, "BlobRead::GetChunks({:?})", addresses),
+            GetMetadata(address) => write!(formatter, "BlobRead::GetMetadata({:?})", address),
         }
     }
 }
@@ -109,6 +207,12 @@ impl fmt::Debug for BlobWrite {
         match self {
             New(blob) => write!(formatter, "BlobWrite::New({:?})", blob),
             DeletePrivate(address) => write!(formatter, "BlobWrite::DeletePrivate({:?})", address),
+            DeleteBatch(addresses) => write!(formatter, "BlobWrite::DeleteBatch({:?})", addresses),
+            NewWithMetadata { blob, metadata } => write!(
+                formatter,
+                "BlobWrite::NewWithMetadata {{ blob: {:?}, metadata: {:?} }}",
+                blob, metadata
+            ),
         }
     }
 }
@@ -7,9 +7,15 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use super::{data::DataCmd, transfer::TransferCmd, AuthorisationKind};
+use super::{
+    data::{DataAddress, DataCmd},
+    transfer::TransferCmd,
+    AuthorisationKind, CmdError, DataAuthKind, Error, PrepaidOps,
+};
+use crate::MessageId;
 use serde::{Deserialize, Serialize};
 use sn_data_types::TransferAgreementProof;
+use std::time::SystemTime;
 use xor_name::XorName;
 
 /// Command messages for data or transfer operations
@@ -22,9 +28,66 @@ pub enum Cmd {
         cmd: DataCmd,
         /// Proof of payment for the data command
         payment: TransferAgreementProof,
+        /// Time before which the Elders are to hold the command rather than apply it,
+        /// e.g. for the embargoed publication of data. Applied right away if not set.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        effective_after: Option<SystemTime>,
+    },
+    /// Commands for manipulating data, paid for out of a bundle of prepaid operations
+    PrepaidData {
+        /// The data command struct itself
+        cmd: DataCmd,
+        /// The bundle of prepaid operations to pay out of
+        prepaid: PrepaidOps,
+        /// Time before which the Elders are to hold the command, see `Cmd::Data`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        effective_after: Option<SystemTime>,
     },
     /// Command for transfering safe network tokens
     Transfer(TransferCmd),
+    /// Command to test the connectivity with the client's Elders, each of which
+    /// replies with an `Event::EchoReply` carrying the same payload.
+    Echo {
+        /// This is the client id.
+        client: XorName,
+        /// The payload to echo, of at most `MAX_ECHO_PAYLOAD_SIZE` bytes.
+        #[serde(with = "serde_bytes")]
+        payload: Vec<u8>,
+    },
+    /// Request the Elders to challenge the holders of the data with a proof-of-storage
+    /// challenge. The outcome is sent back to the owner as an `Event::AuditReport`.
+    /// Only the owner of the data can request an audit of it, public or private.
+    RequestAudit {
+        /// Address of the data to audit.
+        address: DataAddress,
+        /// Proof of payment for the audit.
+        payment: TransferAgreementProof,
+    },
+    /// Cancel a data command held by the Elders until its `effective_after` time.
+    CancelScheduled {
+        /// Address of the data the command applies to.
+        address: DataAddress,
+        /// Id of the message which carried the command.
+        cmd_id: MessageId,
+    },
+}
+
+/// Status of a data command sent with an `effective_after` time,
+/// see `Query::GetScheduledCmdStatus`.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum ScheduledCmdStatus {
+    /// The command is held by the Elders, to be applied after the given time.
+    Pending {
+        /// When the command is to be applied.
+        effective_after: SystemTime,
+    },
+    /// The command was applied.
+    Applied {
+        /// When the command was applied.
+        applied_at: SystemTime,
+    },
+    /// The command was cancelled with `Cmd::CancelScheduled` before being applied.
+    Cancelled,
 }
 
 impl Cmd {
@@ -32,8 +95,25 @@ impl Cmd {
     pub fn authorisation_kind(&self) -> AuthorisationKind {
         use Cmd::*;
         match self {
-            Data { cmd, .. } => cmd.authorisation_kind(),
+            Data { cmd, .. } | PrepaidData { cmd, .. } => cmd.authorisation_kind(),
             Transfer(c) => c.authorisation_kind(),
+            Echo { .. } => AuthorisationKind::None,
+            // Audits are paid for and answered to the owner, so only the owner can request
+            // them, be the data public or private.
+            CancelScheduled { .. } | RequestAudit { .. } => {
+                AuthorisationKind::Data(DataAuthKind::Write)
+            }
+        }
+    }
+
+    /// Creates a Response containing an error, with the Response variant corresponding to the
+    /// Request variant.
+    pub fn error(&self, error: Error) -> CmdError {
+        use Cmd::*;
+        match self {
+            Data { cmd, .. } | PrepaidData { cmd, .. } => cmd.error(error),
+            Transfer(c) => c.error(error),
+            Echo { .. } | RequestAudit { .. } | CancelScheduled { .. } => CmdError::Other(error),
         }
     }
 
@@ -41,8 +121,23 @@ impl Cmd {
     pub fn dst_address(&self) -> XorName {
         use Cmd::*;
         match self {
-            Data { cmd, .. } => cmd.dst_address(),
+            Data { cmd, .. } | PrepaidData { cmd, .. } => cmd.dst_address(),
             Transfer(c) => c.dst_address(),
+            Echo { client, .. } => *client,
+            RequestAudit { address, .. } | CancelScheduled { address, .. } => *address.name(),
+        }
+    }
+
+    /// Returns the time before which the command is to be held rather than applied, if any.
+    pub fn effective_after(&self) -> Option<SystemTime> {
+        match self {
+            Self::Data {
+                effective_after, ..
+            }
+            | Self::PrepaidData {
+                effective_after, ..
+            } => *effective_after,
+            _ => None,
         }
     }
 }
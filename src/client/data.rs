@@ -11,13 +11,15 @@ use super::{
     blob::{BlobRead, BlobWrite},
     map::{MapRead, MapWrite},
     sequence::{SequenceRead, SequenceWrite},
-    AuthorisationKind, CmdError, Error, QueryResponse,
+    AuthorisationKind, CmdError, DataAuthKind, Error, QueryResponse,
 };
-use sn_data_types::PublicKey;
+use crate::signing::{self, AUDIT_REPORT_DOMAIN, REPLICATION_STATUS_DOMAIN};
+use sn_data_types::{BlobAddress, MapAddress, PublicKey, SequenceAddress};
+use threshold_crypto::{PublicKey as BlsPublicKey, Signature as BlsSignature};
 use xor_name::XorName;
 
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{collections::BTreeMap, fmt, time::SystemTime};
 
 /// Data command operations. Creating, updating or removing data
 #[allow(clippy::large_enum_variant)]
@@ -32,8 +34,8 @@ pub enum DataCmd {
 }
 
 impl DataCmd {
-    /// Creates a Response containing an error, with the Response variant corresponding to the
-    /// cuest variant.
+    /// Creates a Response containing an error, attributed to the address of the data written,
+    /// see `CmdError::Data`.
     pub fn error(&self, error: Error) -> CmdError {
         use DataCmd::*;
         match self {
@@ -62,6 +64,15 @@ impl DataCmd {
         }
     }
 
+    /// Returns the kind of operation of the cmd.
+    pub fn op(&self) -> DataOpKind {
+        match self {
+            Self::Blob(write) => write.op(),
+            Self::Map(write) => write.op(),
+            Self::Sequence(write) => write.op(),
+        }
+    }
+
     /// Returns the owner of the data.
     pub fn owner(&self) -> Option<PublicKey> {
         match self {
@@ -93,6 +104,9 @@ pub enum DataQuery {
     Map(MapRead),
     /// TODO: docs
     Sequence(SequenceRead),
+    /// Get the replication status of the data at the given address,
+    /// so that owners can monitor its durability.
+    GetReplicationStatus(DataAddress),
 }
 
 impl DataQuery {
@@ -104,6 +118,7 @@ impl DataQuery {
             Blob(q) => q.error(error),
             Map(q) => q.error(error),
             Sequence(q) => q.error(error),
+            GetReplicationStatus(_) => QueryResponse::GetReplicationStatus(Err(error.into())),
         }
     }
 
@@ -114,6 +129,13 @@ impl DataQuery {
             Blob(q) => q.authorisation_kind(),
             Map(q) => q.authorisation_kind(),
             Sequence(q) => q.authorisation_kind(),
+            GetReplicationStatus(address) => {
+                if address.is_pub() {
+                    AuthorisationKind::Data(DataAuthKind::PublicRead)
+                } else {
+                    AuthorisationKind::Data(DataAuthKind::PrivateRead)
+                }
+            }
         }
     }
 
@@ -124,6 +146,7 @@ impl DataQuery {
             Blob(q) => q.dst_address(),
             Map(q) => q.dst_address(),
             Sequence(q) => q.dst_address(),
+            GetReplicationStatus(address) => *address.name(),
         }
     }
 }
@@ -135,6 +158,145 @@ impl fmt::Debug for DataQuery {
             Blob(q) => write!(formatter, "{:?}", q),
             Map(q) => write!(formatter, "{:?}", q),
             Sequence(q) => write!(formatter, "{:?}", q),
+            GetReplicationStatus(address) => {
+                write!(formatter, "DataQuery::GetReplicationStatus({:?})", address)
+            }
         }
     }
 }
+
+/// Address of any of the data types stored on the network.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum DataAddress {
+    /// Address of a Blob.
+    Blob(BlobAddress),
+    /// Address of a Map.
+    Map(MapAddress),
+    /// Address of a Sequence.
+    Sequence(SequenceAddress),
+}
+
+impl DataAddress {
+    /// Returns the name of the data.
+    pub fn name(&self) -> &XorName {
+        match self {
+            Self::Blob(address) => address.name(),
+            Self::Map(address) => address.name(),
+            Self::Sequence(address) => address.name(),
+        }
+    }
+
+    /// Returns true if the data is public.
+    pub fn is_pub(&self) -> bool {
+        match self {
+            Self::Blob(address) => address.is_pub(),
+            Self::Map(_) => false,
+            Self::Sequence(address) => address.is_pub(),
+        }
+    }
+}
+
+/// Kind of operation of a data cmd, see `CmdError::Data`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum DataOpKind {
+    /// The data is created.
+    New,
+    /// The entries of the data are edited.
+    Edit,
+    /// The data is deleted.
+    Delete,
+    /// The permissions, or policy, of the data are changed.
+    SetPermissions,
+}
+
+/// Replication status of a piece of data, as seen and signed by the Elders responsible for it.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ReplicationStatus {
+    /// Address of the data.
+    pub address: DataAddress,
+    /// Number of nodes currently holding the data.
+    pub holder_count: u64,
+    /// Number of holders the network aims to keep.
+    pub target_redundancy: u64,
+    /// The last time each holder was verified to still hold the data.
+    pub last_verified: BTreeMap<XorName, SystemTime>,
+    /// Key of the section responsible for the data.
+    pub section_key: BlsPublicKey,
+    /// Section signature over all the other fields.
+    pub signature: BlsSignature,
+}
+
+impl ReplicationStatus {
+    /// Returns the bytes the Elders of the section are expected to sign.
+    pub fn signable_bytes(
+        address: &DataAddress,
+        holder_count: u64,
+        target_redundancy: u64,
+        last_verified: &BTreeMap<XorName, SystemTime>,
+    ) -> crate::Result<Vec<u8>> {
+        signing::signable_value(
+            REPLICATION_STATUS_DOMAIN,
+            &(address, holder_count, target_redundancy, last_verified),
+            "replication status",
+        )
+    }
+
+    /// Verifies the section signature over the status against the contained section key.
+    pub fn verify(&self) -> bool {
+        signing::verify_section_signature(
+            &self.section_key,
+            &self.signature,
+            Self::signable_bytes(
+                &self.address,
+                self.holder_count,
+                self.target_redundancy,
+                &self.last_verified,
+            ),
+        )
+    }
+}
+
+/// Outcome of an integrity audit of a piece of data requested by its owner (see
+/// `Cmd::RequestAudit`), as signed by the Elders which challenged the holders.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AuditReport {
+    /// Address of the audited data.
+    pub address: DataAddress,
+    /// When the holders were challenged.
+    pub audited_at: SystemTime,
+    /// The holders of the data, along with whether they passed the proof-of-storage challenge.
+    pub holders: BTreeMap<XorName, bool>,
+    /// Key of the section the holders belong to.
+    pub section_key: BlsPublicKey,
+    /// Section signature over the address, time of the audit and holders.
+    pub signature: BlsSignature,
+}
+
+impl AuditReport {
+    /// Returns the bytes the Elders of the section are expected to sign.
+    pub fn signable_bytes(
+        address: &DataAddress,
+        audited_at: &SystemTime,
+        holders: &BTreeMap<XorName, bool>,
+    ) -> crate::Result<Vec<u8>> {
+        signing::signable_value(
+            AUDIT_REPORT_DOMAIN,
+            &(address, audited_at, holders),
+            "audit report",
+        )
+    }
+
+    /// Verifies the section signature over the report against the contained section key.
+    pub fn verify(&self) -> bool {
+        signing::verify_section_signature(
+            &self.section_key,
+            &self.signature,
+            Self::signable_bytes(&self.address, &self.audited_at, &self.holders),
+        )
+    }
+
+    /// Returns the number of holders which passed the challenge.
+    pub fn passed(&self) -> usize {
+        self.holders.values().filter(|passed| **passed).count()
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::{Message, MessageId};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+use xor_name::XorName;
+
+/// Remembers the client cmds seen within a time window, so that Elders can drop the
+/// retries of a cmd instead of processing it again. Retries are recognised by their
+/// idempotency key, see `Message::with_idempotency_key`, or else by their `MessageId`.
+#[derive(Debug)]
+pub struct DedupCache {
+    ttl: Duration,
+    seen: BTreeMap<Key, MessageId>,
+    // Keys in the order they were seen, to expire them.
+    expiry: VecDeque<(Instant, Key)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Key {
+    Idempotency(XorName),
+    Msg(MessageId),
+}
+
+impl DedupCache {
+    /// Creates a new instance, remembering cmds for the given time.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            seen: BTreeMap::new(),
+            expiry: VecDeque::new(),
+        }
+    }
+
+    /// Records the client message, returning the id of the message it duplicates, if any.
+    /// Messages other than client cmds are never considered duplicates.
+    pub fn check(&mut self, msg: &Message) -> Option<MessageId> {
+        self.check_at(msg, Instant::now())
+    }
+
+    /// Records the client message as received at the given instant, see `check`.
+    pub fn check_at(&mut self, msg: &Message, now: Instant) -> Option<MessageId> {
+        match msg {
+            Message::Cmd { .. } => self.insert_at(msg.idempotency_key(), msg.id(), now),
+            _ => None,
+        }
+    }
+
+    /// Records the cmd with the given idempotency key, if any, and id as received at the
+    /// given instant, returning the id of the cmd it duplicates, if any.
+    pub fn insert_at(
+        &mut self,
+        idempotency_key: Option<XorName>,
+        msg_id: MessageId,
+        now: Instant,
+    ) -> Option<MessageId> {
+        self.prune_at(now);
+        let key = idempotency_key.map_or(Key::Msg(msg_id), Key::Idempotency);
+        if let Some(original) = self.seen.get(&key) {
+            return Some(*original);
+        }
+        let _ = self.seen.insert(key, msg_id);
+        self.expiry.push_back((now + self.ttl, key));
+        None
+    }
+
+    /// Forgets the cmds seen longer ago than the time to live, as of the given instant.
+    pub fn prune_at(&mut self, now: Instant) {
+        while let Some((expires_at, key)) = self.expiry.front().copied() {
+            if expires_at > now {
+                break;
+            }
+            let _ = self.expiry.pop_front();
+            let _ = self.seen.remove(&key);
+        }
+    }
+
+    /// Returns the number of cmds currently remembered.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Returns whether no cmd is currently remembered.
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Cmd;
+
+    #[test]
+    fn dedup_retries() {
+        let echo = |id| Message::Cmd {
+            cmd: Cmd::Echo {
+                client: XorName::default(),
+                payload: vec![],
+            },
+            id,
+            app_id: None,
+            idempotency_key: None,
+            depends_on: vec![],
+        };
+        let now = Instant::now();
+        let mut cache = DedupCache::new(Duration::from_secs(60));
+
+        // Retries with the same id...
+        let first = MessageId::new();
+        assert_eq!(cache.check_at(&echo(first), now), None);
+        assert_eq!(cache.check_at(&echo(first), now), Some(first));
+
+        // ...or with new ids but the same idempotency key.
+        let key = XorName::random();
+        let second = MessageId::new();
+        assert_eq!(
+            cache.check_at(&echo(second).with_idempotency_key(key), now),
+            None
+        );
+        let retry = echo(MessageId::new()).with_idempotency_key(key);
+        assert_eq!(cache.check_at(&retry, now), Some(second));
+        assert_eq!(cache.len(), 2);
+
+        let later = now + Duration::from_secs(61);
+        assert_eq!(cache.check_at(&retry, later), None);
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -7,21 +7,28 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
+use crate::processing::RetryAdvice;
 use serde::{Deserialize, Serialize};
 use sn_data_types::PublicKey;
 use std::{
     fmt::{self, Debug, Formatter},
+    net::SocketAddr,
     result,
 };
 use thiserror::Error;
+use threshold_crypto::PublicKey as BlsPublicKey;
+use xor_name::XorName;
 
 /// A specialised `Result` type.
 pub type Result<T, E = Error> = result::Result<T, E>;
 
+/// A specialised `Result` type for the data cmds and queries, see `DataError`.
+pub type DataResult<T> = result::Result<T, DataError>;
+
 /// Error debug struct
-pub struct ErrorDebug<'a, T>(pub &'a Result<T>);
+pub struct ErrorDebug<'a, T, E = Error>(pub &'a Result<T, E>);
 
-impl<'a, T> Debug for ErrorDebug<'a, T> {
+impl<'a, T, E: Debug> Debug for ErrorDebug<'a, T, E> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         if let Err(error) = self.0 {
             write!(f, "{:?}", error)
@@ -45,7 +52,7 @@ pub enum Error {
 
     /// Access denied for supplied PublicKey
     #[error("Access denied for PublicKey: {0}")]
-    AccessDenied(PublicKey),
+    AccessDenied(Box<PublicKey>),
     /// Error occurred when atempting to verify signature
     #[error("Signature verification error: {0}")]
     SignatureVerification(String),
@@ -58,7 +65,7 @@ pub enum Error {
 
     /// No history found for PublicKey
     #[error("No history found for PublicKey: {0}")]
-    NoHistoryForPublicKey(sn_data_types::PublicKey),
+    NoHistoryForPublicKey(Box<sn_data_types::PublicKey>),
 
     /// Failed to write file, likely due to a system Io error
     #[error("Failed to write file")]
@@ -84,7 +91,7 @@ pub enum Error {
     DuplicateEntryKeys,
     /// The list of owner keys is invalid
     #[error("Invalid owner key: {0}")]
-    InvalidOwners(sn_data_types::PublicKey),
+    InvalidOwners(Box<sn_data_types::PublicKey>),
     /// No Policy has been set to the data
     #[error("No policy has been set for this data")]
     PolicyNotSet,
@@ -169,4 +176,349 @@ pub enum Error {
     /// The node hasn't left the section, and was not marked for relocation during reward operations
     #[error("Node is not being relocated")]
     NodeWasNotRelocated,
+
+    /// The Elder is no longer responsible for the request, e.g. after a split or relocation.
+    /// Contains the Elders currently responsible, to which the request should be sent instead.
+    #[error("Elder is no longer responsible for the request")]
+    NotResponsible {
+        /// Names and addresses of the Elders currently responsible.
+        current_responsible: Vec<(XorName, SocketAddr)>,
+    },
+    /// The message was sent to a section key which is outdated, i.e. the destination section
+    /// has since moved on to a new key. The sender is to fetch the segment of the section chain
+    /// from the provided key to the current one, and resend the message.
+    #[error("Message was sent to an outdated section key")]
+    SectionKeyMismatch {
+        /// The section key the message was sent to.
+        provided: Box<BlsPublicKey>,
+        /// The current key of the section.
+        current: Box<BlsPublicKey>,
+        /// Number of keys in the section chain after the provided one,
+        /// if the provided key is in the chain, i.e. the length of the missing segment.
+        chain_len_hint: Option<u64>,
+    },
+    /// The value of a Map key was not the expected one, see `MapWrite::CompareAndSet`.
+    #[error("Map value does not match the expected one")]
+    CompareAndSetConflict {
+        /// The actual value of the key, `None` if it doesn't exist.
+        actual: Option<Box<sn_data_types::MapValue>>,
+    },
+    /// A cmd the cmd depends on failed, so the cmd wasn't applied, see `Message::with_depends_on`.
+    #[error("Cmd {0:?} the cmd depends on failed")]
+    DependencyFailed(crate::MessageId),
+    /// All the operations of a bundle of prepaid operations were spent already,
+    /// see `PrepaidOpsLedger`.
+    #[error("Bundle of prepaid operations is exhausted")]
+    PrepaidOpsExhausted,
+}
+
+impl Error {
+    /// Returns whether the same request may succeed if sent again later,
+    /// e.g. after a transient failure of the recipient, see `retry_advice`.
+    pub fn is_retryable(&self) -> bool {
+        self.retry_advice().is_some()
+    }
+
+    /// Returns the `SectionKeyMismatch` error for a message sent to the `provided` section key,
+    /// if it isn't the current key of the section, i.e. the last key of `section_chain`,
+    /// which lists the keys of the section oldest first.
+    pub fn section_key_mismatch(
+        provided: BlsPublicKey,
+        section_chain: &[BlsPublicKey],
+    ) -> Option<Self> {
+        let current = *section_chain.last()?;
+        if provided == current {
+            return None;
+        }
+        let chain_len_hint = section_chain
+            .iter()
+            .position(|key| *key == provided)
+            .map(|index| (section_chain.len() - index - 1) as u64);
+        Some(Error::SectionKeyMismatch {
+            provided: Box::new(provided),
+            current: Box::new(current),
+            chain_len_hint,
+        })
+    }
+
+    /// Returns whether the request can't succeed, neither if sent again nor once corrected,
+    /// e.g. when access is denied. The errors neither retryable nor fatal call for the
+    /// request to be corrected first, e.g. sent to another section, or to a newer version.
+    pub fn is_fatal(&self) -> bool {
+        use Error::*;
+        !self.is_retryable()
+            && !matches!(
+                self,
+                UnsupportedVersion(_)
+                    | UnsupportedSerialization(_)
+                    | InvalidSuccessor(_)
+                    | InvalidOwnersSuccessor(_)
+                    | InvalidPermissionsSuccessor(_)
+                    | NotResponsible { .. }
+                    | SectionKeyMismatch { .. }
+                    | CompareAndSetConflict { .. }
+            )
+    }
+
+    /// Returns the advice to send the same request again, if it may succeed later.
+    /// Errors don't carry how long to wait, so the advice leaves it to the sender's backoff
+    /// policy, see `RetryAdvice::after`.
+    pub fn retry_advice(&self) -> Option<RetryAdvice> {
+        use Error::*;
+        match self {
+            FailedToWriteFile | NotEnoughSpace | OpNotCausallyReady | CrdtUnexpectedState
+            | PaymentFailed | FailedToDelete => Some(RetryAdvice::default()),
+            _ => None,
+        }
+    }
+
+    /// Returns the stable code of the error variant, e.g. to report errors in dashboards
+    /// without matching their description. Codes are never reused, new variants get new ones.
+    pub fn code(&self) -> u32 {
+        use Error::*;
+        match self {
+            UnsupportedVersion(..) => 1,
+            UnsupportedSerialization(..) => 2,
+            AccessDenied(..) => 3,
+            SignatureVerification(..) => 4,
+            Serialization(..) => 5,
+            NoSuchData => 6,
+            NoHistoryForPublicKey(..) => 7,
+            FailedToWriteFile => 8,
+            DataExists => 9,
+            NoSuchEntry => 10,
+            TooManyEntries => 11,
+            NoSuchKey => 12,
+            NotEnoughSpace => 13,
+            DuplicateEntryKeys => 14,
+            InvalidOwners(..) => 15,
+            PolicyNotSet => 16,
+            InvalidSuccessor(..) => 17,
+            InvalidOwnersSuccessor(..) => 18,
+            OpNotCausallyReady => 19,
+            InvalidPermissionsSuccessor(..) => 20,
+            InvalidOperation => 21,
+            SigningKeyTypeMismatch => 22,
+            InvalidSignature => 23,
+            DuplicateMessageId => 24,
+            LossOfPrecision => 25,
+            ExcessiveValue => 26,
+            TransactionIdExists => 27,
+            InsufficientBalance => 28,
+            NoSuchBalance => 29,
+            NoSuchSender => 30,
+            NoSuchRecipient => 31,
+            BalanceExists => 32,
+            ExceededSize => 33,
+            CrdtMissingOpSignature => 34,
+            CrdtUnexpectedState => 35,
+            EntryExists(..) => 36,
+            PaymentFailed => 37,
+            FailedToDelete => 38,
+            NodeWasNotRelocated => 39,
+            NotResponsible { .. } => 40,
+            SectionKeyMismatch { .. } => 41,
+            CompareAndSetConflict { .. } => 42,
+            DependencyFailed(..) => 43,
+            PrepaidOpsExhausted => 44,
+        }
+    }
+
+    /// Returns the error of the given code, see `code`. Returns `None` for unknown codes,
+    /// and for the codes of variants carrying data, which the code alone can't restore.
+    pub fn from_code(code: u32) -> Option<Self> {
+        use Error::*;
+        Some(match code {
+            6 => NoSuchData,
+            8 => FailedToWriteFile,
+            9 => DataExists,
+            10 => NoSuchEntry,
+            11 => TooManyEntries,
+            12 => NoSuchKey,
+            13 => NotEnoughSpace,
+            14 => DuplicateEntryKeys,
+            16 => PolicyNotSet,
+            19 => OpNotCausallyReady,
+            21 => InvalidOperation,
+            22 => SigningKeyTypeMismatch,
+            23 => InvalidSignature,
+            24 => DuplicateMessageId,
+            25 => LossOfPrecision,
+            26 => ExcessiveValue,
+            27 => TransactionIdExists,
+            28 => InsufficientBalance,
+            29 => NoSuchBalance,
+            30 => NoSuchSender,
+            31 => NoSuchRecipient,
+            32 => BalanceExists,
+            33 => ExceededSize,
+            34 => CrdtMissingOpSignature,
+            35 => CrdtUnexpectedState,
+            37 => PaymentFailed,
+            38 => FailedToDelete,
+            39 => NodeWasNotRelocated,
+            44 => PrepaidOpsExhausted,
+            _ => return None,
+        })
+    }
+}
+
+/// The errors of the data cmds and queries, structured for clients to react to
+/// without matching their description.
+/// See `CmdError::data_error` and `QueryResponse::data_error`.
+#[derive(Error, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum DataError {
+    /// The data doesn't exist.
+    #[error("Requested data not found")]
+    NotFound,
+    /// The entry doesn't exist in the data.
+    #[error("Requested entry not found")]
+    EntryNotFound,
+    /// The key doesn't exist in the data.
+    #[error("Key does not exist")]
+    KeyNotFound,
+    /// The data already exists.
+    #[error("Data provided already exists")]
+    AlreadyExists,
+    /// The entry already exists in the data.
+    #[error("Entry already exists {key}")]
+    EntryExists {
+        /// The current entry key.
+        key: u8,
+    },
+    /// The operation doesn't follow the current version of the data.
+    #[error("Invalid {of:?} version provided, expected the successor of {expected}")]
+    VersionMismatch {
+        /// What the version is of.
+        of: VersionOf,
+        /// The current version, which the operation is expected to succeed.
+        expected: u64,
+        /// The version provided with the operation, if known.
+        actual: Option<u64>,
+    },
+    /// Access to the data is denied to the key.
+    #[error("Access denied for PublicKey: {key}")]
+    AccessDenied {
+        /// The key denied access.
+        key: Box<PublicKey>,
+    },
+    /// The owner key is invalid.
+    #[error("Invalid owner key: {key}")]
+    InvalidOwner {
+        /// The invalid key.
+        key: Box<PublicKey>,
+    },
+    /// No policy has been set for the data.
+    #[error("No policy has been set for this data")]
+    PolicyNotSet,
+    /// The operation would exceed the limit on the number of entries.
+    #[error("Exceeded a limit on a number of entries")]
+    TooManyEntries,
+    /// The operation holds duplicate entry keys.
+    #[error("Duplicate entries provided")]
+    DuplicateEntryKeys,
+    /// The data would exceed its size limit.
+    #[error("Size of the structure exceeds the limit")]
+    ExceededSize,
+    /// The operation isn't valid for the data, e.g. editing a Blob.
+    #[error("Invalid operation")]
+    InvalidOperation,
+    /// The operation depends on operations not yet applied.
+    #[error("Operation is not causally ready")]
+    NotCausallyReady,
+    /// The value of a Map key was not the expected one, see `MapWrite::CompareAndSet`.
+    #[error("Map value does not match the expected one")]
+    CompareAndSetConflict {
+        /// The actual value of the key, `None` if it doesn't exist.
+        actual: Option<Box<sn_data_types::MapValue>>,
+    },
+    /// Any other error, e.g. not specific to data.
+    #[error(transparent)]
+    Other(Box<Error>),
+}
+
+/// What the version of a `DataError::VersionMismatch` is of.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum VersionOf {
+    /// The data, i.e. its entries.
+    Data,
+    /// The owners of the data.
+    Owners,
+    /// The permissions of the data.
+    Permissions,
+}
+
+impl From<Error> for DataError {
+    fn from(error: Error) -> Self {
+        let mismatch = |of, expected| Self::VersionMismatch {
+            of,
+            expected,
+            actual: None,
+        };
+        match error {
+            Error::NoSuchData => Self::NotFound,
+            Error::NoSuchEntry => Self::EntryNotFound,
+            Error::NoSuchKey => Self::KeyNotFound,
+            Error::DataExists => Self::AlreadyExists,
+            Error::EntryExists(key) => Self::EntryExists { key },
+            Error::InvalidSuccessor(version) => mismatch(VersionOf::Data, version),
+            Error::InvalidOwnersSuccessor(version) => mismatch(VersionOf::Owners, version),
+            Error::InvalidPermissionsSuccessor(version) => {
+                mismatch(VersionOf::Permissions, version)
+            }
+            Error::AccessDenied(key) => Self::AccessDenied { key },
+            Error::InvalidOwners(key) => Self::InvalidOwner { key },
+            Error::PolicyNotSet => Self::PolicyNotSet,
+            Error::TooManyEntries => Self::TooManyEntries,
+            Error::DuplicateEntryKeys => Self::DuplicateEntryKeys,
+            Error::ExceededSize => Self::ExceededSize,
+            Error::InvalidOperation => Self::InvalidOperation,
+            Error::OpNotCausallyReady => Self::NotCausallyReady,
+            Error::CompareAndSetConflict { actual } => Self::CompareAndSetConflict { actual },
+            error => Self::Other(Box::new(error)),
+        }
+    }
+}
+
+/// Converts the error back to the untyped `Error`, e.g. for the node errors which carry one.
+impl From<DataError> for Error {
+    fn from(error: DataError) -> Self {
+        match error {
+            DataError::NotFound => Self::NoSuchData,
+            DataError::EntryNotFound => Self::NoSuchEntry,
+            DataError::KeyNotFound => Self::NoSuchKey,
+            DataError::AlreadyExists => Self::DataExists,
+            DataError::EntryExists { key } => Self::EntryExists(key),
+            DataError::VersionMismatch { of, expected, .. } => match of {
+                VersionOf::Data => Self::InvalidSuccessor(expected),
+                VersionOf::Owners => Self::InvalidOwnersSuccessor(expected),
+                VersionOf::Permissions => Self::InvalidPermissionsSuccessor(expected),
+            },
+            DataError::AccessDenied { key } => Self::AccessDenied(key),
+            DataError::InvalidOwner { key } => Self::InvalidOwners(key),
+            DataError::PolicyNotSet => Self::PolicyNotSet,
+            DataError::TooManyEntries => Self::TooManyEntries,
+            DataError::DuplicateEntryKeys => Self::DuplicateEntryKeys,
+            DataError::ExceededSize => Self::ExceededSize,
+            DataError::InvalidOperation => Self::InvalidOperation,
+            DataError::NotCausallyReady => Self::OpNotCausallyReady,
+            DataError::CompareAndSetConflict { actual } => Self::CompareAndSetConflict { actual },
+            DataError::Other(error) => *error,
+        }
+    }
+}
+
+/// Converts the errors of the crate, e.g. a client message which failed to parse, to the error
+/// to send back to the client. A wrapped `client::Error` is unwrapped, the errors which have
+/// no counterpart are reported as a `Serialization` error, with their description.
+impl From<crate::Error> for Error {
+    fn from(error: crate::Error) -> Self {
+        match error {
+            crate::Error::Client(error) => error,
+            crate::Error::UnsupportedVersion(version) => Self::UnsupportedVersion(version),
+            crate::Error::MessageTooLarge { .. } => Self::ExceededSize,
+            error => Self::Serialization(error.to_string()),
+        }
+    }
 }
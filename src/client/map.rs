@@ -7,11 +7,15 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use super::{AuthorisationKind, CmdError, DataAuthKind, Error, QueryResponse};
+use super::{
+    AuthorisationKind, CmdError, DataAddress, DataAuthKind, DataOpKind, Error, QueryResponse,
+    QuotaToken,
+};
 use sn_data_types::{
     Map, MapAddress as Address, MapEntryActions as Changes, MapPermissionSet as PermissionSet,
-    PublicKey,
+    MapValue, PublicKey,
 };
+use tiny_keccak::{Hasher, Sha3};
 use xor_name::XorName;
 
 use serde::{Deserialize, Serialize};
@@ -56,6 +60,8 @@ pub enum MapRead {
 #[derive(Hash, Eq, PartialEq, PartialOrd, Clone, Serialize, Deserialize)]
 pub enum MapWrite {
     /// Create new Map.
+    /// The Map can carry its initial entries and permissions (see `Map::new_with_data`),
+    /// which are then created along with it.
     New(Map),
     /// Delete instance.
     Delete(Address),
@@ -65,6 +71,9 @@ pub enum MapWrite {
         address: Address,
         /// Changes to apply.
         changes: Changes,
+        /// Claim on the owner's quota of fee-free mutations, if any.
+        #[serde(default)]
+        quota_claim: Option<QuotaToken>,
     },
     /// Delete user permissions.
     DelUserPermissions {
@@ -86,6 +95,20 @@ pub enum MapWrite {
         /// Version to set.
         version: u64,
     },
+    /// Set the value of a key, only if its current value is the expected one.
+    /// On conflict, the cmd fails with `Error::CompareAndSetConflict`, carrying the actual value.
+    CompareAndSet {
+        /// Map address.
+        address: Address,
+        /// Key to set.
+        #[serde(with = "serde_bytes")]
+        key: Vec<u8>,
+        /// Hash of the expected current value (see `MapWrite::value_hash`),
+        /// or `None` if the key is expected not to exist yet.
+        expected: Option<[u8; 32]>,
+        /// The value to set.
+        new: MapValue,
+    },
 }
 
 impl MapRead {
@@ -94,15 +117,15 @@ impl MapRead {
     pub fn error(&self, error: Error) -> QueryResponse {
         use MapRead::*;
         match *self {
-            Get(_) => QueryResponse::GetMap(Err(error)),
-            GetValue { .. } => QueryResponse::GetMapValue(Err(error)),
-            GetShell(_) => QueryResponse::GetMapShell(Err(error)),
-            GetVersion(_) => QueryResponse::GetMapVersion(Err(error)),
-            ListEntries(_) => QueryResponse::ListMapEntries(Err(error)),
-            ListKeys(_) => QueryResponse::ListMapKeys(Err(error)),
-            ListValues(_) => QueryResponse::ListMapValues(Err(error)),
-            ListPermissions(_) => QueryResponse::ListMapPermissions(Err(error)),
-            ListUserPermissions { .. } => QueryResponse::ListMapUserPermissions(Err(error)),
+            Get(_) => QueryResponse::GetMap(Err(error.into())),
+            GetValue { .. } => QueryResponse::GetMapValue(Err(error.into())),
+            GetShell(_) => QueryResponse::GetMapShell(Err(error.into())),
+            GetVersion(_) => QueryResponse::GetMapVersion(Err(error.into())),
+            ListEntries(_) => QueryResponse::ListMapEntries(Err(error.into())),
+            ListKeys(_) => QueryResponse::ListMapKeys(Err(error.into())),
+            ListValues(_) => QueryResponse::ListMapValues(Err(error.into())),
+            ListPermissions(_) => QueryResponse::ListMapPermissions(Err(error.into())),
+            ListUserPermissions { .. } => QueryResponse::ListMapUserPermissions(Err(error.into())),
         }
     }
 
@@ -161,10 +184,33 @@ impl fmt::Debug for MapRead {
 }
 
 impl MapWrite {
-    /// Creates a Response containing an error, with the Response variant corresponding to the
-    /// Request variant.
+    /// Creates a Response containing an error, attributed to the address of the Map written.
     pub fn error(&self, error: Error) -> CmdError {
-        CmdError::Data(error)
+        use MapWrite::*;
+        let address = match self {
+            New(ref data) => *data.address(),
+            Delete(ref address)
+            | SetUserPermissions { ref address, .. }
+            | DelUserPermissions { ref address, .. }
+            | Edit { ref address, .. }
+            | CompareAndSet { ref address, .. } => *address,
+        };
+        CmdError::Data {
+            address: DataAddress::Map(address),
+            op: self.op(),
+            error: error.into(),
+        }
+    }
+
+    /// Returns the kind of operation of the request.
+    pub fn op(&self) -> DataOpKind {
+        use MapWrite::*;
+        match self {
+            New(_) => DataOpKind::New,
+            Edit { .. } | CompareAndSet { .. } => DataOpKind::Edit,
+            Delete(_) => DataOpKind::Delete,
+            SetUserPermissions { .. } | DelUserPermissions { .. } => DataOpKind::SetPermissions,
+        }
     }
 
     /// Returns the type of authorisation needed for the request.
@@ -180,10 +226,27 @@ impl MapWrite {
             Delete(ref address)
             | SetUserPermissions { ref address, .. }
             | DelUserPermissions { ref address, .. }
-            | Edit { ref address, .. } => *address.name(),
+            | Edit { ref address, .. }
+            | CompareAndSet { ref address, .. } => *address.name(),
         }
     }
 
+    /// Returns the hash of a value, as expected by `MapWrite::CompareAndSet`.
+    /// The version of a sequenced value is part of the hash.
+    pub fn value_hash(value: &MapValue) -> [u8; 32] {
+        let mut hasher = Sha3::v256();
+        let mut output = [0; 32];
+        match value {
+            MapValue::Seq(value) => {
+                hasher.update(&value.data);
+                hasher.update(&value.version.to_be_bytes());
+            }
+            MapValue::Unseq(data) => hasher.update(data),
+        }
+        hasher.finalize(&mut output);
+        output
+    }
+
     /// Returns the owner of the data on a New map write.
     pub fn owner(&self) -> Option<PublicKey> {
         match self {
@@ -205,7 +268,34 @@ impl fmt::Debug for MapWrite {
                 SetUserPermissions { .. } => "SetMapUserPermissions",
                 DelUserPermissions { .. } => "DelMapUserPermissions",
                 Edit { .. } => "EditMap",
+                CompareAndSet { .. } => "CompareAndSetMapValue",
             }
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sn_data_types::MapSeqValue;
+
+    #[test]
+    fn value_hash() {
+        let value = MapSeqValue {
+            data: vec![1, 2, 3],
+            version: 0,
+        };
+        let hash = MapWrite::value_hash(&MapValue::Seq(value.clone()));
+        assert_eq!(hash, MapWrite::value_hash(&MapValue::Seq(value.clone())));
+
+        let next = MapSeqValue {
+            version: 1,
+            ..value
+        };
+        assert_ne!(hash, MapWrite::value_hash(&MapValue::Seq(next)));
+        assert_ne!(
+            MapWrite::value_hash(&MapValue::Unseq(vec![1, 2, 3])),
+            MapWrite::value_hash(&MapValue::Unseq(vec![1, 2, 4]))
+        );
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::signing::{self, SIGNED_ROOT_DOMAIN};
+use serde::{Deserialize, Serialize};
+use threshold_crypto::{PublicKey as BlsPublicKey, Signature as BlsSignature};
+use tiny_keccak::{Hasher, Sha3};
+
+/// Proof that a leaf (e.g. a page of a response) is part of the set of leaves
+/// a merkle root was computed over.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct MerkleProof {
+    /// Index of the leaf in the set.
+    pub index: u32,
+    /// Hashes of the sibling nodes, from the leaf up to the root.
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+    /// Builds the merkle tree over the leaves, returning its root
+    /// along with the proof of each leaf, in the order of the leaves.
+    pub fn build(leaves: &[&[u8]]) -> ([u8; 32], Vec<Self>) {
+        let mut levels = vec![leaves
+            .iter()
+            .map(|leaf| hash_leaf(leaf))
+            .collect::<Vec<_>>()];
+        while levels[levels.len() - 1].len() > 1 {
+            let level = &levels[levels.len() - 1];
+            // When a level has an odd number of nodes, the last one is paired with itself.
+            let next = level
+                .chunks(2)
+                .map(|pair| hash_nodes(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+                .collect();
+            levels.push(next);
+        }
+        let root = levels[levels.len() - 1]
+            .first()
+            .copied()
+            .unwrap_or_else(|| hash_leaf(&[]));
+
+        let proofs = (0..leaves.len())
+            .map(|index| {
+                let mut position = index;
+                let siblings = levels[..levels.len() - 1]
+                    .iter()
+                    .map(|level| {
+                        let sibling = *level.get(position ^ 1).unwrap_or(&level[position]);
+                        position /= 2;
+                        sibling
+                    })
+                    .collect();
+                Self {
+                    index: index as u32,
+                    siblings,
+                }
+            })
+            .collect();
+
+        (root, proofs)
+    }
+
+    /// Verifies that the leaf is part of the set the root was computed over, at this proof's index.
+    pub fn verify(&self, leaf: &[u8], root: &[u8; 32]) -> bool {
+        let mut position = self.index;
+        let mut hash = hash_leaf(leaf);
+        for sibling in &self.siblings {
+            hash = if position & 1 == 0 {
+                hash_nodes(&hash, sibling)
+            } else {
+                hash_nodes(sibling, &hash)
+            };
+            position /= 2;
+        }
+        position == 0 && hash == *root
+    }
+}
+
+/// A merkle root, e.g. over the pages of a response, as signed by the Elders of a section,
+/// so that the recipient of the leaves can tell they were served as the section computed
+/// them, rather than made up by a single Elder along with a matching root.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SignedRoot {
+    /// The merkle root.
+    pub root: [u8; 32],
+    /// Number of leaves the root was computed over.
+    pub leaves: u32,
+    /// Key of the section which signed the root.
+    pub section_key: BlsPublicKey,
+    /// Section signature over the root and number of leaves.
+    pub signature: BlsSignature,
+}
+
+impl SignedRoot {
+    /// Returns the bytes the Elders of the section are expected to sign.
+    pub fn signable_bytes(root: &[u8; 32], leaves: u32) -> crate::Result<Vec<u8>> {
+        signing::signable_value(SIGNED_ROOT_DOMAIN, &(root, leaves), "merkle root")
+    }
+
+    /// Verifies the section signature over the root against the contained section key.
+    pub fn verify(&self) -> bool {
+        signing::verify_section_signature(
+            &self.section_key,
+            &self.signature,
+            Self::signable_bytes(&self.root, self.leaves),
+        )
+    }
+}
+
+// Leaves and inner nodes are hashed with distinct prefixes,
+// so that an inner node can never be passed off as a leaf.
+fn hash_leaf(leaf: &[u8]) -> [u8; 32] {
+    sha3(&[&[0], leaf])
+}
+
+fn hash_nodes(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    sha3(&[&[1], left, right])
+}
+
+fn sha3(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha3::v256();
+    let mut output = [0; 32];
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize(&mut output);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merkle_proofs() {
+        let leaves: Vec<&[u8]> = vec![b"a", b"b", b"c", b"d", b"e"];
+        let (root, proofs) = MerkleProof::build(&leaves);
+        assert_eq!(proofs.len(), leaves.len());
+        for (leaf, proof) in leaves.iter().zip(&proofs) {
+            assert!(proof.verify(leaf, &root));
+        }
+
+        assert!(!proofs[0].verify(b"b", &root));
+        assert!(!proofs[4].verify(b"e", &MerkleProof::build(&leaves[..4]).0));
+    }
+}
@@ -7,52 +7,72 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
+mod barrier;
 mod blob;
 mod cmd;
 mod data;
+mod dedup;
 mod duty;
 mod errors;
 mod map;
-mod msg_id;
+mod merkle;
 mod network;
+mod offload;
 mod query;
+mod quota;
+mod receipt;
 mod sender;
 mod sequence;
 mod transfer;
 
 pub use self::{
-    blob::{BlobRead, BlobWrite},
-    cmd::Cmd,
-    data::{DataCmd, DataQuery},
+    barrier::{DependencyBarrier, Readiness},
+    blob::{BlobMetadata, BlobRead, BlobWrite},
+    cmd::{Cmd, ScheduledCmdStatus},
+    data::{AuditReport, DataAddress, DataCmd, DataOpKind, DataQuery, ReplicationStatus},
+    dedup::DedupCache,
     duty::{AdultDuties, Duty, ElderDuties, NodeDuties},
-    errors::{Error, ErrorDebug, Result},
+    errors::{DataError, DataResult, Error, ErrorDebug, Result, VersionOf},
     map::{MapRead, MapWrite},
-    msg_id::MessageId,
+    merkle::{MerkleProof, SignedRoot},
     network::{
-        NodeCmd, NodeCmdError, NodeDataCmd, NodeDataError, NodeDataQuery, NodeDataQueryResponse,
-        NodeEvent, NodeQuery, NodeQueryResponse, NodeRewardError, NodeRewardQuery,
-        NodeRewardQueryResponse, NodeSystemCmd, NodeTransferCmd, NodeTransferError,
-        NodeTransferQuery, NodeTransferQueryResponse,
+        ChunkHolders, ClientSigned, MetadataDigest, NodeCmd, NodeCmdError, NodeDataCmd,
+        NodeDataError, NodeDataQuery, NodeDataQueryResponse, NodeEvent, NodeQuery,
+        NodeQueryResponse, NodeRewardError, NodeRewardQuery, NodeRewardQueryResponse,
+        NodeSystemCmd, NodeSystemQuery, NodeSystemQueryResponse, NodeTransferCmd,
+        NodeTransferError, NodeTransferQuery, NodeTransferQueryResponse, Penalty, PenaltyReason,
+        RangeDigest, RewardState, SectionKnowledge, SnapshotDataType, SnapshotManifest,
+        SnapshotPart, ERROR_BATCH_FLUSH_INTERVAL, HEARTBEAT_INTERVAL,
     },
-    query::Query,
+    offload::OffloadedResponse,
+    query::{Query, Registration},
+    quota::{PrepaidOps, PrepaidOpsLedger, QuotaToken},
+    receipt::StorageReceipt,
     sender::{Address, MsgSender, TransientElderKey, TransientSectionKey},
-    sequence::{SequenceRead, SequenceWrite},
-    transfer::{TransferCmd, TransferQuery},
+    sequence::{EntryHash, PolicyTemplate, SequenceInitialPolicy, SequenceRead, SequenceWrite},
+    transfer::{HistoricalBalance, TransferCmd, TransferQuery},
 };
 
-use crate::{MessageType, WireMsg};
+pub use crate::processing::{ProcessingError, ProcessingReason, RetryAdvice};
+pub use crate::MessageId;
+
+use crate::{
+    limits::{MAX_BLOB_METADATA_SIZE, MAX_CHUNK_SIZE, MAX_ECHO_PAYLOAD_SIZE},
+    MsgHeader, SizeClass, WireMsg,
+};
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use sn_data_types::{
-    ActorHistory, AppPermissions, Blob, Map, MapEntries, MapPermissionSet, MapValue, MapValues,
-    PublicKey, ReplicaPublicKeySet, Sequence, SequenceEntries, SequenceEntry, SequencePermissions,
-    SequencePrivatePolicy, SequencePublicPolicy, Signature, Token, TransferAgreementProof,
-    TransferValidated,
+    ActorHistory, AppPermissions, Blob, BlobAddress, Map, MapEntries, MapPermissionSet, MapValue,
+    MapValues, PublicKey, ReplicaPublicKeySet, Sequence, SequenceAddress, SequenceEntries,
+    SequenceEntry, SequencePermissions, SequencePrivatePolicy, SequencePublicPolicy, Signature,
+    Token, TransferAgreementProof, TransferValidated,
 };
 use std::{
     collections::{BTreeMap, BTreeSet},
     convert::TryFrom,
     fmt,
+    time::{Duration, SystemTime},
 };
 use xor_name::XorName;
 
@@ -63,14 +83,7 @@ impl Message {
     /// Convinience function to deserialize a 'Message' from bytes received over the wire.
     /// It returns an error if the bytes don't correspond to a client message.
     pub fn from(bytes: Bytes) -> crate::Result<Self> {
-        let deserialized = WireMsg::deserialize(bytes)?;
-        if let MessageType::ClientMessage(msg) = deserialized {
-            Ok(msg)
-        } else {
-            Err(crate::Error::FailedToParse(
-                "bytes as a client message".to_string(),
-            ))
-        }
+        WireMsg::deserialize(bytes)?.into_client_msg()
     }
 
     /// serialize this Message into bytes ready to be sent over the wire.
@@ -78,6 +91,19 @@ impl Message {
         WireMsg::serialize_client_msg(self)
     }
 
+    /// Returns the JSON representation of this Message, e.g. for tooling or logs.
+    /// Note that messages holding maps keyed by non-string types can't be represented in JSON.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> crate::Result<String> {
+        crate::serialisation::to_json(self, "client message")
+    }
+
+    /// Parses a Message from its JSON representation, see `to_json`.
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str) -> crate::Result<Self> {
+        crate::serialisation::from_json(json, "client message")
+    }
+
     // /// Return the final destination address for this message.
     // // todo: evaluate deprecating this
     // pub fn destination(&self) -> Result<Address> {
@@ -149,6 +175,7 @@ impl Message {
 ///
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum Message {
     /// A Cmd is leads to a write / change of state.
     /// We expect them to be successful, and only return a msg
@@ -158,6 +185,18 @@ pub enum Message {
         cmd: Cmd,
         /// Message ID.
         id: MessageId,
+        /// Id of the application which issued the cmd, if any.
+        /// See `Message::with_app_id`.
+        #[serde(default)]
+        app_id: Option<XorName>,
+        /// Key shared by the retries of the cmd, if any, so that duplicates can be dropped.
+        /// See `Message::with_idempotency_key` and `DedupCache`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        idempotency_key: Option<XorName>,
+        /// Ids of the cmds which are to be applied before this one.
+        /// See `Message::with_depends_on` and `DependencyBarrier`.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        depends_on: Vec<MessageId>,
     },
     /// Queries is a read-only operation.
     Query {
@@ -165,6 +204,14 @@ pub enum Message {
         query: Query,
         /// Message ID.
         id: MessageId,
+        /// Id of the application which issued the query, if any.
+        /// See `Message::with_app_id`.
+        #[serde(default)]
+        app_id: Option<XorName>,
+        /// Where the client prefers the response to be sent, if anywhere else than
+        /// the connection the query came from. See `Message::with_reply_to`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reply_to: Option<ReplyTo>,
     },
     /// An Event is a fact about something that happened.
     Event {
@@ -241,6 +288,48 @@ pub enum Message {
         correlation_id: MessageId,
         /// The sender of the causing query.
         query_origin: Address,
+        /// The duty the responding node answered in, e.g. a metadata Elder or a chunk-holding
+        /// Adult, so that responses can be weighted or validated when aggregated.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        responder: Option<Duty>,
+    },
+    /// A message was dropped in transit, e.g. after its max retries or hops.
+    /// Sent back to the source by the relay or Elder which dropped the message,
+    /// so that the source can tell it was lost in transit rather than failed to be processed.
+    DeadLetter {
+        /// Header of the dropped message.
+        original_header: MsgHeader,
+        /// Why the message was dropped.
+        reason: DeadLetterReason,
+        /// The destination the message was last attempted to be delivered to.
+        final_dst_attempted: Address,
+        /// Message ID.
+        id: MessageId,
+        /// ID of the dropped message.
+        correlation_id: MessageId,
+    },
+    /// A message couldn't be processed by its recipient, e.g. because the recipient lacks
+    /// the context to, and is returned to its sender so that it can be corrected and sent again.
+    /// See `Message::create_processing_error`.
+    ProcessingError {
+        /// The error.
+        error: ProcessingError,
+        /// Message ID.
+        id: MessageId,
+        /// ID of the message which couldn't be processed.
+        correlation_id: MessageId,
+    },
+    /// A message of a kind introduced by a newer release of the protocol, which this
+    /// release can't decode. It can still be forwarded as is, or rejected lazily.
+    /// It is never produced by nor sent to the wire as such: serializing it fails.
+    #[serde(skip)]
+    Unknown {
+        /// Index of the unknown variant, as encoded on the wire.
+        kind: u16,
+        /// Message ID, as read from the header of the wire message.
+        id: MessageId,
+        /// The undecoded payload of the message.
+        bytes: Bytes,
     },
 }
 
@@ -257,20 +346,428 @@ impl Message {
             | Self::NodeEvent { id, .. }
             | Self::NodeQuery { id, .. }
             | Self::NodeCmdError { id, .. }
-            | Self::NodeQueryResponse { id, .. } => *id,
+            | Self::NodeQueryResponse { id, .. }
+            | Self::DeadLetter { id, .. }
+            | Self::ProcessingError { id, .. }
+            | Self::Unknown { id, .. } => *id,
+        }
+    }
+
+    /// Returns the id of the message this message responds to, for query responses and
+    /// errors returned for cmds or unprocessable messages. Returns `None` for other messages,
+    /// including events, even though they carry the id of the message which caused them.
+    pub fn response_to(&self) -> Option<MessageId> {
+        match self {
+            Self::QueryResponse { correlation_id, .. }
+            | Self::CmdError { correlation_id, .. }
+            | Self::NodeCmdError { correlation_id, .. }
+            | Self::NodeQueryResponse { correlation_id, .. }
+            | Self::ProcessingError { correlation_id, .. } => Some(*correlation_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the destination of the message, if known from the message itself.
+    pub fn dst_name(&self) -> Option<XorName> {
+        match self {
+            Self::Cmd { cmd, .. } => Some(cmd.dst_address()),
+            Self::Query { query, .. } => Some(query.dst_address()),
+            Self::Event { event, .. } => Some(event.dst_address()),
+            Self::QueryResponse { query_origin, .. } => Some(query_origin.xorname()),
+            Self::CmdError { cmd_origin, .. } => Some(cmd_origin.xorname()),
+            Self::NodeCmd { cmd, .. } => Some(cmd.dst_address().xorname()),
+            Self::NodeCmdError { cmd_origin, .. } => Some(cmd_origin.xorname()),
+            Self::NodeEvent { event, .. } => Some(event.dst_address().xorname()),
+            Self::NodeQuery { query, .. } => Some(query.dst_address().xorname()),
+            Self::NodeQueryResponse { query_origin, .. } => Some(query_origin.xorname()),
+            Self::DeadLetter { .. } | Self::ProcessingError { .. } | Self::Unknown { .. } => None,
+        }
+    }
+
+    /// Creates the `ProcessingError` to return to the sender of this message, which couldn't
+    /// be processed for the given reason, with this message attached.
+    pub fn create_processing_error(&self, reason: ProcessingReason) -> crate::Result<Self> {
+        let error = ProcessingError::new()
+            .with_reason(reason)
+            .with_source_message(self.serialize()?);
+        Ok(Self::ProcessingError {
+            error,
+            id: MessageId::new(),
+            correlation_id: self.id(),
+        })
+    }
+
+    /// Creates the response carrying the given error to this cmd or query, sent by the
+    /// given origin, e.g. the `Error::SectionKeyMismatch` of a message sent to an outdated
+    /// section key, see `Error::section_key_mismatch`. Returns `None` for the other messages,
+    /// which aren't responded to.
+    pub fn error_response(&self, error: Error, origin: Address) -> Option<Self> {
+        match self {
+            Self::Cmd { cmd, id, .. } => Some(Self::cmd_error(cmd.error(error), *id, origin)),
+            Self::Query { query, id, .. } => {
+                Some(Self::query_response(query.error(error), *id, origin))
+            }
+            _ => None,
+        }
+    }
+
+    /// Creates the response to the query with the given id, sent by the given origin.
+    /// The id of the response is derived from the id of the query,
+    /// see `MessageId::in_response_to`.
+    pub fn query_response(
+        response: QueryResponse,
+        correlation_id: MessageId,
+        query_origin: Address,
+    ) -> Self {
+        Self::QueryResponse {
+            response,
+            id: MessageId::in_response_to(&correlation_id),
+            correlation_id,
+            query_origin,
+        }
+    }
+
+    /// Creates the error returned for the cmd with the given id, sent by the given origin.
+    /// The id of the error is derived from the id of the cmd, see `MessageId::in_response_to`.
+    pub fn cmd_error(error: CmdError, correlation_id: MessageId, cmd_origin: Address) -> Self {
+        Self::CmdError {
+            error,
+            id: MessageId::in_response_to(&correlation_id),
+            correlation_id,
+            cmd_origin,
+        }
+    }
+
+    /// Gets the id of the application which issued the message, if any.
+    /// Only client cmds and queries carry an application id.
+    pub fn app_id(&self) -> Option<XorName> {
+        match self {
+            Self::Cmd { app_id, .. } | Self::Query { app_id, .. } => *app_id,
+            _ => None,
+        }
+    }
+
+    /// Sets the id of the application issuing the message, i.e. the hash of the
+    /// application's public key, so that sections can account for operations per application.
+    /// This has no effect on messages other than client cmds and queries.
+    pub fn with_app_id(mut self, app_key: &PublicKey) -> Self {
+        if let Self::Cmd { app_id, .. } | Self::Query { app_id, .. } = &mut self {
+            *app_id = Some(XorName::from(*app_key));
+        }
+        self
+    }
+
+    /// Gets the idempotency key of the message, if any.
+    /// Only client cmds carry an idempotency key.
+    pub fn idempotency_key(&self) -> Option<XorName> {
+        match self {
+            Self::Cmd {
+                idempotency_key, ..
+            } => *idempotency_key,
+            _ => None,
+        }
+    }
+
+    /// Sets the key shared by all the retries of a cmd, which the client generates once
+    /// for the cmd, e.g. with `XorName::random`, so that Elders can tell a retry from
+    /// a new cmd even when it is sent with a new `MessageId`. See `DedupCache`.
+    /// This has no effect on messages other than client cmds.
+    pub fn with_idempotency_key(mut self, key: XorName) -> Self {
+        if let Self::Cmd {
+            idempotency_key, ..
+        } = &mut self
+        {
+            *idempotency_key = Some(key);
+        }
+        self
+    }
+
+    /// Gets the ids of the cmds the message depends on.
+    /// Only client cmds can depend on other cmds.
+    pub fn depends_on(&self) -> &[MessageId] {
+        match self {
+            Self::Cmd { depends_on, .. } => depends_on,
+            _ => &[],
+        }
+    }
+
+    /// Sets the ids of the cmds which Elders are to apply successfully before this one,
+    /// e.g. the creation of the data this cmd edits, so that a client can send both cmds
+    /// without waiting for the first one to be acked. If any of them fails, this cmd isn't
+    /// applied, and `Error::DependencyFailed` is returned instead. See `DependencyBarrier`.
+    /// This has no effect on messages other than client cmds.
+    pub fn with_depends_on(mut self, ids: impl IntoIterator<Item = MessageId>) -> Self {
+        if let Self::Cmd { depends_on, .. } = &mut self {
+            depends_on.extend(ids);
+        }
+        self
+    }
+
+    /// Gets the preferred reply path of the message, if any.
+    /// Only client queries carry a reply path hint.
+    pub fn reply_to(&self) -> Option<&ReplyTo> {
+        match self {
+            Self::Query { reply_to, .. } => reply_to.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Sets the path the client prefers the response to be sent through, e.g. a dedicated
+    /// connection for bulk transfers when several are multiplexed. It is only a hint,
+    /// which Elders can ignore, e.g. if the socket is unknown to them.
+    /// This has no effect on messages other than client queries.
+    pub fn with_reply_to(mut self, path: ReplyTo) -> Self {
+        if let Self::Query { reply_to, .. } = &mut self {
+            *reply_to = Some(path);
+        }
+        self
+    }
+
+    /// Returns the size class of the message by its variant, i.e. the class of the largest
+    /// payload the variant is expected to carry, e.g. `Large` for a chunk however small.
+    /// As for the other kinds of messages, the header of the wire message is set to the class
+    /// of the length of the serialized payload, so that the transport layer can pick how to
+    /// send it; this class is only a floor to it, so that the messages of a variant are sent
+    /// the same way, but never a message classed below its actual size.
+    pub fn size_class(&self) -> SizeClass {
+        match self {
+            Self::Cmd {
+                cmd: Cmd::Data { cmd, .. },
+                ..
+            }
+            | Self::Cmd {
+                cmd: Cmd::PrepaidData { cmd, .. },
+                ..
+            } => match cmd {
+                DataCmd::Blob(BlobWrite::New(_))
+                | DataCmd::Blob(BlobWrite::NewWithMetadata { .. }) => SizeClass::Large,
+                DataCmd::Blob(_) => SizeClass::Small,
+                DataCmd::Map(_) | DataCmd::Sequence(_) => SizeClass::Medium,
+            },
+            Self::QueryResponse { response, .. }
+            | Self::NodeQueryResponse {
+                response: NodeQueryResponse::ForwardedClientQuery(response),
+                ..
+            } => match response {
+                QueryResponse::GetBlob(_) | QueryResponse::GetChunks(_) => SizeClass::Large,
+                QueryResponse::GetMap(_)
+                | QueryResponse::ListMapEntries(_)
+                | QueryResponse::ListMapValues(_)
+                | QueryResponse::GetSequence(_)
+                | QueryResponse::GetSequenceRange(_)
+                | QueryResponse::SubscribeToSequence(_)
+                | QueryResponse::GetStorageReceipts(_) => SizeClass::Medium,
+                _ => SizeClass::Small,
+            },
+            Self::NodeCmd {
+                cmd: NodeCmd::Data(cmd),
+                ..
+            } => match cmd {
+                NodeDataCmd::Blob(BlobWrite::New(_))
+                | NodeDataCmd::Blob(BlobWrite::NewWithMetadata { .. })
+                | NodeDataCmd::ForwardedClientCmd {
+                    cmd: DataCmd::Blob(BlobWrite::New(_)),
+                    ..
+                }
+                | NodeDataCmd::ForwardedClientCmd {
+                    cmd: DataCmd::Blob(BlobWrite::NewWithMetadata { .. }),
+                    ..
+                } => SizeClass::Large,
+                NodeDataCmd::ForwardedClientCmd {
+                    cmd: DataCmd::Map(_),
+                    ..
+                }
+                | NodeDataCmd::ForwardedClientCmd {
+                    cmd: DataCmd::Sequence(_),
+                    ..
+                } => SizeClass::Medium,
+                _ => SizeClass::Small,
+            },
+            Self::NodeQueryResponse {
+                response: NodeQueryResponse::Data(_),
+                ..
+            }
+            | Self::NodeQueryResponse {
+                response: NodeQueryResponse::DataPage { .. },
+                ..
+            }
+            | Self::NodeQueryResponse {
+                response: NodeQueryResponse::System(NodeSystemQueryResponse::SnapshotPart(_)),
+                ..
+            }
+            | Self::NodeCmd {
+                cmd: NodeCmd::System(NodeSystemCmd::ImportSnapshotPart { .. }),
+                ..
+            } => SizeClass::Large,
+            Self::ProcessingError { error, .. } => error
+                .source_message
+                .as_ref()
+                .map_or(SizeClass::Small, |bytes| SizeClass::from_len(bytes.len())),
+            Self::Cmd { .. }
+            | Self::Query { .. }
+            | Self::Event { .. }
+            | Self::CmdError { .. }
+            | Self::NodeCmd { .. }
+            | Self::NodeCmdError { .. }
+            | Self::NodeEvent { .. }
+            | Self::NodeQuery { .. }
+            | Self::NodeQueryResponse { .. }
+            | Self::DeadLetter { .. }
+            | Self::Unknown { .. } => SizeClass::Small,
+        }
+    }
+
+    /// Checks this message against the limits of the `limits` module,
+    /// e.g. that a chunk being stored doesn't exceed `MAX_CHUNK_SIZE`,
+    /// and that batches of Blob addresses aren't empty.
+    pub fn check_limits(&self) -> crate::Result<()> {
+        if let Self::Cmd {
+            cmd:
+                Cmd::Data {
+                    cmd: DataCmd::Blob(BlobWrite::DeleteBatch(addresses)),
+                    ..
+                }
+                | Cmd::PrepaidData {
+                    cmd: DataCmd::Blob(BlobWrite::DeleteBatch(addresses)),
+                    ..
+                },
+            ..
+        }
+        | Self::Query {
+            query: Query::Data(DataQuery::Blob(BlobRead::GetChunks(addresses))),
+            ..
+        } = self
+        {
+            if addresses.is_empty() {
+                return Err(crate::Error::EmptyBatch);
+            }
+        }
+        // The metadata of a Blob is checked wherever it is carried, be it in the client's cmd,
+        // or in the cmd of an Elder storing it at, or forwarding the client's cmd to, Adults.
+        if let Self::Cmd {
+            cmd:
+                Cmd::Data {
+                    cmd: DataCmd::Blob(BlobWrite::NewWithMetadata { metadata, .. }),
+                    ..
+                }
+                | Cmd::PrepaidData {
+                    cmd: DataCmd::Blob(BlobWrite::NewWithMetadata { metadata, .. }),
+                    ..
+                },
+            ..
+        }
+        | Self::NodeCmd {
+            cmd:
+                NodeCmd::Data(
+                    NodeDataCmd::Blob(BlobWrite::NewWithMetadata { metadata, .. })
+                    | NodeDataCmd::ForwardedClientCmd {
+                        cmd: DataCmd::Blob(BlobWrite::NewWithMetadata { metadata, .. }),
+                        ..
+                    },
+                ),
+            ..
+        } = self
+        {
+            if metadata.size() > MAX_BLOB_METADATA_SIZE {
+                return Err(crate::Error::MessageTooLarge {
+                    size: metadata.size(),
+                    limit: MAX_BLOB_METADATA_SIZE,
+                });
+            }
+        }
+        let (size, limit) = match self {
+            Self::Cmd {
+                cmd:
+                    Cmd::Data {
+                        cmd:
+                            DataCmd::Blob(
+                                BlobWrite::New(blob) | BlobWrite::NewWithMetadata { blob, .. },
+                            ),
+                        ..
+                    }
+                    | Cmd::PrepaidData {
+                        cmd:
+                            DataCmd::Blob(
+                                BlobWrite::New(blob) | BlobWrite::NewWithMetadata { blob, .. },
+                            ),
+                        ..
+                    },
+                ..
+            } => (blob.value().len(), MAX_CHUNK_SIZE),
+            Self::Cmd {
+                cmd: Cmd::Echo { payload, .. },
+                ..
+            } => (payload.len(), MAX_ECHO_PAYLOAD_SIZE),
+            _ => return Ok(()),
+        };
+        if size > limit {
+            return Err(crate::Error::MessageTooLarge { size, limit });
         }
+        Ok(())
     }
 }
 
+/// Preferred path for the response to a query, see `Message::with_reply_to`.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum ReplyTo {
+    /// A socket of the client, by the id it was registered with, see `Registration`.
+    Socket(XorName),
+    /// Another end user of the client, by its name, e.g. one set up for bulk transfers.
+    EndUser(XorName),
+}
+
+/// Why a message was dropped in transit, see `Message::DeadLetter`.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum DeadLetterReason {
+    /// The message could not be delivered within the max number of retries.
+    MaxRetriesExceeded,
+    /// The message exceeded the max number of hops.
+    MaxHopsExceeded,
+    /// No route to the destination could be found.
+    NoRoute,
+}
+
 ///
 #[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub enum CmdError {
     ///
     Auth(Error), // temporary, while Authenticator is not handling this
-    ///
-    Data(Error), // DataError enum for better differentiation?
+    /// Error of a data cmd, along with the address of the data and the kind of operation,
+    /// so that clients with many concurrent writes can tell which one failed without
+    /// relying on the correlation id only. See `DataCmd::error`.
+    Data {
+        /// Address of the data the cmd was writing.
+        address: DataAddress,
+        /// Kind of operation of the cmd.
+        op: DataOpKind,
+        /// The error.
+        error: DataError,
+    },
+    /// Errors of a batched data cmd, per data address.
+    /// Addresses not included were successfully processed.
+    DataBatch(BTreeMap<BlobAddress, Error>),
     ///
     Transfer(TransferError),
+    /// Error of a cmd which neither writes data nor transfers tokens, e.g. `Cmd::Echo`.
+    Other(Error),
+}
+
+impl CmdError {
+    /// Returns the address of the data the error is attributed to, if any.
+    pub fn address(&self) -> Option<&DataAddress> {
+        match self {
+            Self::Data { address, .. } => Some(address),
+            _ => None,
+        }
+    }
+
+    /// Returns the error of a data cmd, if any.
+    pub fn data_error(&self) -> Option<&DataError> {
+        match self {
+            Self::Data { error, .. } => Some(error),
+            _ => None,
+        }
+    }
 }
 
 ///
@@ -295,6 +792,11 @@ pub enum Event {
         /// This is the validation of the transfer
         /// requested by the client for an account.
         event: TransferValidated,
+        /// The number of Replicas in the set validating the transfer.
+        replica_count: u32,
+        /// The number of validations the client needs to accumulate
+        /// from the set, in order to reach agreement.
+        threshold: u32,
     },
     /// An aggregate event created client side
     /// (for upper Client layers) out of a quorum of TransferValidated events.
@@ -308,6 +810,66 @@ pub enum Event {
         /// The accumulated proof.
         proof: TransferAgreementProof,
     },
+    /// The client's query was forwarded by the Elders to another section,
+    /// from which the final response is to be expected.
+    ForwardReceipt {
+        /// This is the client id.
+        client: XorName,
+        /// ID of the forwarded query.
+        original_id: MessageId,
+        /// Name of the section the query was forwarded to.
+        forwarded_to: XorName,
+        /// When the query was forwarded.
+        at: SystemTime,
+    },
+    /// The store cost has changed significantly. Broadcast to connected clients
+    /// so that they can defer bulk writes to cheaper periods.
+    PriceSignal {
+        /// This is the client id.
+        client: XorName,
+        /// The current store cost relative to the baseline cost,
+        /// in percent (i.e. 100 means the baseline cost).
+        multiplier: u32,
+        /// The period over which the multiplier is expected to hold.
+        window: Duration,
+    },
+    /// Entries were appended to a Sequence the client subscribed to
+    /// (see `SequenceRead::SubscribeFrom`).
+    SequenceEntriesAppended {
+        /// This is the client id.
+        client: XorName,
+        /// Address of the Sequence.
+        address: SequenceAddress,
+        /// Index of the first of the appended entries.
+        from_index: u64,
+        /// The appended entries.
+        entries: SequenceEntries,
+    },
+    /// A bundle of prepaid operations was issued to the client after its payment,
+    /// to be attached to subsequent data cmds (see `Cmd::PrepaidData`).
+    PrepaidOpsIssued {
+        /// This is the client id.
+        client: XorName,
+        /// The issued bundle.
+        prepaid: PrepaidOps,
+    },
+    /// The reply of an Elder to a `Cmd::Echo`.
+    EchoReply {
+        /// This is the client id.
+        client: XorName,
+        /// The payload of the echo cmd.
+        #[serde(with = "serde_bytes")]
+        payload: Vec<u8>,
+        /// Name of the replying Elder.
+        elder: XorName,
+    },
+    /// The outcome of an audit requested with `Cmd::RequestAudit`.
+    AuditReport {
+        /// This is the client id.
+        client: XorName,
+        /// The signed report.
+        report: AuditReport,
+    },
 }
 
 impl Event {
@@ -317,6 +879,12 @@ impl Event {
         match self {
             TransferValidated { client, .. } => *client,
             TransferAgreementReached { client, .. } => *client,
+            ForwardReceipt { client, .. } => *client,
+            PriceSignal { client, .. } => *client,
+            PrepaidOpsIssued { client, .. } => *client,
+            SequenceEntriesAppended { client, .. } => *client,
+            EchoReply { client, .. } => *client,
+            AuditReport { client, .. } => *client,
         }
     }
 }
@@ -329,45 +897,54 @@ pub enum QueryResponse {
     // ===== Blob =====
     //
     /// Get Blob.
-    GetBlob(Result<Blob>),
+    GetBlob(DataResult<Blob>),
+    /// Get a batch of Blobs, with the result of each, per address.
+    GetChunks(DataResult<BTreeMap<BlobAddress, DataResult<Blob>>>),
     //
     // ===== Map =====
     //
     /// Get Map.
-    GetMap(Result<Map>),
+    GetMap(DataResult<Map>),
     /// Get Map shell.
-    GetMapShell(Result<Map>),
+    GetMapShell(DataResult<Map>),
     /// Get Map version.
-    GetMapVersion(Result<u64>),
+    GetMapVersion(DataResult<u64>),
     /// List all Map entries (key-value pairs).
-    ListMapEntries(Result<MapEntries>),
+    ListMapEntries(DataResult<Listing<MapEntries>>),
     /// List all Map keys.
-    ListMapKeys(Result<BTreeSet<Vec<u8>>>),
+    ListMapKeys(DataResult<Listing<BTreeSet<Vec<u8>>>>),
     /// List all Map values.
-    ListMapValues(Result<MapValues>),
+    ListMapValues(DataResult<Listing<MapValues>>),
     /// Get Map permissions for a user.
-    ListMapUserPermissions(Result<MapPermissionSet>),
+    ListMapUserPermissions(DataResult<MapPermissionSet>),
     /// List all Map permissions.
-    ListMapPermissions(Result<BTreeMap<PublicKey, MapPermissionSet>>),
+    ListMapPermissions(DataResult<BTreeMap<PublicKey, MapPermissionSet>>),
     /// Get Map value.
-    GetMapValue(Result<MapValue>),
+    GetMapValue(DataResult<MapValue>),
     //
     // ===== Sequence Data =====
     //
     /// Get Sequence.
-    GetSequence(Result<Sequence>),
+    GetSequence(DataResult<Sequence>),
     /// Get Sequence owners.
-    GetSequenceOwner(Result<PublicKey>),
+    GetSequenceOwner(DataResult<PublicKey>),
     /// Get Sequence entries from a range.
-    GetSequenceRange(Result<SequenceEntries>),
+    GetSequenceRange(DataResult<Listing<SequenceEntries>>),
     /// Get Sequence last entry.
-    GetSequenceLastEntry(Result<(u64, SequenceEntry)>),
+    GetSequenceLastEntry(DataResult<(u64, SequenceEntry)>),
     /// Get public Sequence permissions for a user.
-    GetSequencePublicPolicy(Result<SequencePublicPolicy>),
+    GetSequencePublicPolicy(DataResult<SequencePublicPolicy>),
     /// Get private Sequence permissions for a user.
-    GetSequencePrivatePolicy(Result<SequencePrivatePolicy>),
+    GetSequencePrivatePolicy(DataResult<SequencePrivatePolicy>),
     /// Get Sequence permissions for a user.
-    GetSequenceUserPermissions(Result<SequencePermissions>),
+    GetSequenceUserPermissions(DataResult<SequencePermissions>),
+    /// Subscribe to a Sequence, replaying the entries appended since the requested entry.
+    SubscribeToSequence(DataResult<SequenceEntries>),
+    //
+    // ===== Data =====
+    //
+    /// Get replication status, signed by the section, see `ReplicationStatus::verify`.
+    GetReplicationStatus(DataResult<ReplicationStatus>),
     //
     // ===== Tokens =====
     //
@@ -375,10 +952,14 @@ pub enum QueryResponse {
     GetReplicaKeys(Result<ReplicaPublicKeySet>),
     /// Get key balance.
     GetBalance(Result<Token>),
+    /// Get key balance as of a prior replica era.
+    GetBalanceAt(Result<HistoricalBalance>),
     /// Get key transfer history.
     GetHistory(Result<ActorHistory>),
     /// Get Store Cost.
     GetStoreCost(Result<Token>),
+    /// Get the quota of fee-free small mutations.
+    GetMutationQuota(Result<QuotaToken>),
     //
     // ===== Account =====
     //
@@ -389,6 +970,95 @@ pub enum QueryResponse {
     //
     /// Get a list of authorised keys and the version of the auth keys container from Elders.
     ListAuthKeysAndVersion(Result<(BTreeMap<PublicKey, AppPermissions>, u64)>),
+    //
+    // ===== Client registrations =====
+    //
+    /// Get the registrations held by the section for the client.
+    GetMyRegistrations(Result<Vec<Registration>>),
+    //
+    // ===== Scheduled cmds =====
+    //
+    /// Get the status of a data command sent with an `effective_after` time.
+    GetScheduledCmdStatus(Result<ScheduledCmdStatus>),
+    //
+    // ===== Blob metadata =====
+    //
+    /// Get the metadata a Blob was stored with.
+    GetBlobMetadata(DataResult<BlobMetadata>),
+    //
+    // ===== Storage receipts =====
+    //
+    /// Get the receipts of the data stored by an owner.
+    GetStorageReceipts(Result<Vec<StorageReceipt>>),
+    //
+    // ===== Entry counts =====
+    //
+    /// Get the number of entries of a Sequence.
+    GetSequenceLength(DataResult<u64>),
+    //
+    // ===== Offloaded responses =====
+    //
+    /// A response too large to be sent inline, stored as a temporary Blob instead.
+    /// The response read back from the Blob answers the same query.
+    Offloaded(OffloadedResponse),
+    /// A response of a kind introduced by a newer release of the protocol, which this
+    /// release can't decode. It is never sent to the wire as such: serializing it fails.
+    #[serde(skip)]
+    Unknown {
+        /// Index of the unknown variant, as encoded on the wire.
+        kind: u16,
+        /// The undecoded payload of the message carrying the response.
+        bytes: Bytes,
+    },
+}
+
+impl QueryResponse {
+    /// Returns the error the response carries, if any, other than a data error.
+    pub fn error(&self) -> Option<&Error> {
+        match self {
+            Self::GetReplicaKeys(Err(error))
+            | Self::GetBalance(Err(error))
+            | Self::GetBalanceAt(Err(error))
+            | Self::GetHistory(Err(error))
+            | Self::GetStoreCost(Err(error))
+            | Self::GetMutationQuota(Err(error))
+            | Self::GetAccount(Err(error))
+            | Self::ListAuthKeysAndVersion(Err(error))
+            | Self::GetMyRegistrations(Err(error))
+            | Self::GetScheduledCmdStatus(Err(error))
+            | Self::GetStorageReceipts(Err(error)) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Returns the error the response to a data query carries, if any.
+    pub fn data_error(&self) -> Option<&DataError> {
+        match self {
+            Self::GetBlob(Err(error))
+            | Self::GetChunks(Err(error))
+            | Self::GetMap(Err(error))
+            | Self::GetMapShell(Err(error))
+            | Self::GetMapVersion(Err(error))
+            | Self::ListMapEntries(Err(error))
+            | Self::ListMapKeys(Err(error))
+            | Self::ListMapValues(Err(error))
+            | Self::ListMapUserPermissions(Err(error))
+            | Self::ListMapPermissions(Err(error))
+            | Self::GetMapValue(Err(error))
+            | Self::GetSequence(Err(error))
+            | Self::GetSequenceOwner(Err(error))
+            | Self::GetSequenceRange(Err(error))
+            | Self::GetSequenceLastEntry(Err(error))
+            | Self::GetSequencePublicPolicy(Err(error))
+            | Self::GetSequencePrivatePolicy(Err(error))
+            | Self::GetSequenceUserPermissions(Err(error))
+            | Self::SubscribeToSequence(Err(error))
+            | Self::GetReplicationStatus(Err(error))
+            | Self::GetBlobMetadata(Err(error))
+            | Self::GetSequenceLength(Err(error)) => Some(error),
+            _ => None,
+        }
+    }
 }
 
 /// The kind of authorisation needed for a request.
@@ -431,17 +1101,37 @@ pub enum MiscAuthKind {
     ManageAppKeys,
     /// Request to mutate and transfer tokens from key.
     WriteAndTransfer,
+    /// Request to read the registrations held for a key.
+    ReadRegistrations,
+}
+
+/// The items of a listing response, which the Elders may have had to cap
+/// to fit within the max message size.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Listing<T> {
+    /// The listed items.
+    pub items: T,
+    /// Whether the items were capped, in which case the client is to
+    /// paginate to fetch the remaining ones.
+    pub truncated: bool,
+    /// Total number of items available.
+    pub total_available: u64,
 }
 
 /// Error type for an attempted conversion from `QueryResponse` to a type implementing
 /// `TryFrom<Response>`.
-#[derive(Debug, PartialEq)]
+#[derive(thiserror::Error, Debug, PartialEq)]
 #[allow(clippy::large_enum_variant)]
 pub enum TryFromError {
     /// Wrong variant found in `QueryResponse`.
+    #[error("Query response of the wrong type")]
     WrongType,
     /// The `QueryResponse` contained an error.
-    Response(Error),
+    #[error("Query response contained an error")]
+    Response(#[from] Error),
+    /// The `QueryResponse` of a data query contained an error.
+    #[error("Query response contained a data error")]
+    DataResponse(#[from] DataError),
 }
 
 macro_rules! try_from {
@@ -452,7 +1142,7 @@ macro_rules! try_from {
                 match response {
                     $(
                         QueryResponse::$variant(Ok(data)) => Ok(data),
-                        QueryResponse::$variant(Err(error)) => Err(TryFromError::Response(error)),
+                        QueryResponse::$variant(Err(error)) => Err(error.into()),
                     )*
                     _ => Err(TryFromError::WrongType),
                 }
@@ -462,29 +1152,38 @@ macro_rules! try_from {
 }
 
 try_from!(Blob, GetBlob);
+try_from!(BTreeMap<BlobAddress, DataResult<Blob>>, GetChunks);
 try_from!(Map, GetMap, GetMapShell);
-try_from!(u64, GetMapVersion);
-try_from!(MapEntries, ListMapEntries);
-try_from!(BTreeSet<Vec<u8>>, ListMapKeys);
-try_from!(MapValues, ListMapValues);
+try_from!(u64, GetMapVersion, GetSequenceLength);
+try_from!(Listing<MapEntries>, ListMapEntries);
+try_from!(Listing<BTreeSet<Vec<u8>>>, ListMapKeys);
+try_from!(Listing<MapValues>, ListMapValues);
 try_from!(MapPermissionSet, ListMapUserPermissions);
 try_from!(BTreeMap<PublicKey, MapPermissionSet>, ListMapPermissions);
 try_from!(MapValue, GetMapValue);
 try_from!(Sequence, GetSequence);
 try_from!(PublicKey, GetSequenceOwner);
-try_from!(SequenceEntries, GetSequenceRange);
+try_from!(Listing<SequenceEntries>, GetSequenceRange);
+try_from!(SequenceEntries, SubscribeToSequence);
 try_from!((u64, SequenceEntry), GetSequenceLastEntry);
 try_from!(SequencePublicPolicy, GetSequencePublicPolicy);
 try_from!(SequencePrivatePolicy, GetSequencePrivatePolicy);
 try_from!(SequencePermissions, GetSequenceUserPermissions);
+try_from!(ReplicationStatus, GetReplicationStatus);
 try_from!(Token, GetBalance);
+try_from!(HistoricalBalance, GetBalanceAt);
 try_from!(ReplicaPublicKeySet, GetReplicaKeys);
 try_from!(ActorHistory, GetHistory);
+try_from!(QuotaToken, GetMutationQuota);
 try_from!(
     (BTreeMap<PublicKey, AppPermissions>, u64),
     ListAuthKeysAndVersion
 );
 try_from!((Vec<u8>, Signature), GetAccount);
+try_from!(Vec<Registration>, GetMyRegistrations);
+try_from!(ScheduledCmdStatus, GetScheduledCmdStatus);
+try_from!(BlobMetadata, GetBlobMetadata);
+try_from!(Vec<StorageReceipt>, GetStorageReceipts);
 
 impl fmt::Debug for QueryResponse {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -493,6 +1192,7 @@ impl fmt::Debug for QueryResponse {
         match self {
             // Blob
             GetBlob(res) => write!(f, "QueryResponse::GetBlob({:?})", ErrorDebug(res)),
+            GetChunks(res) => write!(f, "QueryResponse::GetChunks({:?})", ErrorDebug(res)),
             // Map
             GetMap(res) => write!(f, "QueryResponse::GetMap({:?})", ErrorDebug(res)),
             GetMapShell(res) => write!(f, "QueryResponse::GetMapShell({:?})", ErrorDebug(res)),
@@ -518,6 +1218,13 @@ impl fmt::Debug for QueryResponse {
             GetSequenceRange(res) => {
                 write!(f, "QueryResponse::GetSequenceRange({:?})", ErrorDebug(res))
             }
+            SubscribeToSequence(res) => {
+                write!(
+                    f,
+                    "QueryResponse::SubscribeToSequence({:?})",
+                    ErrorDebug(res)
+                )
+            }
             GetSequenceLastEntry(res) => write!(
                 f,
                 "QueryResponse::GetSequenceLastEntry({:?})",
@@ -541,13 +1248,23 @@ impl fmt::Debug for QueryResponse {
             GetSequenceOwner(res) => {
                 write!(f, "QueryResponse::GetSequenceOwner({:?})", ErrorDebug(res))
             }
+            // Data
+            GetReplicationStatus(res) => write!(
+                f,
+                "QueryResponse::GetReplicationStatus({:?})",
+                ErrorDebug(res)
+            ),
             // Tokens
             GetReplicaKeys(res) => {
                 write!(f, "QueryResponse::GetReplicaKeys({:?})", ErrorDebug(res))
             }
             GetBalance(res) => write!(f, "QueryResponse::GetBalance({:?})", ErrorDebug(res)),
+            GetBalanceAt(res) => write!(f, "QueryResponse::GetBalanceAt({:?})", ErrorDebug(res)),
             GetHistory(res) => write!(f, "QueryResponse::GetHistory({:?})", ErrorDebug(res)),
             GetStoreCost(res) => write!(f, "QueryResponse::GetStoreCost({:?})", ErrorDebug(res)),
+            GetMutationQuota(res) => {
+                write!(f, "QueryResponse::GetMutationQuota({:?})", ErrorDebug(res))
+            }
             // Account
             GetAccount(res) => write!(f, "QueryResponse::GetAccount({:?})", ErrorDebug(res)),
             // Client Auth
@@ -556,6 +1273,42 @@ impl fmt::Debug for QueryResponse {
                 "QueryResponse::ListAuthKeysAndVersion({:?})",
                 ErrorDebug(res)
             ),
+            // Client registrations
+            GetMyRegistrations(res) => write!(
+                f,
+                "QueryResponse::GetMyRegistrations({:?})",
+                ErrorDebug(res)
+            ),
+            // Scheduled cmds
+            GetScheduledCmdStatus(res) => write!(
+                f,
+                "QueryResponse::GetScheduledCmdStatus({:?})",
+                ErrorDebug(res)
+            ),
+            // Blob metadata
+            GetBlobMetadata(res) => {
+                write!(f, "QueryResponse::GetBlobMetadata({:?})", ErrorDebug(res))
+            }
+            // Storage receipts
+            GetStorageReceipts(res) => {
+                write!(
+                    f,
+                    "QueryResponse::GetStorageReceipts({:?})",
+                    ErrorDebug(res)
+                )
+            }
+            // Entry counts
+            GetSequenceLength(res) => {
+                write!(f, "QueryResponse::GetSequenceLength({:?})", ErrorDebug(res))
+            }
+            // Offloaded responses
+            Offloaded(offloaded) => write!(f, "QueryResponse::Offloaded({:?})", offloaded),
+            Unknown { kind, bytes } => write!(
+                f,
+                "QueryResponse::Unknown {{ kind: {}, bytes: {} bytes }}",
+                kind,
+                bytes.len()
+            ),
         }
     }
 }
@@ -563,6 +1316,7 @@ impl fmt::Debug for QueryResponse {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::limits::MAX_MESSAGE_SIZE;
     use anyhow::{anyhow, Result};
     use sn_data_types::{Keypair, PublicBlob, UnseqMap};
     use std::convert::{TryFrom, TryInto};
@@ -584,12 +1338,91 @@ mod tests {
         gen_keypairs().iter().map(PublicKey::from).collect()
     }
 
+    // A bundle of 10 prepaid operations of the owner, signed by a random section key.
+    fn prepaid_ops(owner: PublicKey) -> Result<PrepaidOps> {
+        let section_key = threshold_crypto::SecretKey::random();
+        let token_bundle = Token::from_nano(1_000);
+        let id = XorName::random();
+        Ok(PrepaidOps {
+            id,
+            owner,
+            token_bundle,
+            ops_remaining: 10,
+            section_key: section_key.public_key(),
+            signature: section_key.sign(PrepaidOps::signable_bytes(&id, &owner, token_bundle, 10)?),
+        })
+    }
+
+    #[test]
+    fn verify_client_origin() -> Result<()> {
+        let keypair = Keypair::new_ed25519(&mut rand::thread_rng());
+        let other = Keypair::new_ed25519(&mut rand::thread_rng());
+        let cmd = DataCmd::Blob(BlobWrite::New(Blob::Public(PublicBlob::new(vec![1, 2, 3]))));
+        let signature = keypair.sign(&ClientSigned::signable_bytes(&cmd)?);
+        let forwarded = |public_key, origin| NodeDataCmd::ForwardedClientCmd {
+            cmd: cmd.clone(),
+            client_signed: ClientSigned {
+                public_key,
+                signature: signature.clone(),
+            },
+            origin,
+        };
+
+        let origin = XorName::from(keypair.public_key());
+        assert!(forwarded(keypair.public_key(), origin).verify_client_origin());
+        assert!(!forwarded(keypair.public_key(), XorName::random()).verify_client_origin());
+        let other_origin = XorName::from(other.public_key());
+        assert!(!forwarded(other.public_key(), other_origin).verify_client_origin());
+
+        Ok(())
+    }
+
+    #[test]
+    fn forwarded_client_ops() -> Result<()> {
+        let keypair = Keypair::new_ed25519(&mut rand::thread_rng());
+        let cmd = DataCmd::Blob(BlobWrite::New(Blob::Public(PublicBlob::new(vec![1, 2, 3]))));
+        let client_signed = ClientSigned {
+            public_key: keypair.public_key(),
+            signature: keypair.sign(&ClientSigned::signable_bytes(&cmd)?),
+        };
+        let node_cmd = NodeCmd::from((cmd.clone(), client_signed.clone()));
+        match &node_cmd {
+            NodeCmd::Data(forwarded) => assert!(forwarded.verify_client_origin()),
+            _ => anyhow::bail!("not a forwarded client cmd: {:?}", node_cmd),
+        }
+        assert_eq!(
+            <(DataCmd, ClientSigned)>::try_from(node_cmd),
+            Ok((cmd, client_signed))
+        );
+
+        let origin = XorName::random();
+        let query = DataQuery::Blob(BlobRead::Get(BlobAddress::Public(XorName::random())));
+        let node_query = NodeQuery::from((query.clone(), origin));
+        assert_eq!(node_query.dst_address(), Address::Node(query.dst_address()));
+        assert_eq!(
+            <(DataQuery, XorName)>::try_from(node_query),
+            Ok((query, origin))
+        );
+
+        let response = QueryResponse::GetBlob(Err(DataError::NotFound));
+        assert_eq!(
+            QueryResponse::try_from(NodeQueryResponse::from(response.clone())),
+            Ok(response)
+        );
+        let other = NodeQueryResponse::Data(NodeDataQueryResponse::GetChunks(Ok(vec![])));
+        assert_eq!(QueryResponse::try_from(other.clone()), Err(other));
+
+        Ok(())
+    }
+
     #[test]
     fn debug_format() -> Result<()> {
         if let Some(key) = gen_keys().first() {
-            let errored_response = QueryResponse::GetSequence(Err(Error::AccessDenied(*key)));
+            let errored_response = QueryResponse::GetSequence(Err(DataError::AccessDenied {
+                key: Box::new(*key),
+            }));
             assert!(format!("{:?}", errored_response)
-                .contains("QueryResponse::GetSequence(AccessDenied(PublicKey::"));
+                .contains("QueryResponse::GetSequence(AccessDenied { key: PublicKey::"));
             Ok(())
         } else {
             Err(anyhow!("Could not generate public key"))
@@ -605,7 +1438,7 @@ mod tests {
         };
 
         let i_data = Blob::Public(PublicBlob::new(vec![1, 3, 1, 4]));
-        let e = Error::AccessDenied(key);
+        let e = DataError::AccessDenied { key: Box::new(key) };
         assert_eq!(
             i_data,
             GetBlob(Ok(i_data.clone()))
@@ -613,7 +1446,7 @@ mod tests {
                 .map_err(|_| anyhow!("Mismatched types".to_string()))?
         );
         assert_eq!(
-            Err(TryFromError::Response(e.clone())),
+            Err(TryFromError::DataResponse(e.clone())),
             Blob::try_from(GetBlob(Err(e.clone())))
         );
 
@@ -634,9 +1467,21 @@ mod tests {
                 .map_err(|_| anyhow!("Mismatched types".to_string()))?
         );
         assert_eq!(
-            Err(TryFromError::Response(e.clone())),
+            Err(TryFromError::DataResponse(e.clone())),
             Map::try_from(GetMap(Err(e)))
         );
+
+        let keys = Listing {
+            items: vec![vec![1]].into_iter().collect::<BTreeSet<_>>(),
+            truncated: true,
+            total_available: 2,
+        };
+        assert_eq!(
+            keys,
+            ListMapKeys(Ok(keys.clone()))
+                .try_into()
+                .map_err(|_| anyhow!("Mismatched types".to_string()))?
+        );
         Ok(())
     }
 
@@ -650,6 +1495,8 @@ mod tests {
         let message = Message::Query {
             query: Query::Transfer(TransferQuery::GetBalance(pk)),
             id,
+            app_id: None,
+            reply_to: None,
         };
 
         // test msgpack serialization
@@ -659,4 +1506,995 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn app_id() -> Result<()> {
+        let app_key = gen_keys()[0];
+        let message = Message::Query {
+            query: Query::Transfer(TransferQuery::GetBalance(gen_keys()[0])),
+            id: MessageId::new(),
+            app_id: None,
+            reply_to: None,
+        };
+        assert_eq!(message.app_id(), None);
+
+        let message = message.with_app_id(&app_key);
+        assert_eq!(message.app_id(), Some(XorName::from(app_key)));
+        assert_eq!(Message::from(message.serialize()?)?, message);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reply_to() -> Result<()> {
+        let socket = XorName::random();
+        let message = Message::Query {
+            query: Query::Transfer(TransferQuery::GetBalance(gen_keys()[0])),
+            id: MessageId::new(),
+            app_id: None,
+            reply_to: None,
+        };
+        assert_eq!(message.reply_to(), None);
+
+        let message = message.with_reply_to(ReplyTo::Socket(socket));
+        assert_eq!(message.reply_to(), Some(&ReplyTo::Socket(socket)));
+        assert_eq!(Message::from(message.serialize()?)?, message);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json() -> Result<()> {
+        let message = Message::Query {
+            query: Query::Transfer(TransferQuery::GetBalance(gen_keys()[0])),
+            id: MessageId::new(),
+            app_id: None,
+            reply_to: None,
+        };
+        let json = message.to_json()?;
+        assert_eq!(Message::from_json(&json)?, message);
+        assert!(json.contains(&format!("\"{:#}\"", message.id())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn echo_payload_limit() -> Result<()> {
+        let echo = |payload| Message::Cmd {
+            cmd: Cmd::Echo {
+                client: XorName::random(),
+                payload,
+            },
+            id: MessageId::new(),
+            app_id: None,
+            idempotency_key: None,
+            depends_on: vec![],
+        };
+
+        let message = echo(vec![0; crate::limits::MAX_ECHO_PAYLOAD_SIZE]);
+        assert_eq!(Message::from(message.serialize()?)?, message);
+        assert!(echo(vec![0; crate::limits::MAX_ECHO_PAYLOAD_SIZE + 1])
+            .serialize()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn blob_metadata() -> Result<()> {
+        let blob = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        let address = *blob.address();
+        let metadata = |value_len| BlobMetadata {
+            content_type: Some("text/html".to_string()),
+            user_meta: vec![("lang".to_string(), vec![0; value_len])]
+                .into_iter()
+                .collect(),
+        };
+        let prepaid = prepaid_ops(gen_keys()[0])?;
+        let store = |metadata| Message::Cmd {
+            cmd: Cmd::PrepaidData {
+                cmd: DataCmd::Blob(BlobWrite::NewWithMetadata {
+                    blob: blob.clone(),
+                    metadata,
+                }),
+                prepaid: prepaid.clone(),
+                effective_after: None,
+            },
+            id: MessageId::new(),
+            app_id: None,
+            idempotency_key: None,
+            depends_on: vec![],
+        };
+
+        let message = store(metadata(2));
+        assert_eq!(message.size_class(), SizeClass::Large);
+        assert_eq!(Message::from(message.serialize()?)?, message);
+        assert!(store(metadata(crate::limits::MAX_BLOB_METADATA_SIZE))
+            .serialize()
+            .is_err());
+
+        // Elders storing the Blob at Adults, or forwarding the client's cmd to them.
+        let write = |metadata| BlobWrite::NewWithMetadata {
+            blob: blob.clone(),
+            metadata,
+        };
+        let keypair = Keypair::new_ed25519(&mut rand::thread_rng());
+        let forward = |metadata| -> Result<NodeDataCmd> {
+            let cmd = DataCmd::Blob(write(metadata));
+            let signature = keypair.sign(&ClientSigned::signable_bytes(&cmd)?);
+            Ok(NodeDataCmd::ForwardedClientCmd {
+                cmd,
+                client_signed: ClientSigned {
+                    public_key: keypair.public_key(),
+                    signature,
+                },
+                origin: XorName::from(keypair.public_key()),
+            })
+        };
+        let node_cmd = |cmd| Message::NodeCmd {
+            cmd: NodeCmd::Data(cmd),
+            id: MessageId::new(),
+        };
+        for value_len in [2, crate::limits::MAX_BLOB_METADATA_SIZE] {
+            let valid = value_len < crate::limits::MAX_BLOB_METADATA_SIZE;
+            let store = node_cmd(NodeDataCmd::Blob(write(metadata(value_len))));
+            assert_eq!(store.serialize().is_ok(), valid);
+            let forwarded = node_cmd(forward(metadata(value_len))?);
+            assert_eq!(forwarded.serialize().is_ok(), valid);
+        }
+
+        let query = BlobRead::GetMetadata(address);
+        assert_eq!(query.dst_address(), *address.name());
+        assert!(matches!(
+            query.authorisation_kind(),
+            AuthorisationKind::Data(DataAuthKind::PublicRead)
+        ));
+        assert_eq!(
+            BlobMetadata::try_from(query.error(Error::NoSuchData)),
+            Err(TryFromError::DataResponse(DataError::NotFound))
+        );
+        assert_eq!(
+            BlobMetadata::try_from(QueryResponse::GetBlobMetadata(Ok(metadata(2)))),
+            Ok(metadata(2))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn processing_error() -> Result<()> {
+        let query = Message::Query {
+            query: Query::Transfer(TransferQuery::GetBalance(gen_keys()[0])),
+            id: MessageId::new(),
+            app_id: None,
+            reply_to: None,
+        };
+        let elders = vec![(XorName::random(), ([127, 0, 0, 1], 12000).into())];
+        let error = query.create_processing_error(ProcessingReason::NotResponsible {
+            current_responsible: elders.clone(),
+        })?;
+        assert_eq!(Message::from(error.serialize()?)?, error);
+        match error {
+            Message::ProcessingError {
+                error:
+                    ProcessingError {
+                        reason:
+                            Some(ProcessingReason::NotResponsible {
+                                current_responsible,
+                            }),
+                        source_message: Some(bytes),
+                    },
+                correlation_id,
+                ..
+            } => {
+                assert_eq!(current_responsible, elders);
+                assert_eq!(correlation_id, query.id());
+                assert_eq!(Message::from(Bytes::from(bytes))?, query);
+            }
+            _ => anyhow::bail!("unexpected processing error: {:?}", error),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn section_key_mismatch() -> Result<()> {
+        let chain: Vec<_> = (0..3)
+            .map(|_| threshold_crypto::SecretKey::random().public_key())
+            .collect();
+        let current = chain[2];
+        assert_eq!(Error::section_key_mismatch(current, &chain), None);
+        assert_eq!(Error::section_key_mismatch(chain[0], &[]), None);
+        let unknown = threshold_crypto::SecretKey::random().public_key();
+        assert_eq!(
+            Error::section_key_mismatch(unknown, &chain),
+            Some(Error::SectionKeyMismatch {
+                provided: Box::new(unknown),
+                current: Box::new(current),
+                chain_len_hint: None,
+            })
+        );
+        let error = Error::section_key_mismatch(chain[0], &chain)
+            .ok_or_else(|| anyhow!("the outdated key should be reported"))?;
+        assert_eq!(
+            error,
+            Error::SectionKeyMismatch {
+                provided: Box::new(chain[0]),
+                current: Box::new(current),
+                chain_len_hint: Some(2),
+            }
+        );
+
+        let origin = Address::Client(XorName::random());
+        let query = Message::Query {
+            query: Query::Transfer(TransferQuery::GetBalance(gen_keys()[0])),
+            id: MessageId::new(),
+            app_id: None,
+            reply_to: None,
+        };
+        match query.error_response(error.clone(), origin.clone()) {
+            Some(Message::QueryResponse {
+                response: QueryResponse::GetBalance(Err(err)),
+                correlation_id,
+                query_origin,
+                ..
+            }) => {
+                assert_eq!(err, error);
+                assert_eq!(correlation_id, query.id());
+                assert_eq!(query_origin, origin);
+            }
+            response => anyhow::bail!("unexpected response: {:?}", response),
+        }
+
+        let cmd = Message::Cmd {
+            cmd: Cmd::Echo {
+                client: XorName::random(),
+                payload: vec![],
+            },
+            id: MessageId::new(),
+            app_id: None,
+            idempotency_key: None,
+            depends_on: vec![],
+        };
+        match cmd.error_response(error.clone(), origin.clone()) {
+            Some(Message::CmdError {
+                error: CmdError::Other(err),
+                correlation_id,
+                ..
+            }) => {
+                assert_eq!(err, error);
+                assert_eq!(correlation_id, cmd.id());
+            }
+            response => anyhow::bail!("unexpected response: {:?}", response),
+        }
+        let event = cmd.create_processing_error(ProcessingReason::Deserialization)?;
+        assert_eq!(event.error_response(error, origin), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn audit_authorisation() -> Result<()> {
+        let actor = threshold_crypto::SecretKey::random();
+        let replicas = threshold_crypto::SecretKeySet::random(0, &mut rand::thread_rng());
+        let owner = PublicKey::Bls(actor.public_key());
+        let sign = |bytes: &[u8]| sn_data_types::Signature::Bls(actor.sign(bytes));
+        let payment = TransferAgreementProof {
+            signed_debit: sn_data_types::SignedDebit {
+                debit: sn_data_types::Debit {
+                    id: crdts::Dot::new(owner, 0),
+                    amount: Token::from_nano(10),
+                },
+                actor_signature: sign(b"debit"),
+            },
+            signed_credit: sn_data_types::SignedCredit {
+                credit: sn_data_types::Credit {
+                    id: [0; 32],
+                    amount: Token::from_nano(10),
+                    recipient: owner,
+                    msg: "audit".to_string(),
+                },
+                actor_signature: sign(b"credit"),
+            },
+            debit_sig: sign(b"debit"),
+            credit_sig: sign(b"credit"),
+            debiting_replicas_keys: replicas.public_keys(),
+        };
+        let public = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        let private = Blob::Private(sn_data_types::PrivateBlob::new(vec![1, 2, 3], owner));
+        for blob in &[public, private] {
+            let cmd = Cmd::RequestAudit {
+                address: DataAddress::Blob(*blob.address()),
+                payment: payment.clone(),
+            };
+            assert!(matches!(
+                cmd.authorisation_kind(),
+                AuthorisationKind::Data(DataAuthKind::Write)
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn subscribe_from_entry_hash() -> Result<()> {
+        let address = sn_data_types::SequenceAddress::Public {
+            name: XorName::random(),
+            tag: 15000,
+        };
+        let entry = b"entry".to_vec();
+        let since = EntryHash::of(3, &entry);
+        assert_eq!(since, EntryHash::of(3, &entry));
+        // The same content appended at another index is another entry.
+        assert_ne!(since, EntryHash::of(4, &entry));
+        assert_ne!(since, EntryHash::of(3, b"other"));
+
+        for since in [None, Some(since)] {
+            let msg = Message::Query {
+                query: Query::Data(DataQuery::Sequence(SequenceRead::SubscribeFrom {
+                    address,
+                    since,
+                })),
+                id: MessageId::new(),
+                app_id: None,
+                reply_to: None,
+            };
+            assert_eq!(Message::from(msg.serialize()?)?, msg);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn blob_batches() -> Result<()> {
+        let prepaid = prepaid_ops(gen_keys()[0])?;
+        let delete = |addresses| Message::Cmd {
+            cmd: Cmd::PrepaidData {
+                cmd: DataCmd::Blob(BlobWrite::DeleteBatch(addresses)),
+                prepaid: prepaid.clone(),
+                effective_after: None,
+            },
+            id: MessageId::new(),
+            app_id: None,
+            idempotency_key: None,
+            depends_on: vec![],
+        };
+        let get = |addresses| Message::Query {
+            query: Query::Data(DataQuery::Blob(BlobRead::GetChunks(addresses))),
+            id: MessageId::new(),
+            app_id: None,
+            reply_to: None,
+        };
+        let addresses = vec![
+            BlobAddress::Private(XorName::random()),
+            BlobAddress::Private(XorName::random()),
+        ];
+
+        // Batches are routed to the section of their first address...
+        assert_eq!(
+            BlobWrite::DeleteBatch(addresses.clone()).dst_address(),
+            *addresses[0].name()
+        );
+        assert_eq!(
+            BlobRead::GetChunks(addresses.clone()).dst_address(),
+            *addresses[0].name()
+        );
+        for msg in &[delete(addresses.clone()), get(addresses)] {
+            assert_eq!(&Message::from(msg.serialize()?)?, msg);
+        }
+        // ...so empty batches, which have no section to be routed to, are rejected.
+        assert!(matches!(
+            delete(vec![]).serialize(),
+            Err(crate::Error::EmptyBatch)
+        ));
+        assert!(matches!(
+            get(vec![]).serialize(),
+            Err(crate::Error::EmptyBatch)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn replication_status() -> Result<()> {
+        let section_key = threshold_crypto::SecretKey::random();
+        let address = DataAddress::Blob(BlobAddress::Public(XorName::random()));
+        let last_verified: BTreeMap<_, _> = vec![(XorName::random(), SystemTime::now())]
+            .into_iter()
+            .collect();
+        let status = ReplicationStatus {
+            address,
+            holder_count: 1,
+            target_redundancy: 4,
+            last_verified: last_verified.clone(),
+            section_key: section_key.public_key(),
+            signature: section_key.sign(ReplicationStatus::signable_bytes(
+                &address,
+                1,
+                4,
+                &last_verified,
+            )?),
+        };
+        assert!(status.verify());
+        let tampered = ReplicationStatus {
+            holder_count: 4,
+            ..status.clone()
+        };
+        assert!(!tampered.verify());
+        let other_key = threshold_crypto::SecretKey::random();
+        let forged = ReplicationStatus {
+            section_key: other_key.public_key(),
+            ..status.clone()
+        };
+        assert!(!forged.verify());
+
+        let response = QueryResponse::GetReplicationStatus(Ok(status.clone()));
+        let msg = Message::query_response(
+            response.clone(),
+            MessageId::new(),
+            Address::Client(XorName::random()),
+        );
+        assert_eq!(Message::from(msg.serialize()?)?, msg);
+        assert_eq!(ReplicationStatus::try_from(response), Ok(status));
+
+        Ok(())
+    }
+
+    #[test]
+    fn scheduled_cmds() -> Result<()> {
+        let owner = gen_keys()[0];
+        let prepaid = prepaid_ops(owner)?;
+        let blob = Blob::Private(sn_data_types::PrivateBlob::new(vec![1, 2, 3], owner));
+        let address = DataAddress::Blob(*blob.address());
+        let effective_after = SystemTime::now() + Duration::from_secs(3_600);
+        let scheduled = |effective_after| Message::Cmd {
+            cmd: Cmd::PrepaidData {
+                cmd: DataCmd::Blob(BlobWrite::New(blob.clone())),
+                prepaid: prepaid.clone(),
+                effective_after,
+            },
+            id: MessageId::new(),
+            app_id: None,
+            idempotency_key: None,
+            depends_on: vec![],
+        };
+
+        for message in &[scheduled(None), scheduled(Some(effective_after))] {
+            assert_eq!(&Message::from(message.serialize()?)?, message);
+        }
+        let message = scheduled(Some(effective_after));
+        let (cmd, cmd_id) = match &message {
+            Message::Cmd { cmd, id, .. } => (cmd, *id),
+            _ => return Err(anyhow!("Expected a cmd")),
+        };
+        assert_eq!(cmd.effective_after(), Some(effective_after));
+
+        let cancel = Cmd::CancelScheduled { address, cmd_id };
+        let status = Query::GetScheduledCmdStatus { address, cmd_id };
+        assert_eq!(cancel.dst_address(), cmd.dst_address());
+        assert_eq!(status.dst_address(), cmd.dst_address());
+        assert_eq!(cancel.effective_after(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn node_query_response_responder() -> Result<()> {
+        let response = |responder| Message::NodeQueryResponse {
+            response: NodeQueryResponse::Rewards(NodeRewardQueryResponse::GetPenaltyHistory(Ok(
+                vec![],
+            ))),
+            id: MessageId::new(),
+            correlation_id: MessageId::new(),
+            query_origin: Address::Node(XorName::random()),
+            responder,
+        };
+
+        for message in &[
+            response(None),
+            response(Some(Duty::Adult(AdultDuties::ChunkStorage))),
+            response(Some(Duty::Elder(ElderDuties::Metadata))),
+        ] {
+            assert_eq!(&Message::from(message.serialize()?)?, message);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn node_query_response_pages() -> Result<()> {
+        let blobs = (0..10)
+            .map(|i| Blob::Public(PublicBlob::new(vec![i; 100])))
+            .collect();
+        let response = NodeQueryResponse::Data(NodeDataQueryResponse::GetChunks(Ok(blobs)));
+        let section_key = threshold_crypto::SecretKey::random();
+        let sign = |bytes: Vec<u8>| section_key.sign(bytes);
+
+        assert!(response
+            .into_pages(
+                crate::limits::MAX_QUERY_RESPONSE_PAGE_SIZE + 1,
+                section_key.public_key(),
+                sign
+            )
+            .is_err());
+        let mut pages = response.into_pages(64, section_key.public_key(), sign)?;
+        assert!(pages.len() > 1);
+        pages.reverse();
+        assert_eq!(
+            NodeQueryResponse::from_pages(pages.clone(), &section_key.public_key())?,
+            response
+        );
+
+        let mut tampered = pages.clone();
+        if let Some(NodeQueryResponse::DataPage { payload, .. }) = tampered.first_mut() {
+            payload[0] ^= 1;
+        }
+        assert!(NodeQueryResponse::from_pages(tampered, &section_key.public_key()).is_err());
+
+        // Pages with a consistent root, but which the section didn't sign, are rejected.
+        let other_key = threshold_crypto::SecretKey::random();
+        assert!(NodeQueryResponse::from_pages(pages.clone(), &other_key.public_key()).is_err());
+        let forged =
+            response.into_pages(64, section_key.public_key(), |bytes| other_key.sign(bytes))?;
+        assert!(NodeQueryResponse::from_pages(forged, &section_key.public_key()).is_err());
+
+        let _ = pages.pop();
+        assert!(NodeQueryResponse::from_pages(pages, &section_key.public_key()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_export() -> Result<()> {
+        let section = XorName::random();
+        let data_types = vec![SnapshotDataType::Map, SnapshotDataType::Sequence]
+            .into_iter()
+            .collect();
+        let range = SystemTime::UNIX_EPOCH..SystemTime::now();
+        let query = NodeQuery::System(NodeSystemQuery::ExportSnapshot {
+            section,
+            data_types,
+            range: range.clone(),
+        });
+        assert_eq!(query.dst_address(), Address::Section(section));
+
+        let snapshot = vec![7; 1000];
+        let (manifest, mut parts) =
+            SnapshotManifest::split(section, BTreeSet::new(), range, &snapshot, 64)?;
+        assert!(parts.len() > 1);
+        assert_eq!(manifest.parts as usize, parts.len());
+        assert!(parts.iter().all(|part| manifest.verify(part)));
+
+        let msg = Message::NodeQueryResponse {
+            response: NodeQueryResponse::System(NodeSystemQueryResponse::SnapshotPart(
+                parts[0].clone(),
+            )),
+            id: MessageId::new(),
+            correlation_id: MessageId::new(),
+            query_origin: Address::Node(XorName::random()),
+            responder: None,
+        };
+        assert_eq!(msg.size_class(), SizeClass::Large);
+        assert_eq!(Message::from(msg.serialize()?)?, msg);
+
+        parts.reverse();
+        assert_eq!(manifest.assemble(parts.clone())?, snapshot);
+        let _ = parts.pop();
+        assert!(manifest.assemble(parts).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn heartbeat() -> Result<()> {
+        let (section, neighbour) = (XorName::random(), XorName::random());
+        let section_key = threshold_crypto::SecretKey::random().public_key();
+        let neighbour_key = threshold_crypto::SecretKey::random().public_key();
+        let stale_key = threshold_crypto::SecretKey::random().public_key();
+
+        let event = NodeEvent::heartbeat(section, section_key, neighbour, &neighbour_key);
+        assert_eq!(event.dst_address(), Address::Section(neighbour));
+        let msg = Message::NodeEvent {
+            event: event.clone(),
+            id: MessageId::new(),
+            correlation_id: MessageId::new(),
+        };
+        assert_eq!(Message::from(msg.serialize()?)?, msg);
+
+        assert_eq!(event.heartbeat_sync_query(Some(&section_key)), None);
+        let query = NodeQuery::System(NodeSystemQuery::GetSectionKnowledge { section });
+        assert_eq!(
+            event.heartbeat_sync_query(Some(&stale_key)),
+            Some(query.clone())
+        );
+        assert_eq!(event.heartbeat_sync_query(None), Some(query.clone()));
+        assert_eq!(query.dst_address(), Address::Section(section));
+
+        assert!(!event.heartbeat_is_stale(&neighbour_key));
+        assert!(event.heartbeat_is_stale(&stale_key));
+
+        Ok(())
+    }
+
+    #[test]
+    fn metadata_digest() -> Result<()> {
+        let section = XorName::random();
+        let (low, high) = (
+            XorName([0; 32])..XorName([0x80; 32]),
+            XorName([0x80; 32])..XorName([0xff; 32]),
+        );
+        let query = NodeQuery::System(NodeSystemQuery::GetMetadataDigest {
+            section,
+            ranges: vec![low.clone(), high.clone()],
+        });
+        assert_eq!(query.dst_address(), Address::Section(section));
+
+        let mut store = BTreeMap::new();
+        let _ = store.insert(XorName([0x10; 32]), vec![1, 2, 3]);
+        let _ = store.insert(XorName([0x90; 32]), vec![4]);
+        let digest = |store: &BTreeMap<XorName, Vec<u8>>| MetadataDigest {
+            section,
+            ranges: vec![
+                RangeDigest::new(low.clone(), store),
+                RangeDigest::new(high.clone(), store),
+            ],
+        };
+        let ours = digest(&store);
+        assert_eq!(ours.ranges[0].entries, 1);
+        assert!(ours.diverging(&digest(&store)).is_empty());
+
+        let mut theirs = store.clone();
+        let _ = theirs.insert(XorName([0xa0; 32]), vec![5]);
+        assert_eq!(ours.diverging(&digest(&theirs)), vec![high.clone()]);
+        let partial = MetadataDigest {
+            section,
+            ranges: vec![RangeDigest::new(high, &theirs)],
+        };
+        assert_eq!(ours.diverging(&partial).len(), 2);
+
+        let msg = Message::NodeQueryResponse {
+            response: NodeQueryResponse::System(NodeSystemQueryResponse::GetMetadataDigest(Ok(
+                ours,
+            ))),
+            id: MessageId::new(),
+            correlation_id: MessageId::new(),
+            query_origin: Address::Node(XorName::random()),
+            responder: None,
+        };
+        assert_eq!(Message::from(msg.serialize()?)?, msg);
+
+        Ok(())
+    }
+
+    #[test]
+    fn response_id_chains() -> Result<()> {
+        let query_id = MessageId::new();
+        let origin = Address::Client(XorName::random());
+        let response = Message::query_response(
+            QueryResponse::GetBalance(Err(Error::NoSuchBalance)),
+            query_id,
+            origin.clone(),
+        );
+        assert!(response.id().is_in_response_to(&query_id));
+        assert!(!query_id.is_in_response_to(&response.id()));
+        assert_eq!(Message::from(response.serialize()?)?, response);
+
+        let cmd_id = MessageId::new();
+        let error = Message::cmd_error(
+            CmdError::Transfer(TransferError::TransferValidation(Error::NoSuchBalance)),
+            cmd_id,
+            origin,
+        );
+        assert!(error.id().is_in_response_to(&cmd_id));
+
+        Ok(())
+    }
+
+    #[test]
+    fn storage_receipts() -> Result<()> {
+        let owner = gen_keys()[0];
+        let section_key = threshold_crypto::SecretKey::random();
+        let address = DataAddress::Blob(BlobAddress::Private(XorName::random()));
+        let paid = Token::from_nano(1_000);
+        let stored_at = SystemTime::now();
+        let receipt = StorageReceipt {
+            owner,
+            address,
+            size: 1024,
+            paid,
+            stored_at,
+            section_key: section_key.public_key(),
+            signature: section_key.sign(StorageReceipt::signable_bytes(
+                &owner, &address, 1024, paid, &stored_at,
+            )?),
+        };
+        assert!(receipt.verify());
+        assert!(!StorageReceipt {
+            size: 2048,
+            ..receipt.clone()
+        }
+        .verify());
+
+        let query = Query::GetStorageReceipts {
+            owner,
+            range: SystemTime::UNIX_EPOCH..stored_at,
+        };
+        assert_eq!(query.dst_address(), XorName::from(owner));
+        let response = Message::query_response(
+            QueryResponse::GetStorageReceipts(Ok(vec![receipt.clone()])),
+            MessageId::new(),
+            Address::Client(XorName::from(owner)),
+        );
+        assert_eq!(Message::from(response.serialize()?)?, response);
+        if let Message::QueryResponse { response, .. } = response {
+            assert_eq!(Vec::<StorageReceipt>::try_from(response), Ok(vec![receipt]));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn sequence_length() -> Result<()> {
+        let address = sn_data_types::SequenceAddress::Public {
+            name: XorName::random(),
+            tag: 15000,
+        };
+        let query = Query::Data(DataQuery::Sequence(SequenceRead::GetLength(address)));
+        assert_eq!(query.dst_address(), *address.name());
+        assert_eq!(
+            query.error(Error::NoSuchData),
+            QueryResponse::GetSequenceLength(Err(DataError::NotFound))
+        );
+
+        let response = QueryResponse::GetSequenceLength(Ok(42));
+        assert_eq!(u64::try_from(response.clone()), Ok(42));
+        let msg = Message::query_response(
+            response,
+            MessageId::new(),
+            Address::Client(XorName::random()),
+        );
+        assert_eq!(msg.size_class(), SizeClass::Small);
+        assert_eq!(Message::from(msg.serialize()?)?, msg);
+
+        Ok(())
+    }
+
+    #[test]
+    fn size_class_of_payload() -> Result<()> {
+        let listing = |key_len| Listing {
+            items: vec![vec![0; key_len]].into_iter().collect(),
+            truncated: false,
+            total_available: 1,
+        };
+        let msg = |key_len| {
+            Message::query_response(
+                QueryResponse::ListMapKeys(Ok(listing(key_len))),
+                MessageId::new(),
+                Address::Client(XorName::random()),
+            )
+        };
+        let header_class = |msg: Message| -> Result<SizeClass> {
+            Ok(WireMsg::from(msg.serialize()?)?.header().size_class)
+        };
+
+        assert_eq!(msg(1).size_class(), SizeClass::Small);
+        assert_eq!(header_class(msg(1))?, SizeClass::Small);
+        let large = msg(crate::limits::MAX_MEDIUM_MSG_SIZE);
+        assert_eq!(large.size_class(), SizeClass::Small);
+        assert_eq!(header_class(large)?, SizeClass::Large);
+
+        Ok(())
+    }
+
+    #[test]
+    fn offloaded_responses() -> Result<()> {
+        let blob = Blob::Public(PublicBlob::new(vec![7; 2048]));
+        let response = QueryResponse::GetBlob(Ok(blob.clone()));
+        let expires = SystemTime::now() + Duration::from_secs(600);
+
+        let (inline, stored) = response.clone().offload(4096, expires)?;
+        assert_eq!((inline, stored), (response.clone(), vec![]));
+
+        let (offloaded, stored) = response.clone().offload(1024, expires)?;
+        let offloaded = match offloaded {
+            QueryResponse::Offloaded(offloaded) => offloaded,
+            other => return Err(anyhow!("unexpected response: {:?}", other)),
+        };
+        assert_eq!(stored.len(), 1);
+        assert_eq!(offloaded.addresses, vec![*stored[0].address()]);
+        assert!(stored.iter().all(Blob::is_pub));
+        assert_eq!(offloaded.open(&stored)?, response);
+        assert!(offloaded.open(&[blob]).is_err());
+        assert!(!offloaded.is_expired_at(SystemTime::now()));
+
+        let msg = Message::query_response(
+            QueryResponse::Offloaded(offloaded),
+            MessageId::new(),
+            Address::Client(XorName::random()),
+        );
+        assert_eq!(msg.size_class(), SizeClass::Small);
+        assert_eq!(Message::from(msg.serialize()?)?, msg);
+
+        // A response larger than a chunk is split over several Blobs.
+        let blob = Blob::Public(PublicBlob::new(vec![7; MAX_CHUNK_SIZE + 1024]));
+        let response = QueryResponse::GetBlob(Ok(blob));
+        let (offloaded, stored) = response.clone().offload(1024, expires)?;
+        let offloaded = match offloaded {
+            QueryResponse::Offloaded(offloaded) => offloaded,
+            other => return Err(anyhow!("unexpected response: {:?}", other)),
+        };
+        assert_eq!(stored.len(), 2);
+        assert!(stored
+            .iter()
+            .all(|blob| blob.value().len() <= MAX_CHUNK_SIZE));
+        assert_eq!(offloaded.open(&stored)?, response);
+        let reversed: Vec<_> = stored.iter().rev().cloned().collect();
+        assert!(offloaded.open(&reversed).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn offloaded_responses_fetched_by_the_client() -> Result<()> {
+        let blob = Blob::Public(PublicBlob::new(vec![7; MAX_CHUNK_SIZE + 1024]));
+        let response = QueryResponse::GetBlob(Ok(blob));
+        let expires = SystemTime::now() + Duration::from_secs(600);
+        let (offloaded, stored) = response.clone().offload(1024, expires)?;
+        let offloaded = match offloaded {
+            QueryResponse::Offloaded(offloaded) => offloaded,
+            other => return Err(anyhow!("unexpected response: {:?}", other)),
+        };
+        let store: BTreeMap<_, _> = stored
+            .into_iter()
+            .map(|blob| (*blob.address(), blob))
+            .collect();
+
+        // The client, which doesn't own the Blobs, fetches them as public data.
+        let fetched = offloaded
+            .queries()
+            .into_iter()
+            .map(|query| {
+                assert!(matches!(
+                    query.authorisation_kind(),
+                    AuthorisationKind::Data(DataAuthKind::PublicRead)
+                ));
+                let address = match query {
+                    Query::Data(DataQuery::Blob(BlobRead::Get(address))) => address,
+                    other => return Err(anyhow!("unexpected query: {:?}", other)),
+                };
+                let blob = store
+                    .get(&address)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("no Blob at {:?}", address))?;
+                Ok(Blob::try_from(QueryResponse::GetBlob(Ok(blob)))?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(offloaded.open(&fetched)?, response);
+
+        Ok(())
+    }
+
+    #[test]
+    fn offloaded_responses_larger_than_a_message() -> Result<()> {
+        // Responses too large to be sent in a message are what offloading is for.
+        let response =
+            QueryResponse::GetBlob(Ok(Blob::Public(PublicBlob::new(vec![7; MAX_MESSAGE_SIZE]))));
+        let expires = SystemTime::now() + Duration::from_secs(600);
+        let (offloaded, stored) = response.offload(MAX_MESSAGE_SIZE, expires)?;
+        let offloaded = match offloaded {
+            QueryResponse::Offloaded(offloaded) => offloaded,
+            other => return Err(anyhow!("unexpected response: {:?}", other)),
+        };
+        assert!(stored.len() > MAX_MESSAGE_SIZE / MAX_CHUNK_SIZE);
+        assert_eq!(offloaded.addresses.len(), stored.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn attributed_cmd_errors() -> Result<()> {
+        let address = sn_data_types::SequenceAddress::Public {
+            name: XorName::random(),
+            tag: 15000,
+        };
+        let cmd = DataCmd::Sequence(SequenceWrite::Delete(address));
+        let error = cmd.error(Error::NoSuchData);
+        assert_eq!(
+            error,
+            CmdError::Data {
+                address: DataAddress::Sequence(address),
+                op: DataOpKind::Delete,
+                error: DataError::NotFound,
+            }
+        );
+        assert_eq!(error.address(), Some(&DataAddress::Sequence(address)));
+
+        let msg = Message::cmd_error(error, MessageId::new(), Address::Client(XorName::random()));
+        assert_eq!(Message::from(msg.serialize()?)?, msg);
+
+        // Batches are attributed per address, with `CmdError::DataBatch`.
+        let addresses = vec![BlobAddress::Private(XorName::random())];
+        let batch = DataCmd::Blob(BlobWrite::DeleteBatch(addresses.clone()));
+        assert_eq!(batch.op(), DataOpKind::Delete);
+        assert_eq!(
+            batch.error(Error::NoSuchData),
+            CmdError::DataBatch(
+                addresses
+                    .into_iter()
+                    .map(|address| (address, Error::NoSuchData))
+                    .collect()
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn data_errors() -> Result<()> {
+        let error = CmdError::Data {
+            address: DataAddress::Map(sn_data_types::MapAddress::Seq {
+                name: XorName::random(),
+                tag: 15000,
+            }),
+            op: DataOpKind::SetPermissions,
+            error: Error::InvalidOwnersSuccessor(3).into(),
+        };
+        let data_error = error
+            .data_error()
+            .cloned()
+            .ok_or_else(|| anyhow!("not a data error"))?;
+        assert_eq!(
+            data_error,
+            DataError::VersionMismatch {
+                of: VersionOf::Owners,
+                expected: 3,
+                actual: None,
+            }
+        );
+        assert_eq!(Error::from(data_error), Error::InvalidOwnersSuccessor(3));
+        assert_eq!(
+            CmdError::Transfer(TransferError::TransferValidation(Error::NoSuchBalance))
+                .data_error(),
+            None
+        );
+
+        let response = QueryResponse::GetSequenceLength(Err(DataError::NotFound));
+        assert_eq!(response.data_error(), Some(&DataError::NotFound));
+        assert_eq!(response.error(), None);
+        assert_eq!(QueryResponse::GetSequenceLength(Ok(1)).data_error(), None);
+        let response = QueryResponse::GetBalance(Err(Error::NoSuchBalance));
+        assert_eq!(response.error(), Some(&Error::NoSuchBalance));
+        assert_eq!(response.data_error(), None);
+        assert_eq!(
+            DataError::from(Error::InsufficientBalance),
+            DataError::Other(Box::new(Error::InsufficientBalance))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn retry_advice() {
+        assert_eq!(
+            Error::NotEnoughSpace.retry_advice(),
+            Some(RetryAdvice { after: None })
+        );
+        assert!(!Error::NotEnoughSpace.is_fatal());
+        assert!(Error::InsufficientBalance.is_fatal());
+        let correctable = Error::InvalidSuccessor(1);
+        assert!(!correctable.is_retryable() && !correctable.is_fatal());
+
+        let error = |reason| ProcessingError::new().with_reason(reason);
+        let retry_after = Some(Duration::from_secs(5));
+        assert_eq!(
+            error(ProcessingReason::Overloaded { retry_after }).retry_advice(),
+            Some(RetryAdvice { after: retry_after })
+        );
+        assert!(error(ProcessingReason::Deserialization).is_fatal());
+        let latest = threshold_crypto::SecretKey::random().public_key();
+        let missing_key = error(ProcessingReason::MissingSectionKey { latest });
+        assert!(!missing_key.is_retryable() && !missing_key.is_fatal());
+        assert!(!ProcessingError::new().is_fatal());
+    }
 }
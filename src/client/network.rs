@@ -7,13 +7,29 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use super::{Address, BlobWrite, Error, MsgSender, Result};
+use super::{
+    Address, BlobWrite, DataCmd, DataQuery, Error, MerkleProof, MessageId, MsgSender,
+    QueryResponse, Result, SignedRoot,
+};
+use crate::{
+    limits::MAX_QUERY_RESPONSE_PAGE_SIZE,
+    signing::{self, CHUNK_HOLDERS_DOMAIN, CLIENT_MSG_DOMAIN, REWARD_STATE_DOMAIN},
+};
 use serde::{Deserialize, Serialize};
+use sn_data_types::Token;
 use sn_data_types::{
     Blob, BlobAddress, Credit, DebitId, PublicKey, ReplicaEvent, Signature, SignatureShare,
     SignedCredit, SignedTransferShare, TransferAgreementProof, TransferValidated, WalletInfo,
 };
-use std::collections::BTreeSet;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    net::SocketAddr,
+    ops::Range,
+    time::{Duration, SystemTime},
+};
+use threshold_crypto::{PublicKey as BlsPublicKey, Signature as BlsSignature};
+use tiny_keccak::{Hasher, Sha3};
 use xor_name::XorName;
 
 // -------------- Node Cmds --------------
@@ -64,6 +80,39 @@ pub enum NodeSystemCmd {
         /// Section to which the message needs to be sent to. (NB: this is the section of the node id).
         section: XorName,
     },
+    /// Set the window within which replayed messages are detected and rejected.
+    /// Agreed by the Elders and published to the Adults of the section.
+    SetAntiReplayWindow {
+        /// Length of the window, in seconds.
+        seconds: u64,
+        /// The section the window applies to.
+        section: XorName,
+    },
+    /// Announce that the node is leaving the network, so that its chunks can be
+    /// replicated before it disappears rather than after its absence is detected.
+    AnnounceLeave {
+        /// Name of the leaving node.
+        name: XorName,
+        /// When the node will leave.
+        effective_at: SystemTime,
+        /// The section of the node.
+        section: XorName,
+    },
+    /// Restore the section-held metadata from a snapshot, see `NodeSystemQuery::ExportSnapshot`.
+    /// The parts of the snapshot follow, each in an `ImportSnapshotPart`.
+    ImportSnapshot {
+        /// Manifest of the snapshot to import.
+        manifest: SnapshotManifest,
+        /// The section to import the snapshot into.
+        section: XorName,
+    },
+    /// A part of a snapshot being imported, verified against the manifest it refers to.
+    ImportSnapshotPart {
+        /// The part of the snapshot.
+        part: SnapshotPart,
+        /// The section to import the snapshot into.
+        section: XorName,
+    },
 }
 
 ///
@@ -93,10 +142,143 @@ pub enum NodeDataCmd {
     },
     /// Elder-to-Adult cmd.
     Blob(BlobWrite),
+    /// Elder-to-Adult cmd, forwarding a client's write along with the client's
+    /// signature over it, so that the Adult can independently validate the write.
+    ForwardedClientCmd {
+        /// The client's cmd.
+        cmd: DataCmd,
+        /// The client's signature over the cmd.
+        client_signed: ClientSigned,
+        /// Name of the client the cmd originates from.
+        origin: XorName,
+    },
+}
+
+/// A client's signature, as forwarded by Elders along with the client's cmd.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ClientSigned {
+    /// The client's public key.
+    pub public_key: PublicKey,
+    /// The client's signature over the cmd.
+    pub signature: Signature,
+}
+
+impl ClientSigned {
+    /// Returns the bytes the client is expected to sign for the cmd.
+    pub fn signable_bytes(cmd: &DataCmd) -> crate::Result<Vec<u8>> {
+        let bytes = bincode::serialize(cmd).map_err(|err| {
+            crate::Error::Serialisation(format!("could not serialize data cmd: {}", err))
+        })?;
+        Ok(signing::signable_bytes(CLIENT_MSG_DOMAIN, &bytes))
+    }
+}
+
+impl NodeDataCmd {
+    /// Verifies that a forwarded client cmd was signed by the client it claims to originate from,
+    /// i.e. that the signature over the cmd is valid, and that the signing key is the origin's.
+    /// Returns false for any cmd which is not a forwarded client cmd.
+    pub fn verify_client_origin(&self) -> bool {
+        match self {
+            Self::ForwardedClientCmd {
+                cmd,
+                client_signed,
+                origin,
+            } => {
+                if XorName::from(client_signed.public_key) != *origin {
+                    return false;
+                }
+                match ClientSigned::signable_bytes(cmd) {
+                    Ok(bytes) => client_signed
+                        .public_key
+                        .verify(&client_signed.signature, bytes)
+                        .is_ok(),
+                    Err(_) => false,
+                }
+            }
+            Self::ReplicateChunk { .. } | Self::Blob(_) => false,
+        }
+    }
+}
+
+/// Wraps a client's cmd, along with the client's signature over it, for an Elder to forward
+/// it to Adults. The origin is the name of the client's key.
+impl From<(DataCmd, ClientSigned)> for NodeCmd {
+    fn from((cmd, client_signed): (DataCmd, ClientSigned)) -> Self {
+        let origin = XorName::from(client_signed.public_key);
+        Self::Data(NodeDataCmd::ForwardedClientCmd {
+            cmd,
+            client_signed,
+            origin,
+        })
+    }
+}
+
+/// Unwraps a client's cmd forwarded by an Elder, returning the node cmd as is
+/// if it isn't a forwarded client cmd.
+impl TryFrom<NodeCmd> for (DataCmd, ClientSigned) {
+    type Error = NodeCmd;
+    fn try_from(cmd: NodeCmd) -> std::result::Result<Self, Self::Error> {
+        match cmd {
+            NodeCmd::Data(NodeDataCmd::ForwardedClientCmd {
+                cmd, client_signed, ..
+            }) => Ok((cmd, client_signed)),
+            other => Err(other),
+        }
+    }
+}
+
+/// Wraps a client's query, along with the name of the client, for an Elder to forward it to Adults.
+impl From<(DataQuery, XorName)> for NodeQuery {
+    fn from((query, origin): (DataQuery, XorName)) -> Self {
+        Self::Data(NodeDataQuery::ForwardedClientQuery { query, origin })
+    }
+}
+
+/// Unwraps a client's query forwarded by an Elder, returning the node query as is
+/// if it isn't a forwarded client query.
+impl TryFrom<NodeQuery> for (DataQuery, XorName) {
+    type Error = NodeQuery;
+    fn try_from(query: NodeQuery) -> std::result::Result<Self, Self::Error> {
+        match query {
+            NodeQuery::Data(NodeDataQuery::ForwardedClientQuery { query, origin }) => {
+                Ok((query, origin))
+            }
+            other => Err(other),
+        }
+    }
+}
+
+/// Wraps the response to a client's query forwarded by an Elder, for the Adult to send it back.
+impl From<QueryResponse> for NodeQueryResponse {
+    fn from(response: QueryResponse) -> Self {
+        Self::ForwardedClientQuery(response)
+    }
+}
+
+/// Unwraps the response to a client's query, for the Elder to send it back to the client,
+/// returning the node query response as is if it isn't the response to a forwarded client query.
+impl TryFrom<NodeQueryResponse> for QueryResponse {
+    type Error = NodeQueryResponse;
+    fn try_from(response: NodeQueryResponse) -> std::result::Result<Self, Self::Error> {
+        match response {
+            NodeQueryResponse::ForwardedClientQuery(response) => Ok(response),
+            other => Err(other),
+        }
+    }
 }
 
 // -------------- Node Events --------------
 
+/// The interval at which Adults are expected to flush the errors accumulated
+/// for a `NodeEvent::ErrorBatch`. An Adult sends a batch at the latest this long after
+/// the first error in it occurred, so Elders see bursts of failures atomically.
+pub const ERROR_BATCH_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The interval at which Elders are expected to send a `NodeEvent::Heartbeat` to each
+/// neighbouring section, so that stale knowledge of a section key is corrected
+/// before a cross-section message is sent to an outdated key.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
 ///
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
@@ -115,6 +297,120 @@ pub enum NodeEvent {
     SectionPayoutValidated(TransferValidated),
     ///
     SectionPayoutRegistered { from: PublicKey, to: PublicKey },
+    /// A penalty was applied to a node's rewards, emitted by Elders, received by the node.
+    PenaltyApplied {
+        /// The penalised node.
+        node: XorName,
+        /// Why the penalty was applied.
+        reason: PenaltyReason,
+        /// The amount deducted from the node's rewards.
+        amount: Token,
+    },
+    /// A batch of errors, emitted by an Adult, received by Elders.
+    /// See `ERROR_BATCH_FLUSH_INTERVAL`.
+    ErrorBatch {
+        /// The errors, each with the id of the message which caused it.
+        errors: Vec<(MessageId, NodeDataError)>,
+        /// The section of the Adult.
+        section: XorName,
+    },
+    /// Elders acknowledge a node's `NodeSystemCmd::AnnounceLeave`, after having
+    /// started replicating its chunks. The node can leave once this is received.
+    LeaveAcknowledged {
+        /// Name of the leaving node.
+        name: XorName,
+        /// Number of chunks being replicated off the node.
+        chunks_to_replicate: u64,
+    },
+    /// The store cost debit of a client's data cmd has settled,
+    /// emitted by Elders, received by the section of the wallet.
+    PaymentProcessed {
+        /// Id of the paying data cmd.
+        msg_id: MessageId,
+        /// The amount debited.
+        amount: Token,
+        /// Key of the section wallet credited with the payment.
+        section_wallet_key: PublicKey,
+    },
+    /// Keep-alive sent by Elders to each neighbouring section, see `HEARTBEAT_INTERVAL`.
+    /// The receiving Elders sync with the sender if its key isn't the one they know,
+    /// see `NodeEvent::heartbeat_sync_query`, and send their own heartbeat back right away
+    /// if the sender's knowledge of them is stale, see `NodeEvent::heartbeat_is_stale`.
+    Heartbeat {
+        /// The section sending the heartbeat.
+        section: XorName,
+        /// The neighbouring section the heartbeat is sent to.
+        neighbour: XorName,
+        /// The current key of the sending section.
+        current_section_key: BlsPublicKey,
+        /// Hash of the key of the neighbour known by the sending section,
+        /// see `NodeEvent::knowledge_hash`.
+        knowledge_hash: XorName,
+    },
+}
+
+/// The reason for a penalty being applied to a node.
+#[derive(Debug, Hash, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum PenaltyReason {
+    /// The node has not been responding to requests.
+    Inactivity,
+    /// The node failed to return a chunk it was expected to hold.
+    FailedChunkRetrieval,
+    /// The node sent invalid or malicious messages.
+    Misbehaviour,
+}
+
+/// A penalty that was applied to a node.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Penalty {
+    /// Why the penalty was applied.
+    pub reason: PenaltyReason,
+    /// The amount deducted from the node's rewards.
+    pub amount: Token,
+    /// When the penalty was applied.
+    pub applied_at: SystemTime,
+}
+
+/// The state of the rewards of a node, as signed by the Elders of its section.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RewardState {
+    /// The id of the node.
+    pub node_id: XorName,
+    /// The rewards accrued by the node, but not yet paid out.
+    pub accrued: Token,
+    /// The amount and time of the last payout to the node, if any.
+    pub last_payout: Option<(Token, SystemTime)>,
+    /// The wallet currently registered for the node's rewards, if any.
+    pub wallet: Option<PublicKey>,
+    /// Key of the section of the node.
+    pub section_key: BlsPublicKey,
+    /// Section signature over the state.
+    pub signature: BlsSignature,
+}
+
+impl RewardState {
+    /// Returns the bytes the Elders of the section are expected to sign.
+    pub fn signable_bytes(
+        node_id: &XorName,
+        accrued: Token,
+        last_payout: &Option<(Token, SystemTime)>,
+        wallet: &Option<PublicKey>,
+    ) -> crate::Result<Vec<u8>> {
+        signing::signable_value(
+            REWARD_STATE_DOMAIN,
+            &(node_id, accrued, last_payout, wallet),
+            "reward state",
+        )
+    }
+
+    /// Verifies the section signature over the state against the contained section key.
+    pub fn verify(&self) -> bool {
+        signing::verify_section_signature(
+            &self.section_key,
+            &self.signature,
+            Self::signable_bytes(&self.node_id, self.accrued, &self.last_payout, &self.wallet),
+        )
+    }
 }
 
 ///
@@ -126,6 +422,251 @@ pub enum NodeQuery {
     Rewards(NodeRewardQuery),
     ///
     Transfers(NodeTransferQuery),
+    /// Queries related to the running of a section.
+    System(NodeSystemQuery),
+}
+
+/// Queries related to the running of a section.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub enum NodeSystemQuery {
+    /// Export the metadata held by the section, e.g. to back it up during testnets
+    /// or for disaster recovery. The response is a `SnapshotManifest`, followed by
+    /// the parts of the snapshot, each in a `NodeSystemQueryResponse::SnapshotPart`.
+    ExportSnapshot {
+        /// The section to export the metadata of.
+        section: XorName,
+        /// The types of data to include.
+        data_types: BTreeSet<SnapshotDataType>,
+        /// The time range of the history to include.
+        range: Range<SystemTime>,
+    },
+    /// Get the current key and Elders of the section, e.g. after a `NodeEvent::Heartbeat`
+    /// revealed that the key known of it is stale.
+    GetSectionKnowledge {
+        /// The section to get the knowledge of.
+        section: XorName,
+    },
+    /// Get the digest of the metadata held by the recipient Elder within the given ranges
+    /// of data names, for the Elders of a section to periodically cross-check their
+    /// metadata stores, see `MetadataDigest::diverging`.
+    GetMetadataDigest {
+        /// The section of the Elders.
+        section: XorName,
+        /// The ranges of data names to digest, each one separately.
+        ranges: Vec<Range<XorName>>,
+    },
+}
+
+/// The current key and Elders of a section, see `NodeSystemQuery::GetSectionKnowledge`.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SectionKnowledge {
+    /// The section.
+    pub section: XorName,
+    /// The current key of the section.
+    pub key: BlsPublicKey,
+    /// Names and addresses of the current Elders of the section.
+    pub elders: BTreeMap<XorName, SocketAddr>,
+}
+
+/// Digest of the metadata held by an Elder, per range of data names,
+/// see `NodeSystemQuery::GetMetadataDigest`.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct MetadataDigest {
+    /// The section of the Elder.
+    pub section: XorName,
+    /// The digest of each range queried.
+    pub ranges: Vec<RangeDigest>,
+}
+
+/// Digest of the metadata held within a range of data names, see `MetadataDigest`.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RangeDigest {
+    /// The range of data names.
+    pub range: Range<XorName>,
+    /// Number of entries within the range.
+    pub entries: u64,
+    /// Hash over the names and metadata of the entries within the range.
+    pub hash: [u8; 32],
+}
+
+impl RangeDigest {
+    /// Digests the entries, i.e. the data names along with their serialized metadata, which
+    /// are within the range, skipping the others. The entries are to be given ordered by
+    /// name, e.g. iterating a `BTreeMap`, so that Elders holding the same metadata compute
+    /// the same digest.
+    pub fn new<'a, V: AsRef<[u8]>>(
+        range: Range<XorName>,
+        entries: impl IntoIterator<Item = (&'a XorName, V)>,
+    ) -> Self {
+        let mut hasher = Sha3::v256();
+        let mut count = 0;
+        for (name, metadata) in entries {
+            if !range.contains(name) {
+                continue;
+            }
+            let metadata = metadata.as_ref();
+            hasher.update(&name.0);
+            hasher.update(&(metadata.len() as u64).to_be_bytes());
+            hasher.update(metadata);
+            count += 1;
+        }
+        let mut hash = [0; 32];
+        hasher.finalize(&mut hash);
+        Self {
+            range,
+            entries: count,
+            hash,
+        }
+    }
+}
+
+impl MetadataDigest {
+    /// Returns the ranges whose digests differ between the two, or which only one of them
+    /// digested, i.e. the ranges of which the metadata is to be repaired, e.g. by exchanging
+    /// the entries within them only.
+    pub fn diverging(&self, other: &Self) -> Vec<Range<XorName>> {
+        let find = |digests: &[RangeDigest], range: &Range<XorName>| {
+            digests
+                .iter()
+                .find(|digest| digest.range == *range)
+                .cloned()
+        };
+        let mut diverging: Vec<_> = self
+            .ranges
+            .iter()
+            .filter(|digest| find(&other.ranges, &digest.range).as_ref() != Some(*digest))
+            .map(|digest| digest.range.clone())
+            .collect();
+        diverging.extend(
+            other
+                .ranges
+                .iter()
+                .filter(|digest| find(&self.ranges, &digest.range).is_none())
+                .map(|digest| digest.range.clone()),
+        );
+        diverging
+    }
+}
+
+/// Types of data which can be included in a snapshot.
+#[derive(Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Serialize, Deserialize)]
+pub enum SnapshotDataType {
+    /// Metadata of the chunks held by the section, e.g. their holders.
+    Blob,
+    /// Maps.
+    Map,
+    /// Sequences.
+    Sequence,
+    /// Wallets and their transfer history.
+    Transfers,
+    /// Node wallets and reward state.
+    Rewards,
+}
+
+/// Manifest of a snapshot of the metadata held by a section.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    /// Prefix name of the section the snapshot was taken of.
+    pub section: XorName,
+    /// The types of data included.
+    pub data_types: BTreeSet<SnapshotDataType>,
+    /// The time range of the history included.
+    pub range: Range<SystemTime>,
+    /// When the snapshot was taken.
+    pub taken_at: SystemTime,
+    /// Number of parts of the snapshot.
+    pub parts: u32,
+    /// Merkle root over the payloads of all the parts, which also identifies the snapshot.
+    pub root: [u8; 32],
+}
+
+/// A part of a snapshot, see `SnapshotManifest`.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SnapshotPart {
+    /// Merkle root of the snapshot this part belongs to.
+    pub root: [u8; 32],
+    /// Index of this part, starting at zero.
+    pub index: u32,
+    /// This part's payload.
+    #[serde(with = "serde_bytes")]
+    pub payload: Vec<u8>,
+    /// Proof that the payload is part of the snapshot.
+    pub proof: MerkleProof,
+}
+
+impl SnapshotManifest {
+    /// Splits the serialized snapshot into parts of at most `max_part_size` bytes,
+    /// returning the manifest along with the parts, to be streamed after it.
+    /// The part size can't exceed `MAX_QUERY_RESPONSE_PAGE_SIZE`.
+    pub fn split(
+        section: XorName,
+        data_types: BTreeSet<SnapshotDataType>,
+        range: Range<SystemTime>,
+        snapshot: &[u8],
+        max_part_size: usize,
+    ) -> crate::Result<(Self, Vec<SnapshotPart>)> {
+        if max_part_size == 0 || max_part_size > MAX_QUERY_RESPONSE_PAGE_SIZE {
+            return Err(crate::Error::Serialisation(format!(
+                "part size for a snapshot must be between 1 and {} bytes",
+                MAX_QUERY_RESPONSE_PAGE_SIZE
+            )));
+        }
+        let chunks: Vec<&[u8]> = snapshot.chunks(max_part_size).collect();
+        let (root, proofs) = MerkleProof::build(&chunks);
+        let manifest = Self {
+            section,
+            data_types,
+            range,
+            taken_at: SystemTime::now(),
+            parts: chunks.len() as u32,
+            root,
+        };
+        let parts = chunks
+            .into_iter()
+            .zip(proofs)
+            .enumerate()
+            .map(|(index, (payload, proof))| SnapshotPart {
+                root,
+                index: index as u32,
+                payload: payload.to_vec(),
+                proof,
+            })
+            .collect();
+
+        Ok((manifest, parts))
+    }
+
+    /// Returns whether the part belongs to the snapshot of this manifest.
+    pub fn verify(&self, part: &SnapshotPart) -> bool {
+        part.root == self.root
+            && part.index < self.parts
+            && part.proof.index == part.index
+            && part.proof.verify(&part.payload, &self.root)
+    }
+
+    /// Reassembles the serialized snapshot out of all its parts, which can be provided
+    /// in any order. It returns an error if a part is missing or doesn't belong to the snapshot.
+    pub fn assemble(&self, parts: Vec<SnapshotPart>) -> crate::Result<Vec<u8>> {
+        let mut ordered = BTreeMap::new();
+        for part in parts {
+            if !self.verify(&part) {
+                return Err(crate::Error::failed_to_parse(format!(
+                    "part {} not belonging to the snapshot",
+                    part.index
+                )));
+            }
+            let _ = ordered.insert(part.index, part.payload);
+        }
+        if ordered.len() != self.parts as usize {
+            return Err(crate::Error::failed_to_parse(format!(
+                "snapshot of which {} out of {} parts were provided",
+                ordered.len(),
+                self.parts
+            )));
+        }
+
+        Ok(ordered.into_values().flatten().collect())
+    }
 }
 
 /// Reward query that is sent between sections.
@@ -142,6 +683,18 @@ pub enum NodeRewardQuery {
         /// in the new section.
         new_node_id: XorName,
     },
+    /// Sent by a node to its section
+    /// to fetch the penalties applied to it.
+    GetPenaltyHistory {
+        /// The id of the node.
+        node_id: XorName,
+    },
+    /// Sent by a node to its section
+    /// to fetch the state of its rewards.
+    GetMyRewardState {
+        /// The id of the node.
+        node_id: XorName,
+    },
 }
 
 ///
@@ -162,6 +715,7 @@ pub enum NodeTransferQuery {
 }
 
 ///
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub enum NodeDataQuery {
     /// Acquire the chunk from current holders for replication.
@@ -176,6 +730,49 @@ pub enum NodeDataQuery {
         /// Current holders.
         current_holders: BTreeSet<XorName>,
     },
+    /// Get the current holders of the chunk, e.g. for Adult-to-Adult
+    /// replication pulls, without Elders proxying the chunk.
+    GetChunkHolders(BlobAddress),
+    /// Elder-to-Adult query, forwarding a client's read.
+    /// The response is a `NodeQueryResponse::ForwardedClientQuery`.
+    ForwardedClientQuery {
+        /// The client's query.
+        query: DataQuery,
+        /// Name of the client the query originates from.
+        origin: XorName,
+    },
+}
+
+/// The current holders of a chunk, as signed by the Elders of the section.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ChunkHolders {
+    /// Address of the chunk.
+    pub address: BlobAddress,
+    /// Names of the holders, along with their age.
+    pub holders: BTreeMap<XorName, u8>,
+    /// Key of the section the holders belong to.
+    pub section_key: BlsPublicKey,
+    /// Section signature over the address and holders.
+    pub signature: BlsSignature,
+}
+
+impl ChunkHolders {
+    /// Returns the bytes the Elders of the section are expected to sign.
+    pub fn signable_bytes(
+        address: &BlobAddress,
+        holders: &BTreeMap<XorName, u8>,
+    ) -> crate::Result<Vec<u8>> {
+        signing::signable_value(CHUNK_HOLDERS_DOMAIN, &(address, holders), "chunk holders")
+    }
+
+    /// Verifies the section signature over the holders against the contained section key.
+    pub fn verify(&self) -> bool {
+        signing::verify_section_signature(
+            &self.section_key,
+            &self.signature,
+            Self::signable_bytes(&self.address, &self.holders),
+        )
+    }
 }
 
 ///
@@ -188,6 +785,39 @@ pub enum NodeQueryResponse {
     Rewards(NodeRewardQueryResponse),
     ///
     Transfers(NodeTransferQueryResponse),
+    /// A page of a response too large to fit in a single message.
+    /// See `NodeQueryResponse::into_pages` and `NodeQueryResponse::from_pages`.
+    DataPage {
+        /// Index of this page, starting at zero.
+        part: u32,
+        /// Total number of pages.
+        of: u32,
+        /// This page's part of the serialized response.
+        #[serde(with = "serde_bytes")]
+        payload: Vec<u8>,
+        /// Merkle root over the payloads of all the pages, as signed by the section.
+        root: SignedRoot,
+        /// Proof that this page's payload is part of the pages the root was computed over,
+        /// so that a truncated or inconsistent set of pages can be detected.
+        proof: MerkleProof,
+    },
+    /// Responses to queries related to the running of a section.
+    System(NodeSystemQueryResponse),
+    /// The response to a client's query forwarded by an Elder, see `NodeDataQuery::ForwardedClientQuery`.
+    ForwardedClientQuery(QueryResponse),
+}
+
+/// Responses to queries related to the running of a section, see `NodeSystemQuery`.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub enum NodeSystemQueryResponse {
+    /// The manifest of the exported snapshot, which is followed by its parts.
+    ExportSnapshot(Result<SnapshotManifest>),
+    /// A part of an exported snapshot.
+    SnapshotPart(SnapshotPart),
+    /// The current key and Elders of the section.
+    GetSectionKnowledge(Result<SectionKnowledge>),
+    /// The digest of the metadata held by the Elder.
+    GetMetadataDigest(Result<MetadataDigest>),
 }
 
 ///
@@ -198,6 +828,12 @@ pub enum NodeRewardQueryResponse {
     /// together with the new node id,
     /// that followed with the original query.
     GetNodeWalletId(Result<(PublicKey, XorName)>),
+    /// Returns the penalties applied to the node,
+    /// oldest first.
+    GetPenaltyHistory(Result<Vec<Penalty>>),
+    /// Returns the state of the node's rewards,
+    /// as signed by the section.
+    GetMyRewardState(Result<RewardState>),
 }
 
 ///
@@ -228,6 +864,8 @@ pub enum NodeDataQueryResponse {
     GetChunk(Result<Blob>),
     /// Adult to Adult Get
     GetChunks(Result<Vec<Blob>>),
+    /// Current holders of a chunk.
+    GetChunkHolders(Result<ChunkHolders>),
 }
 
 ///
@@ -303,6 +941,10 @@ impl NodeCmd {
         match self {
             System(NodeSystemCmd::RegisterWallet { section, .. }) => Section(*section),
             System(NodeSystemCmd::StorageFull { section, .. }) => Section(*section),
+            System(NodeSystemCmd::SetAntiReplayWindow { section, .. }) => Section(*section),
+            System(NodeSystemCmd::AnnounceLeave { section, .. }) => Section(*section),
+            System(NodeSystemCmd::ImportSnapshot { section, .. }) => Section(*section),
+            System(NodeSystemCmd::ImportSnapshotPart { section, .. }) => Section(*section),
             System(NodeSystemCmd::ProposeGenesis { credit, .. }) => {
                 Section(credit.recipient().into())
             }
@@ -312,6 +954,7 @@ impl NodeCmd {
             Data(cmd) => match cmd {
                 ReplicateChunk { new_holder, .. } => Node(*new_holder),
                 Blob(_write) => Node(XorName::default()), // todo: fix this!
+                ForwardedClientCmd { cmd, .. } => Node(cmd.dst_address()),
             },
             Transfers(cmd) => match cmd {
                 ValidateSectionPayout(signed_debit) => Section(signed_debit.sender().into()),
@@ -326,6 +969,119 @@ impl NodeCmd {
     }
 }
 
+impl NodeQueryResponse {
+    /// Splits this response into `DataPage`s, each carrying at most `max_page_size` bytes
+    /// of the serialized response, so that they respect the message size limits.
+    /// The page size can't exceed `MAX_QUERY_RESPONSE_PAGE_SIZE`. The merkle root over the
+    /// pages is signed with `sign`, which is passed the bytes returned by
+    /// `SignedRoot::signable_bytes`, and is to return the signature of the section of
+    /// the given key over them, e.g. as aggregated out of the Elders' signature shares.
+    pub fn into_pages(
+        &self,
+        max_page_size: usize,
+        section_key: BlsPublicKey,
+        sign: impl FnOnce(Vec<u8>) -> BlsSignature,
+    ) -> crate::Result<Vec<Self>> {
+        if max_page_size == 0 || max_page_size > MAX_QUERY_RESPONSE_PAGE_SIZE {
+            return Err(crate::Error::Serialisation(format!(
+                "page size for a paged response must be between 1 and {} bytes",
+                MAX_QUERY_RESPONSE_PAGE_SIZE
+            )));
+        }
+        let bytes = rmp_serde::to_vec_named(self).map_err(|err| {
+            crate::Error::Serialisation(format!(
+                "could not serialize node query response with Msgpack: {}",
+                err
+            ))
+        })?;
+        let chunks: Vec<&[u8]> = bytes.chunks(max_page_size).collect();
+        let of = chunks.len() as u32;
+        let (root, proofs) = MerkleProof::build(&chunks);
+        let root = SignedRoot {
+            root,
+            leaves: of,
+            section_key,
+            signature: sign(SignedRoot::signable_bytes(&root, of)?),
+        };
+
+        Ok(chunks
+            .into_iter()
+            .zip(proofs)
+            .enumerate()
+            .map(|(part, (payload, proof))| Self::DataPage {
+                part: part as u32,
+                of,
+                payload: payload.to_vec(),
+                root: root.clone(),
+                proof,
+            })
+            .collect())
+    }
+
+    /// Reassembles a response out of all its `DataPage`s, which can be provided in any order.
+    /// All the pages are verified to be part of the same, complete, set of pages, the root
+    /// of which was signed by the section of the given key.
+    pub fn from_pages(pages: Vec<Self>, section_key: &BlsPublicKey) -> crate::Result<Self> {
+        let mut parts = BTreeMap::new();
+        let mut total = None;
+        let mut total_root = None;
+        for page in pages {
+            match page {
+                Self::DataPage {
+                    part,
+                    of,
+                    payload,
+                    root,
+                    proof,
+                } => {
+                    if payload.len() > MAX_QUERY_RESPONSE_PAGE_SIZE {
+                        return Err(crate::Error::MessageTooLarge {
+                            size: payload.len(),
+                            limit: MAX_QUERY_RESPONSE_PAGE_SIZE,
+                        });
+                    }
+                    if total_root.is_none() && (root.section_key != *section_key || !root.verify())
+                    {
+                        return Err(crate::Error::failed_to_parse(
+                            "pages of a node query response not signed by the section".to_string(),
+                        ));
+                    }
+                    if *total.get_or_insert(of) != of
+                        || root.leaves != of
+                        || *total_root.get_or_insert_with(|| root.clone()) != root
+                        || part >= of
+                        || proof.index != part
+                        || !proof.verify(&payload, &root.root)
+                    {
+                        return Err(crate::Error::failed_to_parse(
+                            "inconsistent pages of a node query response".to_string(),
+                        ));
+                    }
+                    let _ = parts.insert(part, payload);
+                }
+                _ => {
+                    return Err(crate::Error::failed_to_parse(
+                        "a page of a node query response".to_string(),
+                    ))
+                }
+            }
+        }
+        if total.unwrap_or_default() as usize != parts.len() || parts.is_empty() {
+            return Err(crate::Error::failed_to_parse(
+                "incomplete pages of a node query response".to_string(),
+            ));
+        }
+
+        let bytes: Vec<u8> = parts.into_values().flatten().collect();
+        rmp_serde::from_slice(&bytes).map_err(|err| {
+            crate::Error::failed_to_parse(format!(
+                "Node query response pages as Msgpack: {:?}",
+                err
+            ))
+        })
+    }
+}
+
 impl NodeEvent {
     /// Returns the address of the destination for `request`.
     pub fn dst_address(&self) -> Address {
@@ -335,6 +1091,71 @@ impl NodeEvent {
             ReplicationCompleted { chunk, .. } => Section(*chunk.name()),
             SectionPayoutValidated(event) => Section(event.sender().into()),
             SectionPayoutRegistered { from, .. } => Section((*from).into()),
+            PenaltyApplied { node, .. } => Node(*node),
+            ErrorBatch { section, .. } => Section(*section),
+            LeaveAcknowledged { name, .. } => Node(*name),
+            PaymentProcessed {
+                section_wallet_key, ..
+            } => Section((*section_wallet_key).into()),
+            Heartbeat { neighbour, .. } => Section(*neighbour),
+        }
+    }
+
+    /// Creates the heartbeat to send to the neighbour, with the key of the neighbour
+    /// currently known by the sending section.
+    pub fn heartbeat(
+        section: XorName,
+        current_section_key: BlsPublicKey,
+        neighbour: XorName,
+        known_neighbour_key: &BlsPublicKey,
+    ) -> Self {
+        Self::Heartbeat {
+            section,
+            neighbour,
+            current_section_key,
+            knowledge_hash: Self::knowledge_hash(known_neighbour_key),
+        }
+    }
+
+    /// Returns the hash of a known section key, as carried by `NodeEvent::Heartbeat`.
+    pub fn knowledge_hash(key: &BlsPublicKey) -> XorName {
+        let mut hasher = Sha3::v256();
+        let mut output = [0; 32];
+        hasher.update(&key.to_bytes());
+        hasher.finalize(&mut output);
+        XorName(output)
+    }
+
+    /// Returns the query to send back to the sender of a heartbeat if its current key
+    /// isn't the given one, known by the receiving section, or if none is known.
+    /// Returns `None` for other events.
+    pub fn heartbeat_sync_query(
+        &self,
+        known_sender_key: Option<&BlsPublicKey>,
+    ) -> Option<NodeQuery> {
+        match self {
+            Self::Heartbeat {
+                section,
+                current_section_key,
+                ..
+            } if known_sender_key != Some(current_section_key) => {
+                Some(NodeQuery::System(NodeSystemQuery::GetSectionKnowledge {
+                    section: *section,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns whether the sender of a heartbeat doesn't know the given current key of the
+    /// receiving section, in which case the receiver sends its own heartbeat back.
+    /// Returns false for other events.
+    pub fn heartbeat_is_stale(&self, current_section_key: &BlsPublicKey) -> bool {
+        match self {
+            Self::Heartbeat { knowledge_hash, .. } => {
+                *knowledge_hash != Self::knowledge_hash(current_section_key)
+            }
+            _ => false,
         }
     }
 }
@@ -352,6 +1173,8 @@ impl NodeQuery {
                 GetChunk {
                     current_holders, ..
                 } => Node(*current_holders.iter().next().unwrap_or(&XorName::random())),
+                GetChunkHolders(address) => Section(*address.name()),
+                ForwardedClientQuery { query, .. } => Node(query.dst_address()),
             },
             Transfers(transfer_query) => match transfer_query {
                 GetReplicaEvents(section_key) => Section((*section_key).into()),
@@ -359,6 +1182,11 @@ impl NodeQuery {
                 CatchUpWithSectionWallet(section_key) => Section((*section_key).into()),
             },
             Rewards(GetNodeWalletId { old_node_id, .. }) => Section(*old_node_id),
+            Rewards(GetPenaltyHistory { node_id }) => Section(*node_id),
+            Rewards(GetMyRewardState { node_id }) => Section(*node_id),
+            System(NodeSystemQuery::ExportSnapshot { section, .. }) => Section(*section),
+            System(NodeSystemQuery::GetSectionKnowledge { section }) => Section(*section),
+            System(NodeSystemQuery::GetMetadataDigest { section, .. }) => Section(*section),
         }
     }
 }
@@ -0,0 +1,132 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::{BlobRead, DataQuery, Query, QueryResponse};
+use crate::limits::{MAX_CHUNK_SIZE, MAX_OFFLOADED_RESPONSE_SIZE};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{Blob, BlobAddress, PublicBlob};
+use std::time::SystemTime;
+use threshold_crypto::{serde_impl::SerdeSecret, Ciphertext, SecretKey as BlsSecretKey};
+
+/// A query response too large to be sent inline, e.g. a full Map dump, which the Elders
+/// stored as temporary Blobs instead, see `QueryResponse::offload`. The serialized response
+/// is encrypted to a key generated for it, so that only the recipient of this
+/// `OffloadedResponse` can read it, and split into chunks of at most `MAX_CHUNK_SIZE` bytes.
+/// Each chunk is stored as a public Blob, which the client can read without being its owner,
+/// the encryption keeping it private all the same, and which the Elders drop once expired.
+/// The client fetches the Blobs with the `queries` and reads the response back with `open`.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct OffloadedResponse {
+    /// Addresses of the temporary Blobs holding the response, in order.
+    pub addresses: Vec<BlobAddress>,
+    /// Key to decrypt the content of the Blobs with.
+    pub decryption_key: SerdeSecret<BlsSecretKey>,
+    /// Time after which the Elders may delete the Blobs.
+    pub expires: SystemTime,
+}
+
+impl OffloadedResponse {
+    /// Seals the response to a new key, returning the `OffloadedResponse` to send to the
+    /// client, along with the Blobs to store, which are to be kept until `expires`.
+    /// Fails if the response is larger than `MAX_OFFLOADED_RESPONSE_SIZE`.
+    pub fn new(response: &QueryResponse, expires: SystemTime) -> crate::Result<(Self, Vec<Blob>)> {
+        Self::seal(&serialize(response)?, expires)
+    }
+
+    // Seals the serialized response, see `new`.
+    fn seal(bytes: &[u8], expires: SystemTime) -> crate::Result<(Self, Vec<Blob>)> {
+        if bytes.len() > MAX_OFFLOADED_RESPONSE_SIZE {
+            return Err(crate::Error::MessageTooLarge {
+                size: bytes.len(),
+                limit: MAX_OFFLOADED_RESPONSE_SIZE,
+            });
+        }
+        let decryption_key = BlsSecretKey::random();
+        let sealed =
+            rmp_serde::to_vec(&decryption_key.public_key().encrypt(bytes)).map_err(|err| {
+                crate::Error::Serialisation(format!(
+                    "could not serialize offloaded query response: {}",
+                    err
+                ))
+            })?;
+        let blobs: Vec<_> = sealed
+            .chunks(MAX_CHUNK_SIZE)
+            .map(|chunk| Blob::Public(PublicBlob::new(chunk.to_vec())))
+            .collect();
+        let offloaded = Self {
+            addresses: blobs.iter().map(|blob| *blob.address()).collect(),
+            decryption_key: SerdeSecret(decryption_key),
+            expires,
+        };
+        Ok((offloaded, blobs))
+    }
+
+    /// Returns the queries to fetch the Blobs holding the response with, in order.
+    pub fn queries(&self) -> Vec<Query> {
+        self.addresses
+            .iter()
+            .map(|address| Query::Data(DataQuery::Blob(BlobRead::Get(*address))))
+            .collect()
+    }
+
+    /// Returns the response held by the Blobs, which are expected to be the ones at
+    /// `addresses`, in the same order.
+    pub fn open(&self, blobs: &[Blob]) -> crate::Result<QueryResponse> {
+        let addresses: Vec<_> = blobs.iter().map(|blob| *blob.address()).collect();
+        if addresses != self.addresses {
+            return Err(crate::Error::failed_to_parse(format!(
+                "offloaded query response from Blobs {:?}, expected {:?}",
+                addresses, self.addresses
+            )));
+        }
+        let sealed: Vec<u8> = blobs
+            .iter()
+            .flat_map(|blob| blob.value().iter().copied())
+            .collect();
+        let sealed: Ciphertext = rmp_serde::from_slice(&sealed).map_err(|err| {
+            crate::Error::failed_to_parse(format!("offloaded query response: {}", err))
+        })?;
+        let bytes = self
+            .decryption_key
+            .decrypt(&sealed)
+            .ok_or_else(|| crate::Error::FailedToDecrypt("offloaded query response".to_string()))?;
+        rmp_serde::from_slice(&bytes).map_err(|err| {
+            crate::Error::failed_to_parse(format!("offloaded query response: {}", err))
+        })
+    }
+
+    /// Returns whether the Blobs may have been deleted already as of the given time.
+    pub fn is_expired_at(&self, now: SystemTime) -> bool {
+        self.expires <= now
+    }
+}
+
+impl QueryResponse {
+    /// Returns the response as is if it serializes to at most `max_size` bytes, or else an
+    /// `Offloaded` response to send instead, along with the Blobs to store, see
+    /// `OffloadedResponse`. Elders call it for the responses which may exceed the message
+    /// size limits, e.g. full Map dumps.
+    pub fn offload(self, max_size: usize, expires: SystemTime) -> crate::Result<(Self, Vec<Blob>)> {
+        let bytes = serialize(&self)?;
+        if bytes.len() <= max_size {
+            return Ok((self, vec![]));
+        }
+        let (offloaded, blobs) = OffloadedResponse::seal(&bytes, expires)?;
+        Ok((Self::Offloaded(offloaded), blobs))
+    }
+}
+
+fn serialize(response: &QueryResponse) -> crate::Result<Vec<u8>> {
+    rmp_serde::to_vec(response).map_err(|err| {
+        crate::Error::Serialisation(format!(
+            "could not serialize query response to offload: {}",
+            err
+        ))
+    })
+}
@@ -7,8 +7,15 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use super::{data::DataQuery, transfer::TransferQuery, AuthorisationKind, Error, QueryResponse};
+use super::{
+    data::{DataAddress, DataQuery},
+    transfer::TransferQuery,
+    AuthorisationKind, DataAuthKind, Error, MiscAuthKind, QueryResponse, TokenAuthKind,
+};
+use crate::MessageId;
 use serde::{Deserialize, Serialize};
+use sn_data_types::PublicKey;
+use std::{ops::Range, time::SystemTime};
 use xor_name::XorName;
 
 /// TODO: docs
@@ -19,6 +26,37 @@ pub enum Query {
     Data(DataQuery),
     ///
     Transfer(TransferQuery),
+    /// Get the registrations the section holds for the given client key,
+    /// e.g. to clean up stale registrations after a crash.
+    GetMyRegistrations(PublicKey),
+    /// Get the current quota of fee-free small mutations for the given owner key.
+    GetMutationQuota(PublicKey),
+    /// Get the status of a data command sent with an `effective_after` time.
+    GetScheduledCmdStatus {
+        /// Address of the data the command applies to.
+        address: DataAddress,
+        /// Id of the message which carried the command.
+        cmd_id: MessageId,
+    },
+    /// Get the receipts of the data stored by the owner within the time range,
+    /// see `StorageReceipt`.
+    GetStorageReceipts {
+        /// The owner of the data.
+        owner: PublicKey,
+        /// When the data was stored.
+        range: Range<SystemTime>,
+    },
+}
+
+/// A registration of a client connection, as stored by the section.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Registration {
+    /// Id of the socket the client registered from.
+    pub socket_id: XorName,
+    /// When the registration was made.
+    pub registered_at: SystemTime,
+    /// Bitmask of the event categories the client subscribed to.
+    pub subscription_mask: u64,
 }
 
 impl Query {
@@ -28,6 +66,11 @@ impl Query {
         match self {
             Data(q) => q.authorisation_kind(),
             Transfer(q) => q.authorisation_kind(),
+            GetMyRegistrations(_) => AuthorisationKind::Misc(MiscAuthKind::ReadRegistrations),
+            GetMutationQuota(_) => AuthorisationKind::Token(TokenAuthKind::ReadBalance),
+            GetScheduledCmdStatus { .. } | GetStorageReceipts { .. } => {
+                AuthorisationKind::Data(DataAuthKind::PrivateRead)
+            }
         }
     }
 
@@ -38,6 +81,10 @@ impl Query {
         match self {
             Data(q) => q.error(error),
             Transfer(q) => q.error(error),
+            GetMyRegistrations(_) => QueryResponse::GetMyRegistrations(Err(error)),
+            GetMutationQuota(_) => QueryResponse::GetMutationQuota(Err(error)),
+            GetScheduledCmdStatus { .. } => QueryResponse::GetScheduledCmdStatus(Err(error)),
+            GetStorageReceipts { .. } => QueryResponse::GetStorageReceipts(Err(error)),
         }
     }
 
@@ -47,6 +94,10 @@ impl Query {
         match self {
             Data(q) => q.dst_address(),
             Transfer(q) => q.dst_address(),
+            GetMyRegistrations(key)
+            | GetMutationQuota(key)
+            | GetStorageReceipts { owner: key, .. } => XorName::from(*key),
+            GetScheduledCmdStatus { address, .. } => *address.name(),
         }
     }
 }
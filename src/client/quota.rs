@@ -0,0 +1,185 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::Error;
+use crate::signing::{self, QUOTA_DOMAIN};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{PublicKey, Token};
+use std::{collections::BTreeMap, time::SystemTime};
+use threshold_crypto::{PublicKey as BlsPublicKey, Signature as BlsSignature};
+use xor_name::XorName;
+
+/// A quota of fee-free small mutations (e.g. Map or Sequence edits),
+/// issued by a section to a data owner.
+#[derive(Debug, Hash, Eq, PartialEq, PartialOrd, Clone, Serialize, Deserialize)]
+pub struct QuotaToken {
+    /// The owner the quota was issued to.
+    pub owner: PublicKey,
+    /// Number of fee-free mutations remaining in the quota.
+    pub remaining_ops: u64,
+    /// When the quota expires.
+    pub expires_at: SystemTime,
+    /// Key of the section which issued the quota.
+    pub section_key: BlsPublicKey,
+    /// Section signature over the owner, remaining ops and expiry.
+    pub signature: BlsSignature,
+}
+
+impl QuotaToken {
+    /// Returns the bytes the issuing section is expected to sign.
+    pub fn signable_bytes(
+        owner: &PublicKey,
+        remaining_ops: u64,
+        expires_at: &SystemTime,
+    ) -> crate::Result<Vec<u8>> {
+        let bytes = bincode::serialize(&(owner, remaining_ops, expires_at)).map_err(|err| {
+            crate::Error::Serialisation(format!("could not serialize quota token: {}", err))
+        })?;
+        Ok(signing::signable_bytes(QUOTA_DOMAIN, &bytes))
+    }
+
+    /// Verifies the section signature over the quota against the contained section key.
+    pub fn verify(&self) -> bool {
+        match Self::signable_bytes(&self.owner, self.remaining_ops, &self.expires_at) {
+            Ok(bytes) => self.section_key.verify(&self.signature, bytes),
+            Err(_) => false,
+        }
+    }
+}
+
+/// A bundle of prepaid operations, issued by a section after a payment,
+/// which can be attached to subsequent cmds instead of a payment per cmd.
+/// The bundle is identified by its `id`, by which the Elders track the operations spent
+/// out of it, see `PrepaidOpsLedger`, so that it can't pay for more than `ops_remaining`
+/// cmds, however many times it's attached.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PrepaidOps {
+    /// Id of the bundle, unique to its issuance.
+    pub id: XorName,
+    /// The owner the operations were prepaid by.
+    pub owner: PublicKey,
+    /// The amount paid for the bundle of operations.
+    pub token_bundle: Token,
+    /// Number of operations remaining in the bundle, as of its issuance.
+    pub ops_remaining: u64,
+    /// Key of the section which issued the bundle.
+    pub section_key: BlsPublicKey,
+    /// Section signature over the id, owner, amount paid and remaining operations.
+    pub signature: BlsSignature,
+}
+
+impl PrepaidOps {
+    /// Returns the bytes the issuing section is expected to sign.
+    pub fn signable_bytes(
+        id: &XorName,
+        owner: &PublicKey,
+        token_bundle: Token,
+        ops_remaining: u64,
+    ) -> crate::Result<Vec<u8>> {
+        let bytes =
+            bincode::serialize(&(id, owner, token_bundle, ops_remaining)).map_err(|err| {
+                crate::Error::Serialisation(format!("could not serialize prepaid ops: {}", err))
+            })?;
+        Ok(signing::signable_bytes(QUOTA_DOMAIN, &bytes))
+    }
+
+    /// Verifies the section signature over the bundle against the contained section key.
+    pub fn verify(&self) -> bool {
+        match Self::signable_bytes(&self.id, &self.owner, self.token_bundle, self.ops_remaining) {
+            Ok(bytes) => self.section_key.verify(&self.signature, bytes),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Tracks the operations spent out of the bundles of prepaid operations, by their id,
+/// for the Elders to reject the cmds paid for out of an exhausted bundle.
+/// The ledger is to be kept along with the section's other data, for as long as the
+/// bundles the section issued are valid.
+#[derive(Debug, Default)]
+pub struct PrepaidOpsLedger {
+    spent: BTreeMap<XorName, u64>,
+}
+
+impl PrepaidOpsLedger {
+    /// Creates a ledger with no operations spent.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spends an operation out of the bundle, returning the number of operations left in it.
+    /// Fails with `Error::InvalidSignature` if the bundle's signature doesn't verify, and with
+    /// `Error::PrepaidOpsExhausted` if its `ops_remaining` operations were all spent already.
+    pub fn spend(&mut self, prepaid: &PrepaidOps) -> Result<u64, Error> {
+        if !prepaid.verify() {
+            return Err(Error::InvalidSignature);
+        }
+        let spent = self.spent.entry(prepaid.id).or_default();
+        if *spent >= prepaid.ops_remaining {
+            return Err(Error::PrepaidOpsExhausted);
+        }
+        *spent += 1;
+        Ok(prepaid.ops_remaining - *spent)
+    }
+
+    /// Returns the number of operations left in the bundle.
+    pub fn remaining(&self, prepaid: &PrepaidOps) -> u64 {
+        let spent = self.spent.get(&prepaid.id).copied().unwrap_or_default();
+        prepaid.ops_remaining.saturating_sub(spent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use threshold_crypto::SecretKey;
+
+    #[test]
+    fn prepaid_ops_reuse() -> Result<()> {
+        let section_key = SecretKey::random();
+        let owner = PublicKey::Bls(SecretKey::random().public_key());
+        let token_bundle = Token::from_nano(1_000);
+        let issue = |id| -> crate::Result<PrepaidOps> {
+            Ok(PrepaidOps {
+                id,
+                owner,
+                token_bundle,
+                ops_remaining: 2,
+                section_key: section_key.public_key(),
+                signature: section_key.sign(PrepaidOps::signable_bytes(
+                    &id,
+                    &owner,
+                    token_bundle,
+                    2,
+                )?),
+            })
+        };
+        let mut ledger = PrepaidOpsLedger::new();
+        let prepaid = issue(XorName::random())?;
+        assert!(prepaid.verify());
+        assert_eq!(ledger.spend(&prepaid), Ok(1));
+        assert_eq!(ledger.spend(&prepaid), Ok(0));
+        // Attaching the bundle again, beyond its operations, is rejected.
+        assert_eq!(ledger.spend(&prepaid), Err(Error::PrepaidOpsExhausted));
+        assert_eq!(ledger.remaining(&prepaid), 0);
+
+        // The id is signed, so the bundle can't be passed off as a new one...
+        let forged = PrepaidOps {
+            id: XorName::random(),
+            ..prepaid.clone()
+        };
+        assert_eq!(ledger.spend(&forged), Err(Error::InvalidSignature));
+        // ...unlike a bundle actually issued again.
+        let reissued = issue(XorName::random())?;
+        assert_eq!(ledger.spend(&reissued), Ok(1));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::DataAddress;
+use crate::signing::{self, RECEIPT_DOMAIN};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{PublicKey, Token};
+use std::time::SystemTime;
+use threshold_crypto::{PublicKey as BlsPublicKey, Signature as BlsSignature};
+
+/// A receipt of the storage of data, issued by a section to the data owner,
+/// see `Query::GetStorageReceipts`. It holds no content, only what was stored and paid,
+/// so that the owner can prove it to third parties, which verify it with `verify`
+/// against a section key they trust.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct StorageReceipt {
+    /// The owner the data was stored by.
+    pub owner: PublicKey,
+    /// Address of the stored data.
+    pub address: DataAddress,
+    /// Size of the stored data, in bytes.
+    pub size: u64,
+    /// The amount paid for storing the data.
+    pub paid: Token,
+    /// When the data was stored.
+    pub stored_at: SystemTime,
+    /// Key of the section which issued the receipt.
+    pub section_key: BlsPublicKey,
+    /// Section signature over all the other fields.
+    pub signature: BlsSignature,
+}
+
+impl StorageReceipt {
+    /// Returns the bytes the issuing section is expected to sign.
+    pub fn signable_bytes(
+        owner: &PublicKey,
+        address: &DataAddress,
+        size: u64,
+        paid: Token,
+        stored_at: &SystemTime,
+    ) -> crate::Result<Vec<u8>> {
+        let bytes =
+            bincode::serialize(&(owner, address, size, paid, stored_at)).map_err(|err| {
+                crate::Error::Serialisation(format!("could not serialize storage receipt: {}", err))
+            })?;
+        Ok(signing::signable_bytes(RECEIPT_DOMAIN, &bytes))
+    }
+
+    /// Verifies the section signature over the receipt against the contained section key.
+    pub fn verify(&self) -> bool {
+        match Self::signable_bytes(
+            &self.owner,
+            &self.address,
+            self.size,
+            self.paid,
+            &self.stored_at,
+        ) {
+            Ok(bytes) => self.section_key.verify(&self.signature, bytes),
+            Err(_) => false,
+        }
+    }
+}
@@ -8,6 +8,7 @@
 // Software.
 
 use super::{AdultDuties, Duty, ElderDuties};
+use crate::signing::{self, CLIENT_MSG_DOMAIN, NODE_MSG_DOMAIN, SECTION_MSG_DOMAIN};
 use ed25519_dalek::PublicKey as Ed25519PublicKey;
 use ed25519_dalek::Signature as Ed25519Signature;
 use hex_fmt::HexFmt;
@@ -315,6 +316,8 @@ impl MsgSender {
     }
 
     /// Verifies a payload as sent by this sender.
+    /// The signature is expected to be over the payload within the signing domain
+    /// of the sender (see `signing::signable_bytes`).
     pub fn verify(&self, payload: &[u8]) -> bool {
         self.entity.try_verify(self.sig.clone(), payload)
     }
@@ -393,29 +396,33 @@ impl Entity {
         }
     }
 
-    ///
+    /// Verifies the signature over the data, within the signing domain
+    /// corresponding to the entity and the kind of signature.
     pub fn try_verify(&self, sig: Option<EntitySignature>, data: &[u8]) -> bool {
         use Entity::*;
+        let client_bytes = || signing::signable_bytes(CLIENT_MSG_DOMAIN, data);
+        let node_bytes = || signing::signable_bytes(NODE_MSG_DOMAIN, data);
+        let section_bytes = || signing::signable_bytes(SECTION_MSG_DOMAIN, data);
         match self {
             Client(key) => {
                 if let Some(EntitySignature::Client(sig)) = sig {
-                    key.verify(&sig, data).is_ok()
+                    key.verify(&sig, client_bytes()).is_ok()
                 } else {
                     false
                 }
             }
             AnyNode(key, ..) | AdultNode(key, ..) => {
                 if let Some(EntitySignature::Node(sig)) = sig {
-                    key.verify(data, &sig).is_ok()
+                    key.verify(&node_bytes(), &sig).is_ok()
                 } else {
                     false
                 }
             }
             ElderNode(key, ..) => {
                 if let Some(EntitySignature::Elder(sig)) = sig {
-                    key.bls_key.verify(&sig, data)
+                    key.bls_key.verify(&sig, section_bytes())
                 } else if let Some(EntitySignature::Node(sig)) = sig {
-                    key.node_id.verify(data, &sig).is_ok()
+                    key.node_id.verify(&node_bytes(), &sig).is_ok()
                 } else {
                     false
                 }
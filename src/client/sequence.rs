@@ -7,16 +7,47 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use super::{AuthorisationKind, CmdError, DataAuthKind, Error, QueryResponse};
+use super::{
+    AuthorisationKind, CmdError, DataAddress, DataAuthKind, DataOpKind, Error, QueryResponse,
+    QuotaToken,
+};
+use hex_fmt::HexFmt;
 use serde::{Deserialize, Serialize};
 use sn_data_types::{
     PublicKey, Sequence, SequenceAddress as Address, SequenceDataWriteOp, SequenceEntry as Entry,
-    SequenceIndex as Index, SequencePolicyWriteOp, SequencePrivatePolicy as PrivatePolicy,
-    SequencePublicPolicy as PublicPolicy, SequenceUser as User,
+    SequenceIndex as Index, SequencePolicy as Policy, SequencePolicyWriteOp,
+    SequencePrivatePermissions as PrivatePermissions, SequencePrivatePolicy as PrivatePolicy,
+    SequencePublicPermissions as PublicPermissions, SequencePublicPolicy as PublicPolicy,
+    SequenceUser as User,
 };
-use std::fmt;
+use std::{collections::BTreeSet, fmt};
+use tiny_keccak::{Hasher, Sha3};
 use xor_name::XorName;
 
+/// Hash of an entry of a Sequence, along with its index, which identifies the entry
+/// the client last received, see `SequenceRead::SubscribeFrom`.
+#[derive(Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct EntryHash(pub [u8; 32]);
+
+impl EntryHash {
+    /// Returns the hash of the entry at the given index. The index is hashed along with the
+    /// entry, so that entries of the same content appended at different times are told apart.
+    pub fn of(index: u64, entry: &[u8]) -> Self {
+        let mut hasher = Sha3::v256();
+        let mut output = [0; 32];
+        hasher.update(&index.to_be_bytes());
+        hasher.update(entry);
+        hasher.finalize(&mut output);
+        Self(output)
+    }
+}
+
+impl fmt::Debug for EntryHash {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "EntryHash({:<8})", HexFmt(&self.0))
+    }
+}
+
 /// TODO: docs
 #[derive(Hash, Eq, PartialEq, PartialOrd, Clone, Serialize, Deserialize)]
 pub enum SequenceRead {
@@ -53,6 +84,21 @@ pub enum SequenceRead {
     },
     /// Get current owner.
     GetOwner(Address),
+    /// Subscribe to the entries appended to the Sequence, after the last one the client
+    /// received. The entries appended since then are replayed in the response, and the ones
+    /// appended afterwards are pushed as `Event::SequenceEntriesAppended`, so that a
+    /// reconnecting client receives every entry at least once.
+    SubscribeFrom {
+        /// Sequence address.
+        address: Address,
+        /// Hash of the last entry the client received, see `EntryHash::of`, or `None` to
+        /// replay all the entries. If no entry of the Sequence matches it, e.g. as the
+        /// Sequence was deleted and created again since, all the entries are replayed.
+        since: Option<EntryHash>,
+    },
+    /// Get the number of entries of the Sequence, e.g. to display the total when
+    /// paginating with `GetRange`, without fetching the entries.
+    GetLength(Address),
 }
 
 /// TODO: docs
@@ -61,8 +107,24 @@ pub enum SequenceRead {
 pub enum SequenceWrite {
     /// Create a new Sequence on the network.
     New(Sequence),
+    /// Create a new Sequence on the network, along with its initial policy and entries,
+    /// which are applied in the same operation, so the Sequence never exists without them.
+    NewWithEntries {
+        /// The new Sequence.
+        data: Sequence,
+        /// The initial policy of the Sequence.
+        policy: SequenceInitialPolicy,
+        /// The initial entries, in the order they are to be applied.
+        entries: Vec<SequenceDataWriteOp<Entry>>,
+    },
     /// Edit the Sequence (insert/remove entry).
-    Edit(SequenceDataWriteOp<Entry>),
+    Edit {
+        /// The write operation.
+        op: SequenceDataWriteOp<Entry>,
+        /// Claim on the owner's quota of fee-free mutations, if any.
+        #[serde(default)]
+        quota_claim: Option<QuotaToken>,
+    },
     /// Delete a private Sequence.
     ///
     /// This operation MUST return an error if applied to public Sequence. Only the current
@@ -74,19 +136,78 @@ pub enum SequenceWrite {
     SetPrivatePolicy(SequencePolicyWriteOp<PrivatePolicy>),
 }
 
+/// The initial policy of a Sequence created with `SequenceWrite::NewWithEntries`.
+#[derive(Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub enum SequenceInitialPolicy {
+    /// Policy of a public Sequence.
+    Public(SequencePolicyWriteOp<PublicPolicy>),
+    /// Policy of a private Sequence.
+    Private(SequencePolicyWriteOp<PrivatePolicy>),
+    /// Policy built out of a template, for the owner of the Sequence.
+    Template(PolicyTemplate),
+}
+
+/// Template of the most common policies, which is much more compact than the
+/// full policy it stands for, see `PolicyTemplate::policy`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum PolicyTemplate {
+    /// Private data, which only the owner can read and write.
+    Private,
+    /// Public data, which anyone can read but only the owner can append to.
+    PublicAppendOnly,
+    /// Private data, which the owner and the members can read and append to.
+    /// Only the owner can change the policy.
+    TeamReadWrite {
+        /// The members of the team, besides the owner.
+        members: BTreeSet<PublicKey>,
+    },
+}
+
+impl PolicyTemplate {
+    /// Returns the full policy this template stands for, for the given owner.
+    pub fn policy(&self, owner: PublicKey) -> Policy {
+        match self {
+            Self::Private => Policy::Private(PrivatePolicy {
+                owner,
+                permissions: vec![(owner, PrivatePermissions::new(true, true, true))]
+                    .into_iter()
+                    .collect(),
+            }),
+            Self::PublicAppendOnly => Policy::Public(PublicPolicy {
+                owner,
+                permissions: vec![(User::Key(owner), PublicPermissions::new(true, true))]
+                    .into_iter()
+                    .collect(),
+            }),
+            Self::TeamReadWrite { members } => Policy::Private(PrivatePolicy {
+                owner,
+                permissions: members
+                    .iter()
+                    .map(|member| (*member, PrivatePermissions::new(true, true, false)))
+                    .chain(Some((owner, PrivatePermissions::new(true, true, true))))
+                    .collect(),
+            }),
+        }
+    }
+}
+
 impl SequenceRead {
     /// Creates a Response containing an error, with the Response variant corresponding to the
     /// Request variant.
     pub fn error(&self, error: Error) -> QueryResponse {
         use SequenceRead::*;
         match *self {
-            Get(_) => QueryResponse::GetSequence(Err(error)),
-            GetRange { .. } => QueryResponse::GetSequenceRange(Err(error)),
-            GetLastEntry(_) => QueryResponse::GetSequenceLastEntry(Err(error)),
-            GetPublicPolicy(_) => QueryResponse::GetSequencePublicPolicy(Err(error)),
-            GetPrivatePolicy(_) => QueryResponse::GetSequencePrivatePolicy(Err(error)),
-            GetUserPermissions { .. } => QueryResponse::GetSequenceUserPermissions(Err(error)),
-            GetOwner(_) => QueryResponse::GetSequenceOwner(Err(error)),
+            Get(_) => QueryResponse::GetSequence(Err(error.into())),
+            GetRange { .. } => QueryResponse::GetSequenceRange(Err(error.into())),
+            GetLastEntry(_) => QueryResponse::GetSequenceLastEntry(Err(error.into())),
+            GetPublicPolicy(_) => QueryResponse::GetSequencePublicPolicy(Err(error.into())),
+            GetPrivatePolicy(_) => QueryResponse::GetSequencePrivatePolicy(Err(error.into())),
+            GetUserPermissions { .. } => {
+                QueryResponse::GetSequenceUserPermissions(Err(error.into()))
+            }
+            GetOwner(_) => QueryResponse::GetSequenceOwner(Err(error.into())),
+            SubscribeFrom { .. } => QueryResponse::SubscribeToSequence(Err(error.into())),
+            GetLength(_) => QueryResponse::GetSequenceLength(Err(error.into())),
         }
     }
 
@@ -100,7 +221,9 @@ impl SequenceRead {
             | GetPublicPolicy(address)
             | GetPrivatePolicy(address)
             | GetUserPermissions { address, .. }
-            | GetOwner(address) => {
+            | GetOwner(address)
+            | SubscribeFrom { address, .. }
+            | GetLength(address) => {
                 if address.is_pub() {
                     AuthorisationKind::Data(DataAuthKind::PublicRead)
                 } else {
@@ -120,7 +243,9 @@ impl SequenceRead {
             | GetPublicPolicy(ref address)
             | GetPrivatePolicy(ref address)
             | GetUserPermissions { ref address, .. }
-            | GetOwner(ref address) => *address.name(),
+            | GetOwner(ref address)
+            | SubscribeFrom { ref address, .. }
+            | GetLength(ref address) => *address.name(),
         }
     }
 }
@@ -139,16 +264,40 @@ impl fmt::Debug for SequenceRead {
                 GetPrivatePolicy { .. } => "GetSequencePrivatePolicy",
                 GetUserPermissions { .. } => "GetUserPermissions",
                 GetOwner { .. } => "GetOwner",
+                SubscribeFrom { .. } => "SubscribeToSequence",
+                GetLength(_) => "GetSequenceLength",
             }
         )
     }
 }
 
 impl SequenceWrite {
-    /// Creates a Response containing an error, with the Response variant corresponding to the
-    /// Request variant.
+    /// Creates a Response containing an error, attributed to the address of the Sequence written.
     pub fn error(&self, error: Error) -> CmdError {
-        CmdError::Data(error)
+        use SequenceWrite::*;
+        let address = match self {
+            New(ref data) | NewWithEntries { ref data, .. } => *data.address(),
+            Delete(ref address) => *address,
+            SetPublicPolicy(ref op) => op.address,
+            SetPrivatePolicy(ref op) => op.address,
+            Edit { ref op, .. } => op.address,
+        };
+        CmdError::Data {
+            address: DataAddress::Sequence(address),
+            op: self.op(),
+            error: error.into(),
+        }
+    }
+
+    /// Returns the kind of operation of the request.
+    pub fn op(&self) -> DataOpKind {
+        use SequenceWrite::*;
+        match self {
+            New(_) | NewWithEntries { .. } => DataOpKind::New,
+            Edit { .. } => DataOpKind::Edit,
+            Delete(_) => DataOpKind::Delete,
+            SetPublicPolicy(_) | SetPrivatePolicy(_) => DataOpKind::SetPermissions,
+        }
     }
 
     /// Returns the access categorisation of the request.
@@ -160,24 +309,36 @@ impl SequenceWrite {
     pub fn dst_address(&self) -> XorName {
         use SequenceWrite::*;
         match self {
-            New(ref data) => *data.name(),
+            New(ref data) | NewWithEntries { ref data, .. } => *data.name(),
             Delete(ref address) => *address.name(),
             SetPublicPolicy(ref op) => *op.address.name(),
             SetPrivatePolicy(ref op) => *op.address.name(),
             // SetOwner(ref op) => *op.address.name(),
-            Edit(ref op) => *op.address.name(),
+            Edit { ref op, .. } => *op.address.name(),
         }
     }
 
     /// Owner of the SequenceWrite
     pub fn owner(&self) -> Option<PublicKey> {
         match self {
-            Self::New(data) => Some(data.owner()),
+            Self::New(data) | Self::NewWithEntries { data, .. } => Some(data.owner()),
             _ => None,
         }
     }
 }
 
+impl fmt::Debug for SequenceInitialPolicy {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Public(_) => write!(formatter, "SequenceInitialPolicy::Public"),
+            Self::Private(_) => write!(formatter, "SequenceInitialPolicy::Private"),
+            Self::Template(template) => {
+                write!(formatter, "SequenceInitialPolicy::Template({:?})", template)
+            }
+        }
+    }
+}
+
 impl fmt::Debug for SequenceWrite {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         use SequenceWrite::*;
@@ -186,12 +347,51 @@ impl fmt::Debug for SequenceWrite {
             "Request::{}",
             match *self {
                 New(_) => "NewSequence",
+                NewWithEntries { .. } => "NewSequenceWithEntries",
                 Delete(_) => "DeleteSequence",
                 SetPublicPolicy(_) => "SetPublicPolicy",
                 SetPrivatePolicy(_) => "SetPrivatePolicy",
                 // SetOwner(_) => "SetOwner",
-                Edit(_) => "EditSequence",
+                Edit { .. } => "EditSequence",
             }
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Result};
+    use sn_data_types::Keypair;
+
+    #[test]
+    fn policy_templates() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let owner = Keypair::new_ed25519(&mut rng).public_key();
+        let member = Keypair::new_ed25519(&mut rng).public_key();
+
+        let policy = match PolicyTemplate::PublicAppendOnly.policy(owner) {
+            Policy::Public(policy) => policy,
+            Policy::Private(_) => return Err(anyhow!("Expected a public policy")),
+        };
+        assert_eq!(policy.owner, owner);
+        assert!(!policy.permissions.contains_key(&User::Anyone));
+
+        let members = vec![member].into_iter().collect();
+        let policy = match (PolicyTemplate::TeamReadWrite { members }).policy(owner) {
+            Policy::Private(policy) => policy,
+            Policy::Public(_) => return Err(anyhow!("Expected a private policy")),
+        };
+        assert_eq!(policy.owner, owner);
+        assert_eq!(
+            policy.permissions.get(&member),
+            Some(&PrivatePermissions::new(true, true, false))
+        );
+        assert_eq!(
+            policy.permissions.get(&owner),
+            Some(&PrivatePermissions::new(true, true, true))
+        );
+
+        Ok(())
+    }
+}
@@ -10,11 +10,13 @@
 use super::{
     AuthorisationKind, CmdError, Error, MiscAuthKind, QueryResponse, TokenAuthKind, TransferError,
 };
+use crate::signing::{self, HISTORICAL_BALANCE_DOMAIN};
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "simulated-payouts")]
 use sn_data_types::Transfer;
-use sn_data_types::{PublicKey, SignedTransfer, TransferAgreementProof};
+use sn_data_types::{PublicKey, SignedTransfer, Token, TransferAgreementProof};
 use std::fmt;
+use threshold_crypto::{PublicKey as BlsPublicKey, Signature as BlsSignature};
 use xor_name::XorName;
 
 /// Token cmd that is sent to network.
@@ -25,7 +27,14 @@ pub enum TransferCmd {
     /// Cmd to simulate a farming payout
     SimulatePayout(Transfer),
     /// The cmd to validate a transfer.
-    ValidateTransfer(SignedTransfer),
+    ValidateTransfer {
+        /// The transfer to validate.
+        signed_transfer: SignedTransfer,
+        /// When set, the replicas respond with the result of the validation without
+        /// recording the debit, so that spendability and fees can be pre-checked.
+        #[serde(default)]
+        dry_run: bool,
+    },
     /// The cmd to register the consensused transfer.
     RegisterTransfer(TransferAgreementProof),
 }
@@ -52,6 +61,44 @@ pub enum TransferQuery {
         ///
         bytes: u64,
     },
+    /// Get key balance as of a prior replica era,
+    /// i.e. when the replicas were signing with the given section key.
+    GetBalanceAt {
+        /// The balance key.
+        key: PublicKey,
+        /// The section key of the era.
+        section_key: BlsPublicKey,
+    },
+}
+
+/// The balance of a key as of a prior replica era,
+/// with the proof of that era's replicas.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct HistoricalBalance {
+    /// The balance key.
+    pub key: PublicKey,
+    /// The balance as of the era.
+    pub balance: Token,
+    /// The section key of the era.
+    pub section_key: BlsPublicKey,
+    /// Signature of the era's replicas over the key and balance.
+    pub proof: BlsSignature,
+}
+
+impl HistoricalBalance {
+    /// Returns the bytes the era's replicas are expected to sign.
+    pub fn signable_bytes(key: &PublicKey, balance: Token) -> crate::Result<Vec<u8>> {
+        signing::signable_value(HISTORICAL_BALANCE_DOMAIN, &(key, balance), "balance")
+    }
+
+    /// Verifies the era proof against the contained section key.
+    pub fn verify(&self) -> bool {
+        signing::verify_section_signature(
+            &self.section_key,
+            &self.proof,
+            Self::signable_bytes(&self.key, self.balance),
+        )
+    }
 }
 
 impl TransferCmd {
@@ -62,7 +109,7 @@ impl TransferCmd {
         use TransferCmd::*;
         use TransferError::*;
         match *self {
-            ValidateTransfer(_) => Transfer(TransferValidation(error)),
+            ValidateTransfer { .. } => Transfer(TransferValidation(error)),
             RegisterTransfer(_) => Transfer(TransferRegistration(error)),
             #[cfg(feature = "simulated-payouts")]
             SimulatePayout(_) => Transfer(TransferRegistration(error)),
@@ -74,7 +121,7 @@ impl TransferCmd {
         use TransferCmd::*;
         match self {
             RegisterTransfer(_) => AuthorisationKind::None, // the proof has the authority within it
-            ValidateTransfer(_) => AuthorisationKind::Misc(MiscAuthKind::WriteAndTransfer),
+            ValidateTransfer { .. } => AuthorisationKind::Misc(MiscAuthKind::WriteAndTransfer),
             #[cfg(feature = "simulated-payouts")]
             SimulatePayout(_) => AuthorisationKind::None,
         }
@@ -85,7 +132,10 @@ impl TransferCmd {
         use TransferCmd::*;
         match self {
             RegisterTransfer(ref proof) => XorName::from(proof.sender()), // this is handled where the debit is made
-            ValidateTransfer(ref signed_transfer) => XorName::from(signed_transfer.sender()), // this is handled where the debit is made
+            ValidateTransfer {
+                ref signed_transfer,
+                ..
+            } => XorName::from(signed_transfer.sender()), // this is handled where the debit is made
             #[cfg(feature = "simulated-payouts")]
             SimulatePayout(ref transfer) => XorName::from(transfer.debit().sender()), // this is handled where the debit is made
         }
@@ -100,7 +150,8 @@ impl fmt::Debug for TransferCmd {
             "TransferCmd::{}",
             match *self {
                 RegisterTransfer { .. } => "RegisterTransfer",
-                ValidateTransfer { .. } => "ValidateTransfer",
+                ValidateTransfer { dry_run: false, .. } => "ValidateTransfer",
+                ValidateTransfer { dry_run: true, .. } => "ValidateTransfer (dry run)",
                 #[cfg(feature = "simulated-payouts")]
                 SimulatePayout { .. } => "SimulatePayout",
             }
@@ -129,6 +180,7 @@ impl TransferQuery {
             GetBalance(_) => QueryResponse::GetBalance(Err(error)),
             GetHistory { .. } => QueryResponse::GetHistory(Err(error)),
             GetStoreCost { .. } => QueryResponse::GetStoreCost(Err(error)),
+            GetBalanceAt { .. } => QueryResponse::GetBalanceAt(Err(error)),
         }
     }
 
@@ -140,6 +192,7 @@ impl TransferQuery {
             GetReplicaKeys(_) => AuthorisationKind::None, // current replica keys
             GetHistory { .. } => AuthorisationKind::Token(TokenAuthKind::ReadHistory), // history of incoming transfers
             GetStoreCost { .. } => AuthorisationKind::None,                            // store cost
+            GetBalanceAt { .. } => AuthorisationKind::Token(TokenAuthKind::ReadBalance), // prior state
         }
     }
 
@@ -150,7 +203,8 @@ impl TransferQuery {
             GetBalance(at)
             | GetReplicaKeys(at)
             | GetHistory { at, .. }
-            | GetStoreCost { requester: at, .. } => XorName::from(*at),
+            | GetStoreCost { requester: at, .. }
+            | GetBalanceAt { key: at, .. } => XorName::from(*at),
         }
     }
 }
@@ -166,6 +220,7 @@ impl fmt::Debug for TransferQuery {
                 GetReplicaKeys(_) => "GetReplicaKeys",
                 GetHistory { .. } => "GetHistory",
                 GetStoreCost { .. } => "GetStoreCost",
+                GetBalanceAt { .. } => "GetBalanceAt",
             }
         )
     }
@@ -0,0 +1,590 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Golden corpus of serialized messages, to check wire-format compatibility.
+//!
+//! The corpus holds a serialized sample of each kind of message, of each variant of the
+//! top-level message enums, and of each optional field flagged in the wire header, as produced
+//! by the release which introduced the current version of the protocol. Downstream crates can call `verify_corpus` in their own tests to assert that
+//! the release they depend on still reads, and writes, these samples exactly the same way.
+
+use crate::{Error, MessageType, Result, WireMsg};
+use bytes::Bytes;
+
+/// Version of the messaging protocol the samples of the corpus were serialized with.
+pub const CORPUS_VERSION: u16 = 7;
+
+/// A serialized message of the corpus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    /// Name of the sample, naming the kind of message it holds.
+    pub name: &'static str,
+    /// Version of the messaging protocol the sample was serialized with.
+    pub version: u16,
+    /// The serialized message, as sent over the wire.
+    pub bytes: &'static [u8],
+}
+
+macro_rules! sample {
+    ($name:literal) => {
+        Sample {
+            name: $name,
+            version: CORPUS_VERSION,
+            bytes: include_bytes!(concat!("v7/", $name, ".bin")),
+        }
+    };
+}
+
+/// The samples of the corpus, for the kinds of messages enabled by the features of this crate.
+pub const CORPUS: &[Sample] = &[
+    sample!("ping"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_section_request"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_section_request_if_changed"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_section_response_success"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_section_response_redirect"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_section_response_unchanged"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_section_stats"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_section_stats_response"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_section_capabilities"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_section_capabilities_response"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_join_status"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_join_status_response"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_key_chain"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_key_chain_response"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_relay_stats"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_get_relay_stats_response"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_signed"),
+    #[cfg(feature = "infrastructure")]
+    sample!("infrastructure_signed_response"),
+    #[cfg(feature = "client-msgs")]
+    sample!("client_cmd"),
+    #[cfg(feature = "client-msgs")]
+    sample!("client_query"),
+    #[cfg(feature = "client-msgs")]
+    sample!("client_event"),
+    #[cfg(feature = "client-msgs")]
+    sample!("client_query_response"),
+    #[cfg(feature = "client-msgs")]
+    sample!("client_cmd_error"),
+    #[cfg(feature = "client-msgs")]
+    sample!("client_node_cmd"),
+    #[cfg(feature = "client-msgs")]
+    sample!("client_node_cmd_error"),
+    #[cfg(feature = "client-msgs")]
+    sample!("client_node_event"),
+    #[cfg(feature = "client-msgs")]
+    sample!("client_node_query"),
+    #[cfg(feature = "client-msgs")]
+    sample!("client_node_query_response"),
+    #[cfg(feature = "client-msgs")]
+    sample!("client_dead_letter"),
+    #[cfg(feature = "client-msgs")]
+    sample!("client_processing_error"),
+    #[cfg(feature = "node-msgs")]
+    sample!("node_message"),
+    sample!("layered"),
+    sample!("multipart_part"),
+    sample!("batch"),
+    sample!("flow_credit"),
+    #[cfg(feature = "infrastructure")]
+    sample!("signed"),
+    #[cfg(feature = "infrastructure")]
+    sample!("encrypted"),
+    sample!("expires"),
+    sample!("hops"),
+    sample!("flow_id"),
+];
+
+impl Sample {
+    /// Verifies that the sample is deserialized, and serialized back, to the very same bytes.
+    /// The samples flagging optional fields of the wire header are serialized back from their
+    /// `WireMsg`, as relayed, since the message alone doesn't carry these fields.
+    pub fn verify(&self) -> Result<()> {
+        let bytes = Bytes::from_static(self.bytes);
+        let header = WireMsg::peek_header(&bytes).map_err(|err| self.error(err))?;
+        if header.version != self.version {
+            return Err(Error::UnsupportedVersion(header.version));
+        }
+        let serialized = if header.encrypted
+            || header.signed
+            || header.expires_at.is_some()
+            || header.hops > 0
+            || header.flow_id.is_some()
+        {
+            let wire_msg = WireMsg::deserialize(bytes.clone()).map_err(|err| self.error(err))?;
+            if !header.encrypted {
+                let _ = wire_msg.to_message().map_err(|err| self.error(err))?;
+            }
+            wire_msg.serialize()?
+        } else {
+            self.deserialize()
+                .map_err(|err| self.error(err))?
+                .serialize()?
+        };
+        if serialized != bytes {
+            return Err(Error::Serialisation(format!(
+                "corpus sample '{}' is not serialized back to the same bytes",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Deserializes the message of the sample, which fails if its payload is encrypted.
+    pub fn deserialize(&self) -> Result<MessageType> {
+        WireMsg::deserialize(Bytes::from_static(self.bytes))?.to_message()
+    }
+
+    fn error(&self, err: Error) -> Error {
+        Error::failed_to_parse(format!("corpus sample '{}': {}", self.name, err))
+    }
+}
+
+/// Verifies every sample of the corpus, see `Sample::verify`.
+pub fn verify_corpus() -> Result<()> {
+    CORPUS.iter().try_for_each(Sample::verify)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn corpus() -> Result<()> {
+        verify_corpus()?;
+
+        let names: BTreeSet<_> = CORPUS.iter().map(|sample| sample.name).collect();
+        assert_eq!(names.len(), CORPUS.len());
+
+        Ok(())
+    }
+
+    // Writes the samples of the corpus, to be run (with `--ignored`) only
+    // when the version of the protocol is bumped, along with `CORPUS_VERSION`.
+    #[test]
+    #[ignore]
+    #[cfg(all(
+        feature = "client-msgs",
+        feature = "node-msgs",
+        feature = "infrastructure"
+    ))]
+    fn generate_corpus() -> Result<()> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/compat")
+            .join(format!("v{}", CORPUS_VERSION));
+        std::fs::create_dir_all(&dir)?;
+        for (name, msg) in generate::samples()? {
+            std::fs::write(dir.join(format!("{}.bin", name)), msg.serialize()?)?;
+        }
+        for (name, bytes) in generate::flagged_samples()? {
+            std::fs::write(dir.join(format!("{}.bin", name)), bytes)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(all(
+        feature = "client-msgs",
+        feature = "node-msgs",
+        feature = "infrastructure"
+    ))]
+    mod generate {
+        use crate::{
+            client::{
+                Address, BlobRead, Cmd, CmdError, DataAddress, DataError, DataOpKind, DataQuery,
+                DeadLetterReason, Error, Event, Message, NodeCmd, NodeCmdError, NodeDataError,
+                NodeEvent, NodeQuery, NodeQueryResponse, NodeRewardQuery, NodeRewardQueryResponse,
+                NodeSystemCmd, PenaltyReason, ProcessingError, ProcessingReason, Query,
+                QueryResponse,
+            },
+            flow::FlowCredit,
+            infrastructure::{
+                GetKeyChainResponse, GetSectionResponse, JoinStatus, Query as InfraQuery,
+                RelayStats, SectionCapabilities, SectionStats, SignedSectionStats,
+            },
+            layered::LayeredDst,
+            multipart::Part,
+            node::NodeMessage,
+            FlowId, MessageId, MessageType, WireMsg,
+        };
+        use anyhow::Result;
+        use bytes::Bytes;
+        use sn_data_types::{BlobAddress, Keypair, Token};
+        use std::{
+            collections::BTreeMap,
+            time::{Duration, UNIX_EPOCH},
+        };
+        use threshold_crypto::SecretKey;
+        use xor_name::{Prefix, XorName};
+
+        pub(super) fn samples() -> Result<Vec<(&'static str, MessageType)>> {
+            let name = XorName::random();
+            let section_key = SecretKey::random();
+            let next_section_key = SecretKey::random();
+            let keypair = Keypair::new_ed25519(&mut rand::thread_rng());
+            let stats = SectionStats {
+                prefix: Prefix::default(),
+                elder_count: 7,
+                adult_count: 42,
+                stored_bytes: 1024,
+                churn_count: 3,
+                churn_window: Duration::from_secs(3600),
+            };
+            let infrastructure = vec![
+                (
+                    "infrastructure_get_section_request",
+                    InfraQuery::GetSectionRequest(name),
+                ),
+                (
+                    "infrastructure_get_section_request_if_changed",
+                    InfraQuery::GetSectionRequestIfChanged {
+                        name,
+                        etag: [7; 32],
+                    },
+                ),
+                (
+                    "infrastructure_get_section_response_success",
+                    InfraQuery::GetSectionResponse(GetSectionResponse::Success {
+                        prefix: Prefix::default(),
+                        key: section_key.public_key(),
+                        elders: vec![(name, ([127, 0, 0, 1], 12000).into())]
+                            .into_iter()
+                            .collect::<BTreeMap<_, _>>(),
+                        endpoints: BTreeMap::new(),
+                    }),
+                ),
+                (
+                    "infrastructure_get_section_response_redirect",
+                    InfraQuery::GetSectionResponse(GetSectionResponse::Redirect(vec![(
+                        [127, 0, 0, 1],
+                        12000,
+                    )
+                        .into()])),
+                ),
+                (
+                    "infrastructure_get_section_response_unchanged",
+                    InfraQuery::GetSectionResponse(GetSectionResponse::Unchanged),
+                ),
+                (
+                    "infrastructure_get_section_stats",
+                    InfraQuery::GetSectionStats(name),
+                ),
+                (
+                    "infrastructure_get_section_stats_response",
+                    InfraQuery::GetSectionStatsResponse(SignedSectionStats {
+                        signature: section_key.sign(SignedSectionStats::signable_bytes(&stats)?),
+                        key: section_key.public_key(),
+                        stats,
+                    }),
+                ),
+                (
+                    "infrastructure_get_section_capabilities",
+                    InfraQuery::GetSectionCapabilities(name),
+                ),
+                (
+                    "infrastructure_get_section_capabilities_response",
+                    InfraQuery::GetSectionCapabilitiesResponse(SectionCapabilities {
+                        prefix: Prefix::default(),
+                        anti_replay_window_secs: 60,
+                    }),
+                ),
+                (
+                    "infrastructure_get_join_status",
+                    InfraQuery::GetJoinStatus(name),
+                ),
+                (
+                    "infrastructure_get_join_status_response",
+                    InfraQuery::GetJoinStatusResponse(JoinStatus {
+                        prefix: Prefix::default(),
+                        accepting: false,
+                        expected_wait: Duration::from_secs(600),
+                        resource_proof_difficulty: 8,
+                    }),
+                ),
+                (
+                    "infrastructure_get_key_chain",
+                    InfraQuery::GetKeyChain {
+                        name,
+                        from_key: section_key.public_key(),
+                        to_key: next_section_key.public_key(),
+                    },
+                ),
+                (
+                    "infrastructure_get_key_chain_response",
+                    InfraQuery::GetKeyChainResponse(GetKeyChainResponse::Success(vec![(
+                        next_section_key.public_key(),
+                        section_key.sign(GetKeyChainResponse::signable_bytes(
+                            &next_section_key.public_key(),
+                        )),
+                    )])),
+                ),
+                (
+                    "infrastructure_get_relay_stats",
+                    InfraQuery::GetRelayStats(name),
+                ),
+                (
+                    "infrastructure_get_relay_stats_response",
+                    InfraQuery::GetRelayStatsResponse(RelayStats {
+                        prefix: Prefix::default(),
+                        relayed_msgs: 1_000,
+                        relay_cost_units: 4_000,
+                        window: Duration::from_secs(3_600),
+                    }),
+                ),
+                (
+                    "infrastructure_signed",
+                    InfraQuery::signed(InfraQuery::GetSectionRequest(name), &keypair)?,
+                ),
+                (
+                    "infrastructure_signed_response",
+                    InfraQuery::SignedResponse {
+                        response: Box::new(InfraQuery::GetSectionResponse(
+                            GetSectionResponse::Unchanged,
+                        )),
+                        requester: keypair.public_key(),
+                    },
+                ),
+            ];
+
+            let id = MessageId::new();
+            let correlation_id = MessageId::new();
+            let origin = Address::Client(name);
+            let cmd = Message::Cmd {
+                cmd: Cmd::Echo {
+                    client: name,
+                    payload: vec![7; 32],
+                },
+                id,
+                app_id: None,
+                idempotency_key: None,
+                depends_on: vec![],
+            };
+            let dead_letter_header = WireMsg::new_client_msg(&cmd)?.header();
+            let client = vec![
+                ("client_cmd", cmd),
+                (
+                    "client_query",
+                    Message::Query {
+                        query: Query::Data(DataQuery::Blob(BlobRead::Get(BlobAddress::Public(
+                            name,
+                        )))),
+                        id,
+                        app_id: Some(name),
+                        reply_to: None,
+                    },
+                ),
+                (
+                    "client_event",
+                    Message::Event {
+                        event: Event::EchoReply {
+                            client: name,
+                            payload: vec![7; 32],
+                            elder: name,
+                        },
+                        id,
+                        correlation_id,
+                    },
+                ),
+                (
+                    "client_query_response",
+                    Message::QueryResponse {
+                        response: QueryResponse::GetMapVersion(Ok(3)),
+                        id,
+                        correlation_id,
+                        query_origin: origin.clone(),
+                    },
+                ),
+                (
+                    "client_cmd_error",
+                    Message::CmdError {
+                        error: CmdError::Data {
+                            address: DataAddress::Blob(BlobAddress::Public(name)),
+                            op: DataOpKind::New,
+                            error: DataError::NotFound,
+                        },
+                        id,
+                        correlation_id,
+                        cmd_origin: origin,
+                    },
+                ),
+                (
+                    "client_node_cmd",
+                    Message::NodeCmd {
+                        cmd: NodeCmd::System(NodeSystemCmd::SetAntiReplayWindow {
+                            seconds: 60,
+                            section: name,
+                        }),
+                        id,
+                    },
+                ),
+                (
+                    "client_node_cmd_error",
+                    Message::NodeCmdError {
+                        error: NodeCmdError::Data(NodeDataError::ChunkReplication {
+                            address: BlobAddress::Public(name),
+                            error: Error::NoSuchData,
+                        }),
+                        id,
+                        correlation_id,
+                        cmd_origin: Address::Node(name),
+                    },
+                ),
+                (
+                    "client_node_event",
+                    Message::NodeEvent {
+                        event: NodeEvent::PenaltyApplied {
+                            node: name,
+                            reason: PenaltyReason::Inactivity,
+                            amount: Token::from_nano(1),
+                        },
+                        id,
+                        correlation_id,
+                    },
+                ),
+                (
+                    "client_node_query",
+                    Message::NodeQuery {
+                        query: NodeQuery::Rewards(NodeRewardQuery::GetMyRewardState {
+                            node_id: name,
+                        }),
+                        id,
+                    },
+                ),
+                (
+                    "client_node_query_response",
+                    Message::NodeQueryResponse {
+                        response: NodeQueryResponse::Rewards(
+                            NodeRewardQueryResponse::GetPenaltyHistory(Ok(vec![])),
+                        ),
+                        id,
+                        correlation_id,
+                        query_origin: Address::Node(name),
+                        responder: None,
+                    },
+                ),
+                (
+                    "client_dead_letter",
+                    Message::DeadLetter {
+                        original_header: dead_letter_header,
+                        reason: DeadLetterReason::MaxHopsExceeded,
+                        final_dst_attempted: Address::Section(name),
+                        id,
+                        correlation_id,
+                    },
+                ),
+                (
+                    "client_processing_error",
+                    Message::ProcessingError {
+                        error: ProcessingError::new()
+                            .with_reason(ProcessingReason::NotResponsible {
+                                current_responsible: vec![(name, ([127, 0, 0, 1], 12000).into())],
+                            })
+                            .with_source_message(vec![7; 32]),
+                        id,
+                        correlation_id,
+                    },
+                ),
+            ];
+
+            let mut samples = vec![("ping", MessageType::Ping)];
+            samples.extend(
+                infrastructure
+                    .into_iter()
+                    .map(|(name, query)| (name, MessageType::InfrastructureQuery(query))),
+            );
+            samples.extend(
+                client
+                    .into_iter()
+                    .map(|(name, msg)| (name, MessageType::ClientMessage(msg))),
+            );
+            samples.push((
+                "node_message",
+                MessageType::NodeMessage(NodeMessage::new(Bytes::from(vec![7; 32]))),
+            ));
+            samples.push((
+                "layered",
+                MessageType::Layered(LayeredDst::wrap(
+                    Bytes::from(vec![7; 32]),
+                    name,
+                    &section_key.public_key(),
+                )?),
+            ));
+            samples.push((
+                "multipart_part",
+                MessageType::Multipart(Part {
+                    msg_id: id,
+                    index: 0,
+                    of: 2,
+                    payload: vec![7; 32],
+                }),
+            ));
+            samples.push((
+                "batch",
+                MessageType::Batch(vec![
+                    MessageType::Ping,
+                    MessageType::Multipart(Part {
+                        msg_id: id,
+                        index: 1,
+                        of: 2,
+                        payload: vec![7; 32],
+                    }),
+                ]),
+            ));
+            samples.push((
+                "flow_credit",
+                MessageType::FlowCredit(FlowCredit {
+                    credits: 64,
+                    window: Duration::from_secs(30),
+                }),
+            ));
+            Ok(samples)
+        }
+
+        // The samples flagging optional fields of the wire header, which are serialized from
+        // their `WireMsg`, as the message alone doesn't carry these fields.
+        pub(super) fn flagged_samples() -> Result<Vec<(&'static str, Bytes)>> {
+            let query = InfraQuery::GetSectionRequest(XorName::random());
+            let mut signed = WireMsg::new_infrastructure_query(&query)?;
+            signed.sign(&Keypair::new_ed25519(&mut rand::thread_rng()))?;
+            let mut encrypted = WireMsg::new_infrastructure_query(&query)?;
+            encrypted.set_dest_section_pk(SecretKey::random().public_key());
+            let mut expires = WireMsg::new_ping_msg();
+            expires.set_expires_at(UNIX_EPOCH + Duration::from_secs(1_000_000));
+            let mut hops = WireMsg::new_ping_msg();
+            let _ = hops.increment_hops()?;
+            let mut flow_id = WireMsg::new_ping_msg();
+            flow_id.set_flow_id(FlowId::from(MessageId::new()));
+            Ok(vec![
+                ("signed", signed.serialize()?),
+                ("encrypted", encrypted.serialize_encrypted()?),
+                ("expires", expires.serialize()?),
+                ("hops", hops.serialize()?),
+                ("flow_id", flow_id.serialize()?),
+            ])
+        }
+    }
+}
@@ -7,7 +7,9 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
+#[cfg(feature = "client-msgs")]
 use crate::client;
+use crate::MessageKind;
 use std::result;
 use thiserror::Error;
 
@@ -17,18 +19,201 @@ pub type Result<T, E = Error> = result::Result<T, E>;
 #[derive(Error, Debug)]
 #[non_exhaustive]
 pub enum Error {
+    #[cfg(feature = "client-msgs")]
     #[error(transparent)]
     Client(#[from] client::Error),
 
     #[error("Failed to serialize message: {0}")]
     Serialisation(String),
-    /// Failed to parse a string.
-    #[error("Failed to parse: {0}")]
-    FailedToParse(String),
+    /// Failed to parse a message, or a part of it.
+    #[error("Failed to parse: {what}{}", context(.kind, .field, .offset, .len))]
+    FailedToParse {
+        /// What failed to parse, and why.
+        what: String,
+        /// Kind of the message attempted, if known.
+        kind: Option<MessageKind>,
+        /// Path to the field of the payload where parsing failed, as reported by serde.
+        field: Option<String>,
+        /// Offset, in bytes, into the payload where parsing failed.
+        offset: Option<usize>,
+        /// Length of the payload, in bytes.
+        len: Option<usize>,
+    },
     /// Message read was built with an unsupported version.
     #[error("Unsupported messaging protocol version: {0}")]
     UnsupportedVersion(u16),
     /// Message is of an unsupported kind.
     #[error("Unsupported messaging kind: {0}")]
     UnsupportedMessageKind(u8),
+    /// Failed to decrypt a message.
+    #[error("Failed to decrypt: {0}")]
+    FailedToDecrypt(String),
+    /// Message exceeds the max message size.
+    #[error("Message of {size} bytes exceeds the limit of {limit} bytes")]
+    MessageTooLarge {
+        /// Size of the message, in bytes.
+        size: usize,
+        /// The max size of a message, in bytes.
+        limit: usize,
+    },
+    /// Message forwarded more times than the max number of hops, e.g. caught in a routing loop.
+    #[error("Message forwarded {hops} times exceeds the limit of {limit} hops")]
+    MaxHopsExceeded {
+        /// Number of hops of the message.
+        hops: u8,
+        /// The max number of hops of a message.
+        limit: u8,
+    },
+    /// Too many messages are already pending reassembly from their parts,
+    /// see `multipart::MultipartAssembler`.
+    #[error("Too many messages pending reassembly, the limit is {limit}")]
+    TooManyPendingMsgs {
+        /// The max number of messages pending reassembly.
+        limit: usize,
+    },
+    /// A batch of Blob addresses is empty, leaving the message with no section to be routed to,
+    /// see `client::Message::check_limits`.
+    #[error("A batch of Blob addresses can't be empty")]
+    EmptyBatch,
+    /// I/O error on the stream a message is read from or written to.
+    #[cfg(feature = "codec")]
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+// Codes of the crate errors start here, below are those of the wrapped `client::Error`.
+const CODES_START: u32 = 1000;
+
+impl Error {
+    /// Returns the stable code of the error variant, e.g. to report errors in dashboards
+    /// without matching their description. The code of a wrapped `client::Error` is its own,
+    /// see `client::Error::code`, the codes of the other variants start at 1000.
+    pub fn code(&self) -> u32 {
+        match self {
+            #[cfg(feature = "client-msgs")]
+            Self::Client(error) => error.code(),
+            Self::Serialisation(_) => CODES_START + 1,
+            Self::FailedToParse { .. } => CODES_START + 2,
+            Self::UnsupportedVersion(_) => CODES_START + 3,
+            Self::UnsupportedMessageKind(_) => CODES_START + 4,
+            Self::FailedToDecrypt(_) => CODES_START + 5,
+            Self::MessageTooLarge { .. } => CODES_START + 6,
+            Self::MaxHopsExceeded { .. } => CODES_START + 7,
+            #[cfg(feature = "codec")]
+            Self::Io(_) => CODES_START + 8,
+            Self::TooManyPendingMsgs { .. } => CODES_START + 9,
+            Self::EmptyBatch => CODES_START + 10,
+        }
+    }
+
+    /// Returns the error of the given code, see `code`. Returns `None` for unknown codes,
+    /// and for the codes of variants carrying data, which the code alone can't restore.
+    /// Of the crate's own variants, only `EmptyBatch` carries no data and is restored.
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            #[cfg(feature = "client-msgs")]
+            code if code < CODES_START => client::Error::from_code(code).map(Self::Client),
+            code if code == CODES_START + 10 => Some(Self::EmptyBatch),
+            _ => None,
+        }
+    }
+
+    // Creates a `FailedToParse` error with no context beyond the description.
+    pub(crate) fn failed_to_parse(what: impl Into<String>) -> Self {
+        Self::FailedToParse {
+            what: what.into(),
+            kind: None,
+            field: None,
+            offset: None,
+            len: None,
+        }
+    }
+}
+
+// Formats the context of a `FailedToParse` error, e.g. " (kind: ClientMessage, field: .cmd,
+// offset: 12 of 48 bytes)", so that failures can be diagnosed from logs alone.
+fn context(
+    kind: &Option<MessageKind>,
+    field: &Option<String>,
+    offset: &Option<usize>,
+    len: &Option<usize>,
+) -> String {
+    let mut parts = Vec::new();
+    if let Some(kind) = kind {
+        parts.push(format!("kind: {:?}", kind));
+    }
+    if let Some(field) = field {
+        parts.push(format!("field: {}", field));
+    }
+    match (offset, len) {
+        (Some(offset), Some(len)) => parts.push(format!("offset: {} of {} bytes", offset, len)),
+        (Some(offset), None) => parts.push(format!("offset: {}", offset)),
+        (None, Some(len)) => parts.push(format!("length: {} bytes", len)),
+        (None, None) => {}
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_codes() {
+        let error = Error::MessageTooLarge { size: 2, limit: 1 };
+        assert_eq!(error.code(), 1006);
+        assert!(Error::from_code(error.code()).is_none());
+        assert!(matches!(Error::from_code(1010), Some(Error::EmptyBatch)));
+
+        #[cfg(feature = "client-msgs")]
+        {
+            let error = Error::Client(client::Error::InsufficientBalance);
+            assert_eq!(error.code(), 28);
+            assert_eq!(
+                Error::from_code(28).map(|error| error.to_string()),
+                Some(error.to_string())
+            );
+            for code in 0..CODES_START {
+                if let Some(error) = client::Error::from_code(code) {
+                    assert_eq!(error.code(), code);
+                }
+            }
+            assert_eq!(client::Error::from_code(0), None);
+            assert_eq!(
+                client::Error::DependencyFailed(crate::MessageId::new()).code(),
+                43
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "client-msgs")]
+    fn error_sources() {
+        use std::error::Error as _;
+
+        let error = client::TryFromError::from(client::Error::NoSuchData);
+        let source = error
+            .source()
+            .and_then(|source| source.downcast_ref::<client::Error>());
+        assert_eq!(source, Some(&client::Error::NoSuchData));
+        assert!(client::TryFromError::WrongType.source().is_none());
+
+        let chained = anyhow::Error::new(error).context("fetching blob");
+        assert_eq!(chained.chain().count(), 3);
+
+        let error = Error::from(client::Error::InsufficientBalance);
+        assert_eq!(
+            client::Error::from(error),
+            client::Error::InsufficientBalance
+        );
+        let error = Error::failed_to_parse("client message");
+        assert_eq!(
+            client::Error::from(error),
+            client::Error::Serialization("Failed to parse: client message".to_string())
+        );
+    }
 }
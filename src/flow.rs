@@ -0,0 +1,154 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::{Error, MessageType, Result, WireMsg};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+use xor_name::XorName;
+
+/// Flow control message granting the peer it is sent to a number of messages it
+/// can send back within a time window, e.g. from an Elder to a client or an Adult.
+/// A new grant replaces the previous one, so senders limit themselves
+/// instead of relying on the receiver dropping the excess traffic.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FlowCredit {
+    /// Number of messages the peer can send within the window.
+    pub credits: u32,
+    /// Time window, starting on receipt of the grant, the credits are valid for.
+    pub window: Duration,
+}
+
+impl FlowCredit {
+    /// Convinience function to deserialize a 'FlowCredit' from bytes received over the wire.
+    /// It returns an error if the bytes don't correspond to a flow credit grant.
+    pub fn from(bytes: Bytes) -> Result<Self> {
+        if let MessageType::FlowCredit(credit) = crate::parse(bytes)?.body {
+            Ok(credit)
+        } else {
+            Err(Error::failed_to_parse("bytes as a flow credit grant"))
+        }
+    }
+
+    /// serialize this FlowCredit into bytes ready to be sent over the wire.
+    pub fn serialize(&self) -> Result<Bytes> {
+        WireMsg::serialize_flow_credit(self)
+    }
+}
+
+/// Keeps track, on the sender side, of the credits granted by each peer
+/// with `FlowCredit` messages, and of how many of them are left.
+#[derive(Debug, Default)]
+pub struct FlowCredits {
+    grants: BTreeMap<XorName, Grant>,
+}
+
+#[derive(Debug)]
+struct Grant {
+    remaining: u32,
+    expires_at: Instant,
+}
+
+impl FlowCredits {
+    /// Creates a new instance, with no credits granted by any peer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the credits granted by the peer, replacing any previous grant from it.
+    pub fn grant(&mut self, peer: XorName, credit: &FlowCredit) {
+        self.grant_at(peer, credit, Instant::now())
+    }
+
+    /// Records the credits granted by the peer, as received at the given instant.
+    pub fn grant_at(&mut self, peer: XorName, credit: &FlowCredit, now: Instant) {
+        let _ = self.grants.insert(
+            peer,
+            Grant {
+                remaining: credit.credits,
+                expires_at: now + credit.window,
+            },
+        );
+    }
+
+    /// Takes one credit granted by the peer, returning false if there are none left,
+    /// in which case the message shouldn't be sent until the peer grants new credits.
+    pub fn try_consume(&mut self, peer: &XorName) -> bool {
+        self.try_consume_at(peer, Instant::now())
+    }
+
+    /// Takes one credit granted by the peer, as of the given instant.
+    pub fn try_consume_at(&mut self, peer: &XorName, now: Instant) -> bool {
+        match self.grants.get_mut(peer) {
+            Some(grant) if grant.expires_at > now && grant.remaining > 0 => {
+                grant.remaining -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the number of credits granted by the peer which are left.
+    pub fn remaining(&self, peer: &XorName) -> u32 {
+        self.remaining_at(peer, Instant::now())
+    }
+
+    /// Returns the number of credits granted by the peer which are left as of the given instant.
+    pub fn remaining_at(&self, peer: &XorName, now: Instant) -> u32 {
+        self.grants
+            .get(peer)
+            .filter(|grant| grant.expires_at > now)
+            .map_or(0, |grant| grant.remaining)
+    }
+
+    /// Drops the grants whose window is over as of the given instant.
+    pub fn prune_expired(&mut self, now: Instant) {
+        self.grants.retain(|_, grant| grant.expires_at > now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn flow_credits() -> Result<()> {
+        let credit = FlowCredit {
+            credits: 2,
+            window: Duration::from_secs(10),
+        };
+        assert_eq!(FlowCredit::from(credit.serialize()?)?, credit);
+
+        let peer = XorName::random();
+        let now = Instant::now();
+        let mut credits = FlowCredits::new();
+        assert!(!credits.try_consume_at(&peer, now));
+
+        credits.grant_at(peer, &credit, now);
+        assert_eq!(credits.remaining_at(&peer, now), 2);
+        assert!(credits.try_consume_at(&peer, now));
+        assert!(credits.try_consume_at(&peer, now));
+        assert!(!credits.try_consume_at(&peer, now));
+        assert_eq!(credits.remaining_at(&peer, now), 0);
+
+        credits.grant_at(peer, &credit, now);
+        let later = now + Duration::from_secs(11);
+        assert_eq!(credits.remaining_at(&peer, later), 0);
+        assert!(!credits.try_consume_at(&peer, later));
+
+        credits.prune_expired(later);
+        assert!(credits.grants.is_empty());
+
+        Ok(())
+    }
+}
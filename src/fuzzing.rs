@@ -0,0 +1,1078 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! `Arbitrary` implementations of the messages, for structure-aware fuzzing.
+//!
+//! Keys are derived from an arbitrary seed rather than from arbitrary bytes, and signatures
+//! are made with them, so that the generated messages are valid and exercise the same code
+//! paths as genuine ones. Only the variants whose content can be generated this way are covered.
+
+#[cfg(feature = "client-msgs")]
+use crate::client::{
+    self, Address, AdultDuties, BlobMetadata, BlobRead, BlobWrite, Cmd, CmdError, DataAddress,
+    DataCmd, DataError, DataOpKind, DataQuery, DataResult, Duty, EntryHash, Event, Listing,
+    MapRead, MapWrite, Message, NodeCmd, NodeCmdError, NodeDataError, NodeEvent, NodeQuery,
+    NodeQueryResponse, NodeRewardQuery, NodeRewardQueryResponse, NodeSystemCmd, PenaltyReason,
+    PrepaidOps, Query, QueryResponse, Registration, ReplyTo, ScheduledCmdStatus, SequenceRead,
+    SequenceWrite, TransferCmd, TransferError, TransferQuery, VersionOf,
+};
+#[cfg(feature = "infrastructure")]
+use crate::infrastructure::{
+    self, Endpoint, GetKeyChainResponse, GetSectionResponse, JoinStatus, RelayStats,
+    SectionCapabilities, SectionStats, SignedSectionStats, Transport,
+};
+#[cfg(feature = "node-msgs")]
+use crate::node::{AckRange, MsgHash, NodeMessage, NodeProcessingError};
+#[cfg(any(feature = "client-msgs", feature = "node-msgs"))]
+use crate::processing::ProcessingError;
+use crate::{processing::ProcessingReason, MessageId};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+#[cfg(any(feature = "client-msgs", feature = "infrastructure"))]
+use sn_data_types::Keypair;
+#[cfg(feature = "client-msgs")]
+use sn_data_types::{
+    Blob, BlobAddress, Credit, Debit, Map, MapAddress, MapSeqValue, MapValue, PrivateBlob,
+    PublicBlob, PublicKey, SeqMap, Sequence, SequenceAddress, SequenceIndex, SequenceUser,
+    Signature, SignatureShare, SignedCredit, SignedDebit, SignedTransfer, Token,
+    TransferAgreementProof, TransferValidated, UnseqMap,
+};
+#[cfg(feature = "infrastructure")]
+use std::net::SocketAddr;
+use std::time::Duration;
+#[cfg(feature = "client-msgs")]
+use std::time::{SystemTime, UNIX_EPOCH};
+use threshold_crypto::SecretKey;
+#[cfg(feature = "client-msgs")]
+use threshold_crypto::SecretKeySet;
+use xor_name::Prefix;
+use xor_name::XorName;
+
+impl<'a> Arbitrary<'a> for MessageId {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self(xor_name(u)?))
+    }
+}
+
+#[cfg(feature = "node-msgs")]
+impl<'a> Arbitrary<'a> for NodeMessage {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            payload: u.arbitrary()?,
+            prev_msg_hash: u.arbitrary::<Option<[u8; 32]>>()?.map(MsgHash),
+            seq: u.arbitrary()?,
+            ack: u
+                .arbitrary::<Option<u64>>()?
+                .map(|up_to| AckRange { up_to }),
+            relay_cost_units: u.arbitrary()?,
+            processing_error: if u.arbitrary()? {
+                Some(NodeProcessingError {
+                    error: ProcessingError {
+                        reason: u.arbitrary()?,
+                        source_message: u.arbitrary()?,
+                    },
+                    source_hash: MsgHash(u.arbitrary()?),
+                })
+            } else {
+                None
+            },
+            src: u.arbitrary::<Option<[u8; 32]>>()?.map(XorName),
+            dst: u.arbitrary::<Option<[u8; 32]>>()?.map(XorName),
+        })
+    }
+}
+
+#[cfg(feature = "infrastructure")]
+impl<'a> Arbitrary<'a> for infrastructure::Query {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        use infrastructure::Query::*;
+        Ok(match u.int_in_range(0..=13)? {
+            0 => GetSectionRequestIfChanged {
+                name: xor_name(u)?,
+                etag: u.arbitrary()?,
+            },
+            1 => GetSectionResponse(u.arbitrary()?),
+            2 => GetSectionStats(xor_name(u)?),
+            3 => GetSectionStatsResponse(u.arbitrary()?),
+            4 => GetSectionCapabilities(xor_name(u)?),
+            5 => GetSectionCapabilitiesResponse(u.arbitrary()?),
+            6 => GetJoinStatus(xor_name(u)?),
+            7 => GetJoinStatusResponse(u.arbitrary()?),
+            8 => GetKeyChain {
+                name: xor_name(u)?,
+                from_key: bls_secret_key(u)?.public_key(),
+                to_key: bls_secret_key(u)?.public_key(),
+            },
+            9 => GetKeyChainResponse(u.arbitrary()?),
+            10 => GetRelayStats(xor_name(u)?),
+            11 => GetRelayStatsResponse(u.arbitrary()?),
+            12 => {
+                let query = GetSectionRequest(xor_name(u)?);
+                Self::signed(query, &keypair(u)?).map_err(|_| arbitrary::Error::IncorrectFormat)?
+            }
+            _ => GetSectionRequest(xor_name(u)?),
+        })
+    }
+}
+
+#[cfg(feature = "infrastructure")]
+impl<'a> Arbitrary<'a> for GetSectionResponse {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=2)? {
+            0 => Self::Success {
+                prefix: prefix(u)?,
+                key: bls_secret_key(u)?.public_key(),
+                elders: (0..u.int_in_range(0..=7)?)
+                    .map(|_| Ok((xor_name(u)?, socket_addr(u)?)))
+                    .collect::<Result<_>>()?,
+                endpoints: (0..u.int_in_range(0..=7)?)
+                    .map(|_| {
+                        let endpoints = (0..u.int_in_range(1..=3)?)
+                            .map(|_| {
+                                Ok(Endpoint {
+                                    addr: socket_addr(u)?,
+                                    transport: *u.choose(&[Transport::Quic, Transport::Udp])?,
+                                    port_mapped: u.arbitrary()?,
+                                })
+                            })
+                            .collect::<Result<_>>()?;
+                        Ok((xor_name(u)?, endpoints))
+                    })
+                    .collect::<Result<_>>()?,
+            },
+            1 => Self::Redirect(
+                (0..u.int_in_range(0..=7)?)
+                    .map(|_| socket_addr(u))
+                    .collect::<Result<_>>()?,
+            ),
+            _ => Self::Unchanged,
+        })
+    }
+}
+
+#[cfg(feature = "infrastructure")]
+impl<'a> Arbitrary<'a> for GetKeyChainResponse {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        if u.arbitrary()? {
+            return Ok(Self::UnknownKey);
+        }
+        let mut last_key = bls_secret_key(u)?;
+        let segment = (0..u.int_in_range(0..=8)?)
+            .map(|_| {
+                let key = bls_secret_key(u)?;
+                let signature = last_key.sign(Self::signable_bytes(&key.public_key()));
+                let link = (key.public_key(), signature);
+                last_key = key;
+                Ok(link)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self::Success(segment))
+    }
+}
+
+#[cfg(feature = "infrastructure")]
+impl<'a> Arbitrary<'a> for SectionCapabilities {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            prefix: prefix(u)?,
+            anti_replay_window_secs: u.arbitrary()?,
+        })
+    }
+}
+
+#[cfg(feature = "infrastructure")]
+impl<'a> Arbitrary<'a> for SectionStats {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            prefix: prefix(u)?,
+            elder_count: u.arbitrary()?,
+            adult_count: u.arbitrary()?,
+            stored_bytes: u.arbitrary()?,
+            churn_count: u.arbitrary()?,
+            churn_window: Duration::from_secs(u.arbitrary()?),
+        })
+    }
+}
+
+#[cfg(feature = "infrastructure")]
+impl<'a> Arbitrary<'a> for SignedSectionStats {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let stats = u.arbitrary()?;
+        let secret_key = bls_secret_key(u)?;
+        let bytes = Self::signable_bytes(&stats).map_err(|_| arbitrary::Error::IncorrectFormat)?;
+        Ok(Self {
+            signature: secret_key.sign(bytes),
+            key: secret_key.public_key(),
+            stats,
+        })
+    }
+}
+
+#[cfg(feature = "infrastructure")]
+impl<'a> Arbitrary<'a> for JoinStatus {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            prefix: prefix(u)?,
+            accepting: u.arbitrary()?,
+            expected_wait: Duration::from_secs(u.arbitrary()?),
+            resource_proof_difficulty: u.arbitrary()?,
+        })
+    }
+}
+
+#[cfg(feature = "infrastructure")]
+impl<'a> Arbitrary<'a> for RelayStats {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            prefix: prefix(u)?,
+            relayed_msgs: u.arbitrary()?,
+            relay_cost_units: u.arbitrary()?,
+            window: Duration::from_secs(u.arbitrary()?),
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for Message {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let id = u.arbitrary()?;
+        Ok(match u.int_in_range(0..=10)? {
+            0 => Self::Query {
+                query: u.arbitrary()?,
+                id,
+                app_id: optional_xor_name(u)?,
+                reply_to: u.arbitrary()?,
+            },
+            1 => Self::Event {
+                event: u.arbitrary()?,
+                id,
+                correlation_id: u.arbitrary()?,
+            },
+            2 => Self::QueryResponse {
+                response: u.arbitrary()?,
+                id,
+                correlation_id: u.arbitrary()?,
+                query_origin: u.arbitrary()?,
+            },
+            3 => Self::CmdError {
+                error: u.arbitrary()?,
+                id,
+                correlation_id: u.arbitrary()?,
+                cmd_origin: u.arbitrary()?,
+            },
+            4 => Self::NodeCmd {
+                cmd: NodeCmd::System(NodeSystemCmd::SetAntiReplayWindow {
+                    seconds: u.arbitrary()?,
+                    section: xor_name(u)?,
+                }),
+                id,
+            },
+            5 => Self::NodeCmdError {
+                error: NodeCmdError::Data(NodeDataError::ChunkReplication {
+                    address: BlobAddress::Public(xor_name(u)?),
+                    error: error(u)?,
+                }),
+                id,
+                correlation_id: u.arbitrary()?,
+                cmd_origin: u.arbitrary()?,
+            },
+            6 => Self::NodeEvent {
+                event: NodeEvent::PenaltyApplied {
+                    node: xor_name(u)?,
+                    reason: *u.choose(&[
+                        PenaltyReason::Inactivity,
+                        PenaltyReason::FailedChunkRetrieval,
+                        PenaltyReason::Misbehaviour,
+                    ])?,
+                    amount: Token::from_nano(u.arbitrary()?),
+                },
+                id,
+                correlation_id: u.arbitrary()?,
+            },
+            7 => Self::NodeQuery {
+                query: NodeQuery::Rewards(NodeRewardQuery::GetMyRewardState {
+                    node_id: xor_name(u)?,
+                }),
+                id,
+            },
+            8 => Self::NodeQueryResponse {
+                response: NodeQueryResponse::Rewards(NodeRewardQueryResponse::GetNodeWalletId(
+                    result(u, |u| Ok((public_key(u)?, xor_name(u)?)))?,
+                )),
+                id,
+                correlation_id: u.arbitrary()?,
+                query_origin: u.arbitrary()?,
+                responder: if u.arbitrary()? {
+                    Some(Duty::Adult(AdultDuties::ChunkStorage))
+                } else {
+                    None
+                },
+            },
+            9 => Self::ProcessingError {
+                error: ProcessingError {
+                    reason: u.arbitrary()?,
+                    source_message: u.arbitrary()?,
+                },
+                id,
+                correlation_id: u.arbitrary()?,
+            },
+            _ => Self::Cmd {
+                cmd: u.arbitrary()?,
+                id,
+                app_id: optional_xor_name(u)?,
+                idempotency_key: optional_xor_name(u)?,
+                depends_on: u.arbitrary()?,
+            },
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for Cmd {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=5)? {
+            0 => Self::Data {
+                cmd: u.arbitrary()?,
+                payment: payment(u)?,
+                effective_after: optional_system_time(u)?,
+            },
+            1 => Self::PrepaidData {
+                cmd: u.arbitrary()?,
+                prepaid: u.arbitrary()?,
+                effective_after: optional_system_time(u)?,
+            },
+            2 => Self::Transfer(u.arbitrary()?),
+            3 => Self::RequestAudit {
+                address: data_address(u)?,
+                payment: payment(u)?,
+            },
+            4 => Self::CancelScheduled {
+                address: data_address(u)?,
+                cmd_id: u.arbitrary()?,
+            },
+            _ => Self::Echo {
+                client: xor_name(u)?,
+                payload: u.arbitrary()?,
+            },
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for DataCmd {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=2)? {
+            0 => Self::Blob(match u.int_in_range(0..=3)? {
+                0 => BlobWrite::New(blob(u)?),
+                1 => BlobWrite::DeletePrivate(BlobAddress::Private(xor_name(u)?)),
+                2 => BlobWrite::DeleteBatch(blob_addresses(u)?),
+                _ => BlobWrite::NewWithMetadata {
+                    blob: blob(u)?,
+                    metadata: blob_metadata(u)?,
+                },
+            }),
+            1 => Self::Map(match u.int_in_range(0..=3)? {
+                0 => MapWrite::New(map(u)?),
+                1 => MapWrite::Delete(map_address(u)?),
+                2 => MapWrite::DelUserPermissions {
+                    address: map_address(u)?,
+                    user: public_key(u)?,
+                    version: u.arbitrary()?,
+                },
+                _ => MapWrite::CompareAndSet {
+                    address: map_address(u)?,
+                    key: u.arbitrary()?,
+                    expected: u.arbitrary()?,
+                    new: map_value(u)?,
+                },
+            }),
+            _ => Self::Sequence(if u.arbitrary()? {
+                SequenceWrite::New(sequence(u)?)
+            } else {
+                SequenceWrite::Delete(SequenceAddress::Private {
+                    name: xor_name(u)?,
+                    tag: u.arbitrary()?,
+                })
+            }),
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for TransferCmd {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(if u.arbitrary()? {
+            Self::ValidateTransfer {
+                signed_transfer: signed_transfer(u)?,
+                dry_run: u.arbitrary()?,
+            }
+        } else {
+            Self::RegisterTransfer(payment(u)?)
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for PrepaidOps {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let id = xor_name(u)?;
+        let owner = public_key(u)?;
+        let token_bundle = Token::from_nano(u.arbitrary()?);
+        let ops_remaining = u.arbitrary()?;
+        let section_key = bls_secret_key(u)?;
+        let bytes = Self::signable_bytes(&id, &owner, token_bundle, ops_remaining)
+            .map_err(|_| arbitrary::Error::IncorrectFormat)?;
+        Ok(Self {
+            id,
+            owner,
+            token_bundle,
+            ops_remaining,
+            section_key: section_key.public_key(),
+            signature: section_key.sign(bytes),
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for Query {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=5)? {
+            0 => Self::Data(u.arbitrary()?),
+            1 => Self::Transfer(u.arbitrary()?),
+            2 => Self::GetMyRegistrations(public_key(u)?),
+            3 => Self::GetMutationQuota(public_key(u)?),
+            4 => Self::GetScheduledCmdStatus {
+                address: data_address(u)?,
+                cmd_id: u.arbitrary()?,
+            },
+            _ => Self::GetStorageReceipts {
+                owner: public_key(u)?,
+                range: UNIX_EPOCH..system_time(u)?,
+            },
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for DataQuery {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=5)? {
+            0 => Self::Blob(BlobRead::GetChunks(blob_addresses(u)?)),
+            1 => Self::Blob(BlobRead::GetMetadata(BlobAddress::Public(xor_name(u)?))),
+            2 => Self::Map(u.arbitrary()?),
+            3 => Self::Sequence(u.arbitrary()?),
+            4 => Self::GetReplicationStatus(data_address(u)?),
+            _ => Self::Blob(BlobRead::Get(BlobAddress::Public(xor_name(u)?))),
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for MapRead {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let address = map_address(u)?;
+        Ok(match u.int_in_range(0..=8)? {
+            0 => Self::GetValue {
+                address,
+                key: u.arbitrary()?,
+            },
+            1 => Self::GetShell(address),
+            2 => Self::GetVersion(address),
+            3 => Self::ListEntries(address),
+            4 => Self::ListKeys(address),
+            5 => Self::ListValues(address),
+            6 => Self::ListPermissions(address),
+            7 => Self::ListUserPermissions {
+                address,
+                user: public_key(u)?,
+            },
+            _ => Self::Get(address),
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for SequenceRead {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let address = sequence_address(u)?;
+        Ok(match u.int_in_range(0..=8)? {
+            0 => Self::GetRange {
+                address,
+                range: (sequence_index(u)?, sequence_index(u)?),
+            },
+            1 => Self::GetLastEntry(address),
+            2 => Self::GetPublicPolicy(address),
+            3 => Self::GetPrivatePolicy(address),
+            4 => Self::GetUserPermissions {
+                address,
+                user: if u.arbitrary()? {
+                    SequenceUser::Key(public_key(u)?)
+                } else {
+                    SequenceUser::Anyone
+                },
+            },
+            5 => Self::GetOwner(address),
+            6 => Self::SubscribeFrom {
+                address,
+                since: u.arbitrary::<Option<[u8; 32]>>()?.map(EntryHash),
+            },
+            7 => Self::GetLength(address),
+            _ => Self::Get(address),
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for TransferQuery {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=4)? {
+            0 => Self::GetReplicaKeys(public_key(u)?),
+            1 => Self::GetHistory {
+                at: public_key(u)?,
+                since_version: u.arbitrary::<u32>()? as usize,
+            },
+            2 => Self::GetStoreCost {
+                requester: public_key(u)?,
+                bytes: u.arbitrary()?,
+            },
+            3 => Self::GetBalanceAt {
+                key: public_key(u)?,
+                section_key: bls_secret_key(u)?.public_key(),
+            },
+            _ => Self::GetBalance(public_key(u)?),
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for QueryResponse {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=14)? {
+            0 => Self::GetMapVersion(data_result(u, |u| u.arbitrary())?),
+            1 => Self::GetBalance(result(u, |u| Ok(Token::from_nano(u.arbitrary()?)))?),
+            2 => Self::GetStoreCost(result(u, |u| Ok(Token::from_nano(u.arbitrary()?)))?),
+            3 => Self::GetMyRegistrations(result(u, |u| {
+                (0..u.int_in_range(0..=16)?)
+                    .map(|_| {
+                        Ok(Registration {
+                            socket_id: xor_name(u)?,
+                            registered_at: system_time(u)?,
+                            subscription_mask: u.arbitrary()?,
+                        })
+                    })
+                    .collect()
+            })?),
+            4 => Self::GetScheduledCmdStatus(result(u, |u| {
+                let at = system_time(u)?;
+                Ok(match u.int_in_range(0..=2)? {
+                    0 => ScheduledCmdStatus::Pending {
+                        effective_after: at,
+                    },
+                    1 => ScheduledCmdStatus::Applied { applied_at: at },
+                    _ => ScheduledCmdStatus::Cancelled,
+                })
+            })?),
+            5 => Self::GetChunks(data_result(u, |u| {
+                (0..u.int_in_range(0..=4)?)
+                    .map(|_| {
+                        let blob = data_result(u, blob)?;
+                        let address = match &blob {
+                            Ok(blob) => *blob.address(),
+                            Err(_) => BlobAddress::Public(xor_name(u)?),
+                        };
+                        Ok((address, blob))
+                    })
+                    .collect()
+            })?),
+            6 => Self::GetBlobMetadata(data_result(u, blob_metadata)?),
+            7 => Self::GetMap(data_result(u, map)?),
+            8 => Self::GetMapValue(data_result(u, map_value)?),
+            9 => Self::ListMapKeys(data_result(u, |u| {
+                Ok(Listing {
+                    items: u.arbitrary()?,
+                    truncated: u.arbitrary()?,
+                    total_available: u.arbitrary()?,
+                })
+            })?),
+            10 => Self::GetSequence(data_result(u, sequence)?),
+            11 => Self::GetSequenceOwner(data_result(u, public_key)?),
+            12 => Self::GetSequenceLastEntry(data_result(u, |u| u.arbitrary())?),
+            13 => Self::SubscribeToSequence(data_result(u, |u| u.arbitrary())?),
+            _ => Self::GetBlob(data_result(u, blob)?),
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for CmdError {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=4)? {
+            0 => Self::Auth(error(u)?),
+            1 => Self::Other(error(u)?),
+            2 => Self::DataBatch(
+                (0..u.int_in_range(1..=16)?)
+                    .map(|_| Ok((BlobAddress::Private(xor_name(u)?), error(u)?)))
+                    .collect::<Result<_>>()?,
+            ),
+            3 => Self::Transfer(if u.arbitrary()? {
+                TransferError::TransferValidation(error(u)?)
+            } else {
+                TransferError::TransferRegistration(error(u)?)
+            }),
+            _ => Self::Data {
+                address: data_address(u)?,
+                op: *u.choose(&[
+                    DataOpKind::New,
+                    DataOpKind::Edit,
+                    DataOpKind::Delete,
+                    DataOpKind::SetPermissions,
+                ])?,
+                error: data_error(u)?,
+            },
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for Event {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let client = xor_name(u)?;
+        Ok(match u.int_in_range(0..=6)? {
+            0 => {
+                let replica_count = u.int_in_range(1..=7)?;
+                Self::TransferValidated {
+                    client,
+                    event: transfer_validated(u, replica_count)?,
+                    replica_count,
+                    threshold: u.int_in_range(1..=replica_count)?,
+                }
+            }
+            1 => Self::TransferAgreementReached {
+                client,
+                proof: payment(u)?,
+            },
+            2 => Self::ForwardReceipt {
+                client,
+                original_id: u.arbitrary()?,
+                forwarded_to: xor_name(u)?,
+                at: system_time(u)?,
+            },
+            3 => Self::PriceSignal {
+                client,
+                multiplier: u.arbitrary()?,
+                window: Duration::from_secs(u.arbitrary()?),
+            },
+            4 => Self::SequenceEntriesAppended {
+                client,
+                address: sequence_address(u)?,
+                from_index: u.arbitrary()?,
+                entries: u.arbitrary()?,
+            },
+            5 => Self::PrepaidOpsIssued {
+                client,
+                prepaid: u.arbitrary()?,
+            },
+            _ => Self::EchoReply {
+                client,
+                payload: u.arbitrary()?,
+                elder: xor_name(u)?,
+            },
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for Address {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=2)? {
+            0 => Self::Client(xor_name(u)?),
+            1 => Self::Node(xor_name(u)?),
+            _ => Self::Section(xor_name(u)?),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for ProcessingReason {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=6)? {
+            0 => Self::MissingSectionKey {
+                latest: bls_secret_key(u)?.public_key(),
+            },
+            1 => Self::Overloaded {
+                retry_after: u
+                    .arbitrary::<Option<u32>>()?
+                    .map(|millis| Duration::from_millis(millis.into())),
+            },
+            2 => Self::NotResponsible {
+                current_responsible: (0..u.int_in_range(0..=7)?)
+                    .map(|_| Ok((xor_name(u)?, socket_addr(u)?)))
+                    .collect::<Result<_>>()?,
+            },
+            3 => Self::Deserialization,
+            4 => Self::OutdatedPrefix {
+                current: prefix(u)?,
+                section_key: bls_secret_key(u)?.public_key(),
+            },
+            5 => Self::UnsupportedVersion {
+                version: u.arbitrary()?,
+                supported: u.arbitrary()?,
+            },
+            _ => Self::Internal(u.arbitrary()?),
+        })
+    }
+}
+
+#[cfg(feature = "client-msgs")]
+impl<'a> Arbitrary<'a> for ReplyTo {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(if u.arbitrary()? {
+            Self::Socket(xor_name(u)?)
+        } else {
+            Self::EndUser(xor_name(u)?)
+        })
+    }
+}
+
+fn xor_name(u: &mut Unstructured) -> Result<XorName> {
+    Ok(XorName(u.arbitrary()?))
+}
+
+#[cfg(feature = "client-msgs")]
+fn optional_xor_name(u: &mut Unstructured) -> Result<Option<XorName>> {
+    Ok(u.arbitrary::<Option<[u8; 32]>>()?.map(XorName))
+}
+
+fn prefix(u: &mut Unstructured) -> Result<Prefix> {
+    Ok(Prefix::new(u.int_in_range(0..=16)?, xor_name(u)?))
+}
+
+// The flow info and scope id of IPv6 addresses aren't serialized, so they aren't generated either.
+#[cfg(feature = "infrastructure")]
+fn socket_addr(u: &mut Unstructured) -> Result<SocketAddr> {
+    Ok(SocketAddr::new(u.arbitrary()?, u.arbitrary()?))
+}
+
+// Returns a seeded rng, from which keys can be derived deterministically.
+fn rng(u: &mut Unstructured) -> Result<StdRng> {
+    Ok(StdRng::seed_from_u64(u.arbitrary()?))
+}
+
+fn bls_secret_key(u: &mut Unstructured) -> Result<SecretKey> {
+    Ok(rng(u)?.gen())
+}
+
+#[cfg(any(feature = "client-msgs", feature = "infrastructure"))]
+fn keypair(u: &mut Unstructured) -> Result<Keypair> {
+    Ok(Keypair::new_ed25519(&mut rng(u)?))
+}
+
+#[cfg(feature = "client-msgs")]
+fn public_key(u: &mut Unstructured) -> Result<PublicKey> {
+    Ok(if u.arbitrary()? {
+        PublicKey::Bls(bls_secret_key(u)?.public_key())
+    } else {
+        keypair(u)?.public_key()
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn system_time(u: &mut Unstructured) -> Result<SystemTime> {
+    Ok(UNIX_EPOCH + Duration::from_secs(u.int_in_range(0..=u32::MAX as u64)?))
+}
+
+#[cfg(feature = "client-msgs")]
+fn optional_system_time(u: &mut Unstructured) -> Result<Option<SystemTime>> {
+    Ok(if u.arbitrary()? {
+        Some(system_time(u)?)
+    } else {
+        None
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn data_address(u: &mut Unstructured) -> Result<DataAddress> {
+    Ok(match u.int_in_range(0..=2)? {
+        0 => DataAddress::Map(map_address(u)?),
+        1 => DataAddress::Sequence(sequence_address(u)?),
+        _ => DataAddress::Blob(if u.arbitrary()? {
+            BlobAddress::Public(xor_name(u)?)
+        } else {
+            BlobAddress::Private(xor_name(u)?)
+        }),
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn blob(u: &mut Unstructured) -> Result<Blob> {
+    Ok(if u.arbitrary()? {
+        Blob::Public(PublicBlob::new(u.arbitrary()?))
+    } else {
+        Blob::Private(PrivateBlob::new(u.arbitrary()?, public_key(u)?))
+    })
+}
+
+// A non-empty batch, see `Message::check_limits`.
+#[cfg(feature = "client-msgs")]
+fn blob_addresses(u: &mut Unstructured) -> Result<Vec<BlobAddress>> {
+    (0..u.int_in_range(1..=16)?)
+        .map(|_| Ok(BlobAddress::Private(xor_name(u)?)))
+        .collect()
+}
+
+// The names and values are picked so that the metadata stays within `MAX_BLOB_METADATA_SIZE`.
+#[cfg(feature = "client-msgs")]
+fn blob_metadata(u: &mut Unstructured) -> Result<BlobMetadata> {
+    Ok(BlobMetadata {
+        content_type: if u.arbitrary()? {
+            Some(
+                u.choose(&["text/html", "image/png", "video/mp4"])?
+                    .to_string(),
+            )
+        } else {
+            None
+        },
+        user_meta: (0..u.int_in_range(0..=4)?)
+            .map(|_| {
+                let name = u.choose(&["author", "title", "tags", "licence"])?;
+                Ok((name.to_string(), u.arbitrary::<[u8; 32]>()?.to_vec()))
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn map_address(u: &mut Unstructured) -> Result<MapAddress> {
+    let name = xor_name(u)?;
+    let tag = u.arbitrary()?;
+    Ok(if u.arbitrary()? {
+        MapAddress::Seq { name, tag }
+    } else {
+        MapAddress::Unseq { name, tag }
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn map(u: &mut Unstructured) -> Result<Map> {
+    let name = xor_name(u)?;
+    let tag = u.arbitrary()?;
+    let owner = public_key(u)?;
+    Ok(if u.arbitrary()? {
+        Map::Seq(SeqMap::new(name, tag, owner))
+    } else {
+        Map::Unseq(UnseqMap::new(name, tag, owner))
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn map_value(u: &mut Unstructured) -> Result<MapValue> {
+    Ok(if u.arbitrary()? {
+        MapValue::Seq(MapSeqValue {
+            data: u.arbitrary()?,
+            version: u.arbitrary()?,
+        })
+    } else {
+        MapValue::Unseq(u.arbitrary()?)
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn sequence_address(u: &mut Unstructured) -> Result<SequenceAddress> {
+    let name = xor_name(u)?;
+    let tag = u.arbitrary()?;
+    Ok(if u.arbitrary()? {
+        SequenceAddress::Public { name, tag }
+    } else {
+        SequenceAddress::Private { name, tag }
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn sequence(u: &mut Unstructured) -> Result<Sequence> {
+    let owner = public_key(u)?;
+    let actor = u.arbitrary()?;
+    let name = xor_name(u)?;
+    let tag = u.arbitrary()?;
+    Ok(if u.arbitrary()? {
+        Sequence::new_public(owner, actor, name, tag)
+    } else {
+        Sequence::new_private(owner, actor, name, tag)
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn sequence_index(u: &mut Unstructured) -> Result<SequenceIndex> {
+    Ok(if u.arbitrary()? {
+        SequenceIndex::FromStart(u.arbitrary()?)
+    } else {
+        SequenceIndex::FromEnd(u.arbitrary()?)
+    })
+}
+
+// A transfer signed by its actor, as `sn_transfers` signs it.
+#[cfg(feature = "client-msgs")]
+fn signed_transfer(u: &mut Unstructured) -> Result<SignedTransfer> {
+    let actor = keypair(u)?;
+    let debit = Debit {
+        id: crdts::Dot::new(actor.public_key(), u.arbitrary()?),
+        amount: Token::from_nano(u.arbitrary()?),
+    };
+    let credit = Credit {
+        id: debit
+            .credit_id()
+            .map_err(|_| arbitrary::Error::IncorrectFormat)?,
+        amount: debit.amount,
+        recipient: public_key(u)?,
+        msg: u.arbitrary()?,
+    };
+    Ok(SignedTransfer {
+        debit: SignedDebit {
+            actor_signature: actor.sign(&transfer_bytes(&debit)?),
+            debit,
+        },
+        credit: SignedCredit {
+            actor_signature: actor.sign(&transfer_bytes(&credit)?),
+            credit,
+        },
+    })
+}
+
+// A signed transfer, agreed on by a set of replicas.
+#[cfg(feature = "client-msgs")]
+fn payment(u: &mut Unstructured) -> Result<TransferAgreementProof> {
+    let SignedTransfer { debit, credit } = signed_transfer(u)?;
+    // With a threshold of 0, the share of any replica signs on behalf of the whole set.
+    let replicas = SecretKeySet::random(0, &mut rng(u)?);
+    let secret_key_share = replicas.secret_key_share(0);
+    Ok(TransferAgreementProof {
+        debit_sig: Signature::Bls(secret_key_share.sign(transfer_bytes(&debit)?).0),
+        credit_sig: Signature::Bls(secret_key_share.sign(transfer_bytes(&credit)?).0),
+        signed_debit: debit,
+        signed_credit: credit,
+        debiting_replicas_keys: replicas.public_keys(),
+    })
+}
+
+// The validation of a signed transfer by one of a set of `replica_count` replicas.
+#[cfg(feature = "client-msgs")]
+fn transfer_validated(u: &mut Unstructured, replica_count: u32) -> Result<TransferValidated> {
+    let SignedTransfer { debit, credit } = signed_transfer(u)?;
+    let replicas = SecretKeySet::random(replica_count as usize - 1, &mut rng(u)?);
+    let index = u.int_in_range(0..=replica_count as usize - 1)?;
+    let secret_key_share = replicas.secret_key_share(index);
+    Ok(TransferValidated {
+        replica_debit_sig: SignatureShare {
+            index,
+            share: secret_key_share.sign(transfer_bytes(&debit)?),
+        },
+        replica_credit_sig: SignatureShare {
+            index,
+            share: secret_key_share.sign(transfer_bytes(&credit)?),
+        },
+        signed_debit: debit,
+        signed_credit: credit,
+        replicas: replicas.public_keys(),
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn transfer_bytes(value: &impl serde::Serialize) -> Result<Vec<u8>> {
+    bincode::serialize(value).map_err(|_| arbitrary::Error::IncorrectFormat)
+}
+
+#[cfg(feature = "client-msgs")]
+fn error(u: &mut Unstructured) -> Result<client::Error> {
+    use client::Error::*;
+    Ok(match u.int_in_range(0..=6)? {
+        0 => DataExists,
+        1 => NoSuchEntry,
+        2 => NoSuchKey,
+        3 => NotEnoughSpace,
+        4 => InvalidSuccessor(u.arbitrary()?),
+        5 => Serialization(u.arbitrary()?),
+        _ => NoSuchData,
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn data_error(u: &mut Unstructured) -> Result<DataError> {
+    Ok(match u.int_in_range(0..=8)? {
+        0 => DataError::EntryNotFound,
+        1 => DataError::KeyNotFound,
+        2 => DataError::AlreadyExists,
+        3 => DataError::VersionMismatch {
+            of: *u.choose(&[VersionOf::Data, VersionOf::Owners, VersionOf::Permissions])?,
+            expected: u.arbitrary()?,
+            actual: u.arbitrary()?,
+        },
+        4 => DataError::AccessDenied {
+            key: Box::new(public_key(u)?),
+        },
+        5 => DataError::PolicyNotSet,
+        6 => DataError::CompareAndSetConflict {
+            actual: if u.arbitrary()? {
+                Some(Box::new(map_value(u)?))
+            } else {
+                None
+            },
+        },
+        7 => DataError::Other(Box::new(error(u)?)),
+        _ => DataError::NotFound,
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn result<'a, T>(
+    u: &mut Unstructured<'a>,
+    ok: impl FnOnce(&mut Unstructured<'a>) -> Result<T>,
+) -> Result<client::Result<T>> {
+    Ok(if u.arbitrary()? {
+        Ok(ok(u)?)
+    } else {
+        Err(error(u)?)
+    })
+}
+
+#[cfg(feature = "client-msgs")]
+fn data_result<'a, T>(
+    u: &mut Unstructured<'a>,
+    ok: impl FnOnce(&mut Unstructured<'a>) -> Result<T>,
+) -> Result<DataResult<T>> {
+    Ok(if u.arbitrary()? {
+        Ok(ok(u)?)
+    } else {
+        Err(data_error(u)?)
+    })
+}
+
+#[cfg(all(test, feature = "client-msgs", feature = "infrastructure"))]
+mod tests {
+    use super::*;
+    use crate::WireMsg;
+    use anyhow::Result;
+
+    #[test]
+    fn arbitrary_messages_roundtrip() -> Result<()> {
+        let bytes: Vec<u8> = (0..64 * 1024).map(|_| rand::random()).collect();
+        let mut u = Unstructured::new(&bytes);
+        for _ in 0..32 {
+            let msg: Message = u.arbitrary()?;
+            let wire_msg = WireMsg::new_client_msg(&msg)?;
+            assert_eq!(
+                WireMsg::deserialize(wire_msg.serialize()?)?.into_client_msg()?,
+                msg
+            );
+
+            let query: infrastructure::Query = u.arbitrary()?;
+            let serialized = query.serialize()?;
+            assert_eq!(infrastructure::Query::from(serialized)?, query);
+        }
+
+        Ok(())
+    }
+}
@@ -6,23 +6,96 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::{MessageType, WireMsg};
+use crate::{
+    signing::{self, INFRASTRUCTURE_DOMAIN},
+    MessageType, WireMsg,
+};
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, net::SocketAddr};
-use threshold_crypto::PublicKey;
+use sn_data_types::{Keypair, PublicKey as ClientPublicKey, Signature as ClientSignature};
+use std::{collections::BTreeMap, net::SocketAddr, time::Duration};
+use threshold_crypto::{PublicKey, Signature};
+use tiny_keccak::{Hasher, Sha3};
 use xor_name::{Prefix, XorName};
 
 /// Message to query the network infrastructure.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[non_exhaustive]
 pub enum Query {
     /// Message to request information about the section that matches the given name.
     GetSectionRequest(XorName),
+    /// Same as `GetSectionRequest`, except that `GetSectionResponse::Unchanged` is
+    /// returned if the response would have the given etag, i.e. is already known.
+    GetSectionRequestIfChanged {
+        /// Name to get the section of.
+        name: XorName,
+        /// Etag of the last response received, see `GetSectionResponse::etag`.
+        etag: [u8; 32],
+    },
     /// Response to `GetSectionRequest`.
     GetSectionResponse(GetSectionResponse),
+    /// Message to request statistics about the section that matches the given name.
+    GetSectionStats(XorName),
+    /// Response to `GetSectionStats`.
+    GetSectionStatsResponse(SignedSectionStats),
+    /// Message to request the capabilities of the section that matches the given name.
+    GetSectionCapabilities(XorName),
+    /// Response to `GetSectionCapabilities`.
+    GetSectionCapabilitiesResponse(SectionCapabilities),
+    /// Message to request whether the section that matches the given name
+    /// is currently accepting new nodes.
+    GetJoinStatus(XorName),
+    /// Response to `GetJoinStatus`.
+    GetJoinStatusResponse(JoinStatus),
+    /// A query signed by the requesting client, so that sections can
+    /// rate-limit or prioritise infrastructure queries per client.
+    Signed {
+        /// The query.
+        query: Box<Query>,
+        /// Key of the requesting client.
+        requester: ClientPublicKey,
+        /// The client's signature over the query.
+        signature: ClientSignature,
+    },
+    /// Response to a `Signed` query, echoing the key of the requester
+    /// so that it can't be reflected to any other client.
+    SignedResponse {
+        /// The response.
+        response: Box<Query>,
+        /// Key of the client which signed the query.
+        requester: ClientPublicKey,
+    },
+    /// Message to request the segment of the key chain of the section that matches
+    /// the given name, connecting two of its keys, e.g. to resolve a `SectionKeyMismatch`.
+    GetKeyChain {
+        /// Name to get the key chain of the section of.
+        name: XorName,
+        /// The key known to the requester.
+        from_key: PublicKey,
+        /// The key to connect it to, e.g. the current key of the section.
+        to_key: PublicKey,
+    },
+    /// Response to `GetKeyChain`.
+    GetKeyChainResponse(GetKeyChainResponse),
+    /// Message to request statistics about the node messages relayed by the section
+    /// that matches the given name.
+    GetRelayStats(XorName),
+    /// Response to `GetRelayStats`.
+    GetRelayStatsResponse(RelayStats),
+    /// A query of a kind introduced by a newer release of the protocol, which this
+    /// release can't decode. It is never sent to the wire as such: serializing it fails.
+    #[serde(skip)]
+    Unknown {
+        /// Index of the unknown variant, as encoded on the wire.
+        kind: u16,
+        /// The undecoded payload of the query.
+        bytes: Bytes,
+    },
 }
 
 /// Information about a section.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub enum GetSectionResponse {
     /// Successful response to `GetSectionRequest`. Contains information about the requested
@@ -34,21 +107,223 @@ pub enum GetSectionResponse {
         key: PublicKey,
         /// Section elders.
         elders: BTreeMap<XorName, SocketAddr>,
+        /// Candidate endpoints of the elders, in order of preference, for clients to pick
+        /// one they can reach, e.g. over IPv6 or through a port mapping.
+        /// Elders without candidates are reachable at their address in `elders` only,
+        /// see `GetSectionResponse::endpoints`.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        endpoints: BTreeMap<XorName, Vec<Endpoint>>,
     },
     /// Response to `GetSectionRequest` containing addresses of nodes that are closer to the
     /// requested name than the recipient. The request should be repeated to these addresses.
     Redirect(Vec<SocketAddr>),
+    /// Response to `GetSectionRequestIfChanged` when the response would be the one already known.
+    Unchanged,
+}
+
+/// An endpoint a node can be reached at, along with hints for clients to tell
+/// whether they can reach it, see `GetSectionResponse::Success`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct Endpoint {
+    /// The address, whose IP version tells whether it is an IPv4 or IPv6 endpoint.
+    pub addr: SocketAddr,
+    /// The transport the node listens with at this address.
+    pub transport: Transport,
+    /// Whether the address is a port mapped by the node's router, e.g. with UPnP,
+    /// rather than one the node is bound to.
+    pub port_mapped: bool,
+}
+
+/// Transport of an `Endpoint`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Transport {
+    /// QUIC, the transport of nodes by default.
+    Quic,
+    /// Plain UDP.
+    Udp,
+}
+
+impl From<SocketAddr> for Endpoint {
+    /// Returns the QUIC endpoint the node is bound to at the address.
+    fn from(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            transport: Transport::Quic,
+            port_mapped: false,
+        }
+    }
+}
+
+impl Endpoint {
+    /// Returns whether the endpoint is an IPv6 one.
+    pub fn is_ipv6(&self) -> bool {
+        self.addr.is_ipv6()
+    }
+}
+
+impl GetSectionResponse {
+    /// Returns the candidate endpoints of each elder of a `Success` response, i.e. those
+    /// listed in `endpoints`, or else its address in `elders`. Empty for other responses.
+    pub fn endpoints(&self) -> BTreeMap<XorName, Vec<Endpoint>> {
+        match self {
+            Self::Success {
+                elders, endpoints, ..
+            } => elders
+                .iter()
+                .map(|(name, addr)| {
+                    let candidates = match endpoints.get(name) {
+                        Some(candidates) if !candidates.is_empty() => candidates.clone(),
+                        _ => vec![Endpoint::from(*addr)],
+                    };
+                    (*name, candidates)
+                })
+                .collect(),
+            Self::Redirect(_) | Self::Unchanged => BTreeMap::new(),
+        }
+    }
+
+    /// Returns the etag of the response, i.e. a hash over its content,
+    /// to be sent in a `GetSectionRequestIfChanged`.
+    pub fn etag(&self) -> crate::Result<[u8; 32]> {
+        let bytes = bincode::serialize(self).map_err(|err| {
+            crate::Error::Serialisation(format!("could not serialize section response: {}", err))
+        })?;
+        let mut hasher = Sha3::v256();
+        let mut output = [0; 32];
+        hasher.update(&bytes);
+        hasher.finalize(&mut output);
+        Ok(output)
+    }
+}
+
+/// A segment of the key chain of a section.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub enum GetKeyChainResponse {
+    /// The keys following `from_key`, up to and including `to_key`, each one signed by the
+    /// previous one. Empty if `from_key` and `to_key` are the same key.
+    Success(Vec<(PublicKey, Signature)>),
+    /// `from_key` or `to_key` is not in the key chain of the section,
+    /// or `to_key` doesn't follow `from_key`.
+    UnknownKey,
+}
+
+impl GetKeyChainResponse {
+    /// Returns the bytes a section key is expected to sign to approve the key following it.
+    /// These are the raw bytes of the key, as signed when the key is created, since the links
+    /// of the section chain predate this message and carry no domain tag.
+    pub fn signable_bytes(key: &PublicKey) -> Vec<u8> {
+        key.to_bytes().to_vec()
+    }
+
+    /// Verifies that the segment connects `from_key` to `to_key`,
+    /// each key being signed by the previous one.
+    pub fn verify(&self, from_key: &PublicKey, to_key: &PublicKey) -> bool {
+        let segment = match self {
+            Self::Success(segment) => segment,
+            Self::UnknownKey => return false,
+        };
+        let mut last_key = from_key;
+        for (key, signature) in segment {
+            if !last_key.verify(signature, Self::signable_bytes(key)) {
+                return false;
+            }
+            last_key = key;
+        }
+        last_key == to_key
+    }
+}
+
+/// Capabilities and configuration of a section, as agreed by its Elders.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SectionCapabilities {
+    /// Prefix of the section.
+    pub prefix: Prefix,
+    /// Length of the window, in seconds, within which replayed messages are rejected.
+    pub anti_replay_window_secs: u64,
+}
+
+/// Whether a section is currently accepting new nodes, and on what terms,
+/// so that prospective nodes can wait rather than make join attempts bound to be rejected.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct JoinStatus {
+    /// Prefix of the section.
+    pub prefix: Prefix,
+    /// Whether the section is currently accepting new nodes.
+    pub accepting: bool,
+    /// Expected time until the section accepts new nodes (again),
+    /// or before a join attempt is processed if it is accepting them already.
+    pub expected_wait: Duration,
+    /// Current difficulty of the resource proof required of joining nodes.
+    pub resource_proof_difficulty: u8,
+}
+
+/// Statistics about the node messages relayed by a section, see `node::NodeMessage::relay_cost_units`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct RelayStats {
+    /// Prefix of the section.
+    pub prefix: Prefix,
+    /// Number of node messages relayed by the section in `window`.
+    pub relayed_msgs: u64,
+    /// Relay cost units the section added to the messages it relayed in `window`.
+    pub relay_cost_units: u64,
+    /// The period over which the statistics were measured.
+    pub window: Duration,
+}
+
+/// Statistics about a section, as seen by its Elders.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SectionStats {
+    /// Prefix of the section.
+    pub prefix: Prefix,
+    /// Number of Elders in the section.
+    pub elder_count: u64,
+    /// Number of Adults in the section.
+    pub adult_count: u64,
+    /// Approximate number of bytes stored by the section.
+    pub stored_bytes: u64,
+    /// Number of churn events (joins, leaves, relocations) observed in `churn_window`.
+    pub churn_count: u64,
+    /// The period over which `churn_count` was measured.
+    pub churn_window: Duration,
+}
+
+/// A snapshot of `SectionStats`, signed by the section.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SignedSectionStats {
+    /// The statistics.
+    pub stats: SectionStats,
+    /// Public key of the section which signed the statistics.
+    pub key: PublicKey,
+    /// Section signature over the serialized statistics.
+    pub signature: Signature,
+}
+
+impl SignedSectionStats {
+    /// Returns the bytes the section is expected to sign for the given statistics.
+    pub fn signable_bytes(stats: &SectionStats) -> crate::Result<Vec<u8>> {
+        let bytes = bincode::serialize(stats).map_err(|err| {
+            crate::Error::Serialisation(format!("could not serialize section stats: {}", err))
+        })?;
+        Ok(signing::signable_bytes(INFRASTRUCTURE_DOMAIN, &bytes))
+    }
+
+    /// Verifies the signature over the statistics against the contained section key.
+    pub fn verify(&self) -> bool {
+        match Self::signable_bytes(&self.stats) {
+            Ok(bytes) => self.key.verify(&self.signature, bytes),
+            Err(_) => false,
+        }
+    }
 }
 
 impl Query {
     /// Convinience function to deserialize a 'Query' from bytes received over the wire.
     /// It returns an error if the bytes don't correspond to an infrastructure query.
     pub fn from(bytes: Bytes) -> crate::Result<Self> {
-        let deserialized = WireMsg::deserialize(bytes)?;
-        if let MessageType::InfrastructureQuery(query) = deserialized {
+        if let MessageType::InfrastructureQuery(query) = crate::parse(bytes)?.body {
             Ok(query)
         } else {
-            Err(crate::Error::FailedToParse(
+            Err(crate::Error::failed_to_parse(
                 "bytes as an infrastructure query message".to_string(),
             ))
         }
@@ -58,4 +333,219 @@ impl Query {
     pub fn serialize(&self) -> crate::Result<Bytes> {
         WireMsg::serialize_infrastructure_query(self)
     }
+
+    /// Returns the JSON representation of this Query, e.g. for tooling or logs.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> crate::Result<String> {
+        crate::serialisation::to_json(self, "infrastructure query")
+    }
+
+    /// Parses a Query from its JSON representation, see `to_json`.
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str) -> crate::Result<Self> {
+        crate::serialisation::from_json(json, "infrastructure query")
+    }
+
+    /// Returns the name the query is destined to, if it is a request.
+    pub fn dst_name(&self) -> Option<XorName> {
+        match self {
+            Self::GetSectionRequest(name)
+            | Self::GetSectionRequestIfChanged { name, .. }
+            | Self::GetSectionStats(name)
+            | Self::GetSectionCapabilities(name)
+            | Self::GetJoinStatus(name)
+            | Self::GetKeyChain { name, .. }
+            | Self::GetRelayStats(name) => Some(*name),
+            Self::Signed { query, .. } => query.dst_name(),
+            Self::GetSectionResponse(_)
+            | Self::GetSectionStatsResponse(_)
+            | Self::GetSectionCapabilitiesResponse(_)
+            | Self::GetJoinStatusResponse(_)
+            | Self::GetKeyChainResponse(_)
+            | Self::GetRelayStatsResponse(_)
+            | Self::SignedResponse { .. }
+            | Self::Unknown { .. } => None,
+        }
+    }
+
+    /// Returns the bytes the requesting client is expected to sign for the query.
+    pub fn signable_bytes(query: &Query) -> crate::Result<Vec<u8>> {
+        let bytes = bincode::serialize(query).map_err(|err| {
+            crate::Error::Serialisation(format!(
+                "could not serialize infrastructure query: {}",
+                err
+            ))
+        })?;
+        Ok(signing::signable_bytes(INFRASTRUCTURE_DOMAIN, &bytes))
+    }
+
+    /// Signs the query with the requesting client's keypair.
+    pub fn signed(query: Query, keypair: &Keypair) -> crate::Result<Self> {
+        let signature = keypair.sign(&Self::signable_bytes(&query)?);
+        Ok(Self::Signed {
+            query: Box::new(query),
+            requester: keypair.public_key(),
+            signature,
+        })
+    }
+
+    /// Returns the key of the requesting client if this is a `Signed` query
+    /// and the client's signature over it is valid.
+    pub fn verified_requester(&self) -> Option<ClientPublicKey> {
+        match self {
+            Self::Signed {
+                query,
+                requester,
+                signature,
+            } => {
+                let bytes = Self::signable_bytes(query).ok()?;
+                requester.verify(signature, bytes).ok()?;
+                Some(*requester)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use threshold_crypto::SecretKey;
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json() -> Result<()> {
+        let query = Query::GetSectionRequestIfChanged {
+            name: XorName::random(),
+            etag: [7; 32],
+        };
+        assert_eq!(Query::from_json(&query.to_json()?)?, query);
+        assert!(Query::from_json("{}").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn signed_section_stats_verification() -> Result<()> {
+        let secret_key = SecretKey::random();
+        let stats = SectionStats {
+            prefix: Prefix::default(),
+            elder_count: 7,
+            adult_count: 42,
+            stored_bytes: 1024,
+            churn_count: 3,
+            churn_window: Duration::from_secs(3600),
+        };
+        let mut signed = SignedSectionStats {
+            signature: secret_key.sign(SignedSectionStats::signable_bytes(&stats)?),
+            key: secret_key.public_key(),
+            stats,
+        };
+        assert!(signed.verify());
+
+        signed.stats.adult_count += 1;
+        assert!(!signed.verify());
+
+        Ok(())
+    }
+
+    #[test]
+    fn key_chain_verification() {
+        let keys: Vec<_> = (0..4).map(|_| SecretKey::random()).collect();
+        let segment: Vec<_> = keys
+            .windows(2)
+            .map(|pair| {
+                // Links are signed over the raw key bytes, as in the section chain.
+                let key = pair[1].public_key();
+                (key, pair[0].sign(key.to_bytes()))
+            })
+            .collect();
+        let from_key = keys[0].public_key();
+        let to_key = keys[3].public_key();
+
+        let response = GetKeyChainResponse::Success(segment.clone());
+        assert!(response.verify(&from_key, &to_key));
+        assert!(!response.verify(&keys[1].public_key(), &to_key));
+        assert!(!response.verify(&from_key, &keys[2].public_key()));
+        assert!(GetKeyChainResponse::Success(vec![]).verify(&from_key, &from_key));
+        assert!(!GetKeyChainResponse::UnknownKey.verify(&from_key, &to_key));
+
+        let mut tampered = segment;
+        tampered[1].1 = keys[0].sign(GetKeyChainResponse::signable_bytes(&tampered[1].0));
+        assert!(!GetKeyChainResponse::Success(tampered).verify(&from_key, &to_key));
+    }
+
+    #[test]
+    fn section_response_etag() -> Result<()> {
+        let response = GetSectionResponse::Success {
+            prefix: Prefix::default(),
+            key: SecretKey::random().public_key(),
+            elders: BTreeMap::new(),
+            endpoints: BTreeMap::new(),
+        };
+        let etag = response.etag()?;
+        assert_eq!(etag, response.etag()?);
+
+        let other = GetSectionResponse::Redirect(vec![]);
+        assert_ne!(etag, other.etag()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn elder_endpoints() -> Result<()> {
+        let (name, other) = (XorName::random(), XorName::random());
+        let addr: SocketAddr = ([127, 0, 0, 1], 12000).into();
+        let mapped = Endpoint {
+            addr: "[::1]:12001".parse()?,
+            transport: Transport::Udp,
+            port_mapped: true,
+        };
+        let response = GetSectionResponse::Success {
+            prefix: Prefix::default(),
+            key: SecretKey::random().public_key(),
+            elders: vec![(name, addr), (other, addr)].into_iter().collect(),
+            endpoints: vec![(name, vec![mapped, Endpoint::from(addr)])]
+                .into_iter()
+                .collect(),
+        };
+        let endpoints = response.endpoints();
+        assert_eq!(endpoints[&name], vec![mapped, Endpoint::from(addr)]);
+        assert_eq!(endpoints[&other], vec![Endpoint::from(addr)]);
+        assert!(mapped.is_ipv6());
+
+        let query = Query::GetSectionResponse(response);
+        assert_eq!(Query::from(query.serialize()?)?, query);
+
+        Ok(())
+    }
+
+    #[test]
+    fn signed_query_verification() -> Result<()> {
+        let keypair = Keypair::new_ed25519(&mut rand::thread_rng());
+        let query = Query::signed(Query::GetSectionRequest(XorName::random()), &keypair)?;
+        assert_eq!(query.verified_requester(), Some(keypair.public_key()));
+
+        let deserialized = Query::from(query.serialize()?)?;
+        assert_eq!(
+            deserialized.verified_requester(),
+            Some(keypair.public_key())
+        );
+
+        if let Query::Signed {
+            query, signature, ..
+        } = deserialized
+        {
+            let other = Keypair::new_ed25519(&mut rand::thread_rng());
+            let forged = Query::Signed {
+                query,
+                requester: other.public_key(),
+                signature,
+            };
+            assert_eq!(forged.verified_requester(), None);
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,166 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::{Error, MessageType, Result, WireMsg};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use threshold_crypto::{
+    Ciphertext, DecryptionShare, PublicKey as BlsPublicKey, PublicKeySet as BlsPublicKeySet,
+    SecretKey as BlsSecretKey, SecretKeyShare as BlsSecretKeyShare,
+};
+use xor_name::XorName;
+
+/// A message wrapped in a layer of encryption, which only the intended
+/// intermediary (a node or a section) can peel off. Peeling reveals the next hop
+/// the inner message shall be forwarded to, and the serialized inner message, which
+/// can itself be a `LayeredDst`, thus allowing multiple layers of addressing.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LayeredDst {
+    sealed: Ciphertext,
+}
+
+// The content sealed in each layer.
+#[derive(Serialize, Deserialize)]
+struct InnerEnvelope {
+    next_hop: XorName,
+    #[serde(with = "serde_bytes")]
+    msg: Vec<u8>,
+}
+
+impl LayeredDst {
+    /// Wraps the serialized message in a new layer which can only be peeled by
+    /// the holder(s) of the secret key corresponding to `peeler_key`.
+    /// After peeling, the message is to be forwarded to `next_hop`.
+    pub fn wrap(msg: Bytes, next_hop: XorName, peeler_key: &BlsPublicKey) -> Result<Self> {
+        let inner = InnerEnvelope {
+            next_hop,
+            msg: msg.to_vec(),
+        };
+        let inner_bytes = rmp_serde::to_vec_named(&inner).map_err(|err| {
+            Error::Serialisation(format!(
+                "could not serialize layered message envelope with Msgpack: {}",
+                err
+            ))
+        })?;
+
+        Ok(Self {
+            sealed: peeler_key.encrypt(inner_bytes),
+        })
+    }
+
+    /// Peels off this layer using the secret key of a single peeler,
+    /// returning the next hop and the serialized inner message.
+    pub fn peel(&self, secret_key: &BlsSecretKey) -> Result<(XorName, Bytes)> {
+        let inner_bytes = secret_key.decrypt(&self.sealed).ok_or_else(|| {
+            Error::FailedToDecrypt("layered message with the provided secret key".to_string())
+        })?;
+        Self::open(&inner_bytes)
+    }
+
+    /// Creates this Elder's decryption share, to be combined with the other
+    /// Elders' shares in `peel_with_shares` when the peeler is a section.
+    pub fn decryption_share(
+        &self,
+        secret_key_share: &BlsSecretKeyShare,
+    ) -> Result<DecryptionShare> {
+        secret_key_share
+            .decrypt_share(&self.sealed)
+            .ok_or_else(|| Error::FailedToDecrypt("layered message is not valid".to_string()))
+    }
+
+    /// Peels off this layer by combining the decryption shares of the section's Elders,
+    /// returning the next hop and the serialized inner message.
+    pub fn peel_with_shares(
+        &self,
+        key_set: &BlsPublicKeySet,
+        shares: &BTreeMap<usize, DecryptionShare>,
+    ) -> Result<(XorName, Bytes)> {
+        if !self.sealed.verify() {
+            return Err(Error::FailedToDecrypt(
+                "layered message is not valid".to_string(),
+            ));
+        }
+        let inner_bytes = key_set.decrypt(shares, &self.sealed).map_err(|err| {
+            Error::FailedToDecrypt(format!("could not combine decryption shares: {}", err))
+        })?;
+        Self::open(&inner_bytes)
+    }
+
+    /// Convinience function to deserialize a 'LayeredDst' from bytes received over the wire.
+    /// It returns an error if the bytes don't correspond to a layered message.
+    pub fn from(bytes: Bytes) -> Result<Self> {
+        if let MessageType::Layered(msg) = crate::parse(bytes)?.body {
+            Ok(msg)
+        } else {
+            Err(Error::failed_to_parse(
+                "bytes as a layered message".to_string(),
+            ))
+        }
+    }
+
+    /// serialize this LayeredDst into bytes ready to be sent over the wire.
+    pub fn serialize(&self) -> Result<Bytes> {
+        WireMsg::serialize_layered_msg(self)
+    }
+
+    // Deserializes the decrypted content of a layer.
+    fn open(inner_bytes: &[u8]) -> Result<(XorName, Bytes)> {
+        let inner: InnerEnvelope = rmp_serde::from_slice(inner_bytes).map_err(|err| {
+            Error::failed_to_parse(format!("Layered message envelope as Msgpack: {:?}", err))
+        })?;
+        Ok((inner.next_hop, Bytes::from(inner.msg)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use threshold_crypto::SecretKeySet;
+
+    #[test]
+    fn wrap_and_peel_layers() -> Result<()> {
+        let first_peeler = BlsSecretKey::random();
+        let second_peeler = SecretKeySet::random(1, &mut rand::thread_rng());
+        let first_hop = XorName::random();
+        let final_dst = XorName::random();
+
+        let msg = WireMsg::new_ping_msg().serialize()?;
+        let inner = LayeredDst::wrap(
+            msg.clone(),
+            final_dst,
+            &second_peeler.public_keys().public_key(),
+        )?;
+        let outer = LayeredDst::wrap(inner.serialize()?, first_hop, &first_peeler.public_key())?;
+
+        let serialized = outer.serialize()?;
+        let (next_hop, inner_bytes) = LayeredDst::from(serialized)?.peel(&first_peeler)?;
+        assert_eq!(next_hop, first_hop);
+
+        let inner = LayeredDst::from(inner_bytes)?;
+        assert!(inner.peel(&first_peeler).is_err());
+        let shares = (0..2)
+            .map(|i| {
+                inner
+                    .decryption_share(&second_peeler.secret_key_share(i))
+                    .map(|share| (i, share))
+            })
+            .collect::<crate::Result<BTreeMap<_, _>>>()?;
+        let (next_hop, peeled) = inner.peel_with_shares(&second_peeler.public_keys(), &shares)?;
+        assert_eq!(next_hop, final_dst);
+        assert_eq!(peeled, msg);
+        assert_eq!(
+            WireMsg::deserialize(peeled)?.to_message()?,
+            MessageType::Ping
+        );
+
+        Ok(())
+    }
+}
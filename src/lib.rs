@@ -7,34 +7,175 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
+#[cfg(feature = "client-msgs")]
 pub mod client;
+pub mod compat;
 mod errors;
+pub mod flow;
+#[cfg(feature = "fuzzing")]
+mod fuzzing;
+#[cfg(feature = "infrastructure")]
 pub mod infrastructure;
+pub mod layered;
+pub mod limits;
+mod msg_id;
+pub mod multipart;
+#[cfg(feature = "node-msgs")]
 pub mod node;
+pub mod outbox;
+pub mod processing;
 mod serialisation;
+pub mod signing;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod util;
 
 use bytes::Bytes;
 pub use errors::{Error, Result};
-pub use serialisation::WireMsg;
+use limits::MAX_MESSAGE_SIZE;
+pub use msg_id::{FlowId, MessageId, FLOW_ID_LEN};
+#[cfg(feature = "codec")]
+pub use serialisation::WireMsgCodec;
+pub use serialisation::{Compression, Encoding, MessageKind, MsgHeader, SizeClass, WireMsg};
+use threshold_crypto::PublicKey as BlsPublicKey;
+use xor_name::XorName;
 
 /// Type of message
 #[derive(PartialEq, Debug)]
 #[allow(clippy::large_enum_variant)]
 pub enum MessageType {
     Ping,
+    #[cfg(feature = "infrastructure")]
     InfrastructureQuery(infrastructure::Query),
+    #[cfg(feature = "client-msgs")]
     ClientMessage(client::Message),
+    #[cfg(feature = "node-msgs")]
     NodeMessage(node::NodeMessage),
+    Layered(layered::LayeredDst),
+    /// A part of a message split by `WireMsg::serialize_multipart`.
+    Multipart(multipart::Part),
+    /// Several messages coalesced into a single frame, e.g. the acks and events sent by an
+    /// Elder to the same peer, each serialized with its own header. Batches can't be nested.
+    Batch(Vec<MessageType>),
+    /// Credits granted to the peer to send messages back, see `flow::FlowCredits`.
+    FlowCredit(flow::FlowCredit),
+}
+
+/// A message parsed from the bytes received over the wire.
+#[derive(PartialEq, Debug)]
+pub struct Parsed {
+    /// The information found in the header of the message.
+    pub header: MsgHeader,
+    /// The deserialized message.
+    pub body: MessageType,
+}
+
+/// Parses the bytes received over the wire, returning the message
+/// along with its header information, so consumers can dispatch
+/// on the message without deserializing it again.
+/// It returns an error if the bytes, or the payload once decompressed, exceed `MAX_MESSAGE_SIZE`.
+pub fn parse(bytes: Bytes) -> Result<Parsed> {
+    let wire_msg = WireMsg::deserialize(bytes)?;
+    Ok(Parsed {
+        header: wire_msg.header(),
+        body: wire_msg.to_message()?,
+    })
 }
 
 impl MessageType {
     /// serialize the message type into bytes ready to be sent over the wire.
+    /// It returns an error if the serialized message would exceed `MAX_MESSAGE_SIZE`.
     pub fn serialize(&self) -> Result<Bytes> {
+        self.serialize_with_limit(MAX_MESSAGE_SIZE)
+    }
+
+    /// Same as `serialize`, with the given max size of the serialized message.
+    pub fn serialize_with_limit(&self, limit: usize) -> Result<Bytes> {
+        self.to_wire_msg(Encoding::MsgPack)?
+            .serialize_with_limit(limit)
+    }
+
+    /// Same as `serialize`, with the payload encoded with the given encoding.
+    /// The recipient picks the encoding up from the header when deserializing the message.
+    pub fn serialize_with_encoding(&self, encoding: Encoding) -> Result<Bytes> {
+        self.to_wire_msg(encoding)?.serialize()
+    }
+
+    /// Serializes the message once for all the given destinations, i.e. their names and
+    /// section keys, returning the message serialized for each destination, in the same order.
+    /// Only the header differs from one destination to the other.
+    pub fn serialize_for_destinations(
+        &self,
+        destinations: &[(XorName, BlsPublicKey)],
+    ) -> Result<Vec<Bytes>> {
+        let mut wire_msg = self.to_wire_msg(Encoding::MsgPack)?;
+        destinations
+            .iter()
+            .map(|(dst, dst_section_pk)| {
+                wire_msg.set_dest(*dst);
+                wire_msg.set_dest_section_pk(*dst_section_pk);
+                wire_msg.serialize()
+            })
+            .collect()
+    }
+
+    // Creates the WireMsg holding the message serialized with the given encoding.
+    fn to_wire_msg(&self, encoding: Encoding) -> Result<WireMsg> {
+        let wire_msg = match self {
+            Self::Ping => WireMsg::new_ping_msg(),
+            #[cfg(feature = "infrastructure")]
+            Self::InfrastructureQuery(query) => {
+                WireMsg::new_infrastructure_query_with_encoding(query, encoding)?
+            }
+            #[cfg(feature = "client-msgs")]
+            Self::ClientMessage(msg) => WireMsg::new_client_msg_with_encoding(msg, encoding)?,
+            #[cfg(feature = "node-msgs")]
+            Self::NodeMessage(msg) => WireMsg::new_node_msg_with_encoding(msg, encoding)?,
+            Self::Layered(msg) => WireMsg::new_layered_msg_with_encoding(msg, encoding)?,
+            Self::Multipart(part) => WireMsg::new_multipart_part_with_encoding(part, encoding)?,
+            Self::Batch(msgs) => WireMsg::new_batch_with_encoding(msgs, encoding)?,
+            Self::FlowCredit(credit) => WireMsg::new_flow_credit_with_encoding(credit, encoding)?,
+        };
+        Ok(wire_msg)
+    }
+
+    /// Returns the kind of the message, e.g. to classify and count traffic.
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Self::Ping => MessageKind::Ping,
+            #[cfg(feature = "infrastructure")]
+            Self::InfrastructureQuery(_) => MessageKind::InfrastructureQuery,
+            #[cfg(feature = "client-msgs")]
+            Self::ClientMessage(_) => MessageKind::ClientMessage,
+            #[cfg(feature = "node-msgs")]
+            Self::NodeMessage(_) => MessageKind::NodeMessage,
+            Self::Layered(_) => MessageKind::LayeredMessage,
+            Self::Multipart(_) => MessageKind::Multipart,
+            Self::Batch(_) => MessageKind::Batch,
+            Self::FlowCredit(_) => MessageKind::FlowCredit,
+        }
+    }
+
+    /// Returns the name of the node or section the message originates from, if known from
+    /// the message itself, e.g. to classify and count routing traffic. Only node messages
+    /// carry it, if set by their sender, see `node::NodeMessage::with_src`.
+    pub fn src(&self) -> Option<XorName> {
+        match self {
+            #[cfg(feature = "node-msgs")]
+            Self::NodeMessage(msg) => msg.src,
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the destination of the message, if known from the message itself.
+    /// For the destination a message is sent to, see the header's `MsgHeader::dst`.
+    pub fn dst(&self) -> Option<XorName> {
         match self {
-            Self::Ping => WireMsg::new_ping_msg().serialize(),
-            Self::InfrastructureQuery(query) => WireMsg::serialize_infrastructure_query(query),
-            Self::ClientMessage(msg) => WireMsg::serialize_client_msg(msg),
-            Self::NodeMessage(msg) => WireMsg::serialize_node_msg(msg),
+            #[cfg(feature = "client-msgs")]
+            Self::ClientMessage(msg) => msg.dst_name(),
+            #[cfg(feature = "node-msgs")]
+            Self::NodeMessage(msg) => msg.dst,
+            _ => None,
         }
     }
 }
@@ -0,0 +1,55 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Maximum sizes of messages and of their parts, and max number of hops.
+//!
+//! These are the limits checked when serializing and deserializing messages, published
+//! so that every implementation of the protocol can plan its capacity against the same values.
+
+/// Default max size, in bytes, of a serialized message, as enforced by `WireMsg::serialize`
+/// and `WireMsg::deserialize`. It also bounds the size of a payload once decompressed,
+/// so that a small compressed payload can't make the recipient allocate arbitrary memory.
+pub const MAX_MESSAGE_SIZE: usize = 32 * 1024 * 1024;
+
+/// Max size, in bytes, of the payload of a message of the `SizeClass::Small` class.
+pub const MAX_SMALL_MSG_SIZE: usize = 16 * 1024;
+
+/// Max size, in bytes, of the payload of a message of the `SizeClass::Medium` class.
+pub const MAX_MEDIUM_MSG_SIZE: usize = 512 * 1024;
+
+/// Max size, in bytes, of the value of a chunk (a `Blob`) stored with a client command.
+/// This matches `sn_data_types::MAX_BLOB_SIZE_IN_BYTES`.
+pub const MAX_CHUNK_SIZE: usize = 1024 * 1024 + 10 * 1024;
+
+/// Max size, in bytes, of the metadata stored along with a Blob, see `client::BlobMetadata::size`.
+pub const MAX_BLOB_METADATA_SIZE: usize = 4 * 1024;
+
+/// Max size, in bytes, of the payload of a `client::Cmd::Echo`.
+pub const MAX_ECHO_PAYLOAD_SIZE: usize = 1024;
+
+/// Max size, in bytes, of the payload of a single page of a paged query response.
+pub const MAX_QUERY_RESPONSE_PAGE_SIZE: usize = 1024 * 1024;
+
+/// Max size, in bytes, of a query response offloaded to temporary Blobs, see
+/// `client::OffloadedResponse`. It bounds the Blobs the Elders store for a single response.
+pub const MAX_OFFLOADED_RESPONSE_SIZE: usize = 256 * 1024 * 1024;
+
+/// Default max number of times a message can be forwarded, as enforced by
+/// `WireMsg::increment_hops`, past which it is assumed to be caught in a routing loop.
+pub const MAX_HOPS: u8 = 32;
+
+#[cfg(all(test, feature = "client-msgs"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_size_matches_data_types() {
+        assert_eq!(MAX_CHUNK_SIZE as u64, sn_data_types::MAX_BLOB_SIZE_IN_BYTES);
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use hex_fmt::HexFmt;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tiny_keccak::{Hasher, Sha3};
+use xor_name::XorName;
+
+// Number of leading bytes holding the creation time of the ids generated with
+// `MessageId::new_ordered`, enough for milliseconds until the year 10889.
+const ORDERED_TIMESTAMP_LEN: usize = 6;
+
+/// Unique ID for messages.
+///
+/// This is used for deduplication: Since the network sends messages redundantly along different
+/// routes, the same message will usually arrive more than once at any given node. A message with
+/// an ID that is already in the cache will be ignored.
+///
+/// It is displayed in its short form, see `MessageId::short`, or in full hex with `{:#}`,
+/// which is also its form with human-readable serializers, e.g. JSON, and the form parsed by
+/// `FromStr`.
+#[derive(Ord, PartialOrd, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct MessageId(pub XorName);
+
+// Number of leading bytes shown in the short form of ids, i.e. 8 hex characters.
+const SHORT_LEN: usize = 4;
+
+// The form of ids with binary serializers, i.e. on the wire.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "MessageId")]
+struct WireMessageId(XorName);
+
+impl MessageId {
+    /// Generates a new `MessageId` with random content.
+    pub fn new() -> Self {
+        Self(XorName::random())
+    }
+
+    /// Generates a new `MessageId` which sorts by creation time, ULID-style: the first
+    /// `ORDERED_TIMESTAMP_LEN` bytes hold the milliseconds since the Unix epoch, big-endian,
+    /// and the rest is random. See `timestamp` to read the creation time back.
+    pub fn new_ordered() -> Self {
+        Self::new_ordered_at(SystemTime::now())
+    }
+
+    /// Generates a new `MessageId` which sorts by the given creation time, see `new_ordered`.
+    pub fn new_ordered_at(created_at: SystemTime) -> Self {
+        let millis = created_at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let mut name = XorName::random();
+        name.0[..ORDERED_TIMESTAMP_LEN]
+            .copy_from_slice(&millis.to_be_bytes()[8 - ORDERED_TIMESTAMP_LEN..]);
+        Self(name)
+    }
+
+    /// Returns the creation time embedded in an id generated with `new_ordered`,
+    /// to the millisecond. It is meaningless for ids generated otherwise.
+    pub fn timestamp(&self) -> SystemTime {
+        let mut bytes = [0; 8];
+        bytes[8 - ORDERED_TIMESTAMP_LEN..].copy_from_slice(&self.0 .0[..ORDERED_TIMESTAMP_LEN]);
+        UNIX_EPOCH + Duration::from_millis(u64::from_be_bytes(bytes))
+    }
+
+    /// Returns the time elapsed since the creation of an id generated with `new_ordered`,
+    /// as of the given time, or zero if it was created later.
+    pub fn age_at(&self, now: SystemTime) -> Duration {
+        now.duration_since(self.timestamp()).unwrap_or_default()
+    }
+
+    /// Returns the first 8 hex characters of the id, as shown in logs and `Debug` output.
+    /// It's enough to tell ids apart when grepping logs, but not to parse them back.
+    pub fn short(&self) -> String {
+        format!("{}", HexFmt(&self.0 .0[..SHORT_LEN]))
+    }
+
+    /// Generates a new based on provided id.
+    pub fn in_response_to(src: &MessageId) -> MessageId {
+        let mut hash_bytes = Vec::new();
+        let src = src.0;
+        hash_bytes.extend_from_slice(&src.0);
+
+        let mut hasher = Sha3::v256();
+        let mut output = [0; 32];
+        hasher.update(&hash_bytes);
+        hasher.finalize(&mut output);
+
+        MessageId(XorName(output))
+    }
+
+    /// Returns whether this id was generated with `in_response_to` from the provided id,
+    /// so that the chain from a request to its responses can be verified when tracing.
+    pub fn is_in_response_to(&self, src: &MessageId) -> bool {
+        *self == Self::in_response_to(src)
+    }
+
+    /// Generates a new id out of the serialized content of a message, so that the retries of
+    /// an identical cmd, or the same message sent by several Elders, share the same id.
+    pub fn from_content(content: &impl Serialize) -> crate::Result<MessageId> {
+        let bytes = rmp_serde::to_vec(content).map_err(|err| {
+            crate::Error::Serialisation(format!(
+                "could not serialize message content to derive its id: {}",
+                err
+            ))
+        })?;
+
+        let mut hasher = Sha3::v256();
+        let mut output = [0; 32];
+        hasher.update(&bytes);
+        hasher.finalize(&mut output);
+
+        Ok(MessageId(XorName(output)))
+    }
+
+    /// Generates a new based on provided sources.
+    pub fn combine(srcs: Vec<XorName>) -> MessageId {
+        let mut hash_bytes = Vec::new();
+        for src in srcs.into_iter() {
+            hash_bytes.extend_from_slice(&src.0);
+        }
+
+        let mut hasher = Sha3::v256();
+        let mut output = [0; 32];
+        hasher.update(&hash_bytes);
+        hasher.finalize(&mut output);
+
+        MessageId(XorName(output))
+    }
+
+    /// Generates a new based on provided sources, e.g. the ids of the messages aggregated,
+    /// regardless of their order, so that Elders aggregating the same messages in a different
+    /// order converge on the same id. See `combine` for the id of sources in a given order.
+    pub fn combine_unordered(mut srcs: Vec<XorName>) -> MessageId {
+        srcs.sort();
+        Self::combine(srcs)
+    }
+}
+
+impl Default for MessageId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{}", HexFmt(&self.0 .0))
+        } else {
+            write!(f, "{}", self.short())
+        }
+    }
+}
+
+impl fmt::Debug for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MessageId({})", self.short())
+    }
+}
+
+impl FromStr for MessageId {
+    type Err = crate::Error;
+
+    /// Parses an id from its full hex form, as displayed with `{:#}`.
+    fn from_str(s: &str) -> crate::Result<Self> {
+        let invalid = || crate::Error::failed_to_parse(format!("{:?} as a message id", s));
+        if s.len() != 2 * xor_name::XOR_NAME_LEN || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let mut name = XorName::default();
+        for (byte, hex) in name.0.iter_mut().zip(s.as_bytes().chunks(2)) {
+            let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(hex, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(name))
+    }
+}
+
+impl Serialize for MessageId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(&format_args!("{:#}", self))
+        } else {
+            WireMessageId(self.0).serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            s.parse().map_err(D::Error::custom)
+        } else {
+            Ok(Self(WireMessageId::deserialize(deserializer)?.0))
+        }
+    }
+}
+
+/// Id of a logical operation, e.g. a client cmd, which follows it across all the messages it
+/// causes: the client message starting it, the node cmds proxying it, and the responses and
+/// errors coming back. Unlike the `MessageId`, which is unique per message, it is carried
+/// unchanged by every hop, in the header of the `WireMsg`, see `WireMsg::set_flow_id`, so that
+/// a single id can be grepped for in the logs of all the nodes involved.
+#[derive(Ord, PartialOrd, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct FlowId(pub [u8; FLOW_ID_LEN]);
+
+/// Number of bytes of a `FlowId`.
+pub const FLOW_ID_LEN: usize = 16;
+
+impl FlowId {
+    /// Generates a new `FlowId` with random content.
+    pub fn new() -> Self {
+        Self::from(MessageId::new())
+    }
+
+    /// Returns the first 8 hex characters of the id, as shown in logs and `Debug` output.
+    pub fn short(&self) -> String {
+        format!("{}", HexFmt(&self.0[..SHORT_LEN]))
+    }
+}
+
+impl Default for FlowId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Starts a flow at the first message of the operation, e.g. the client cmd or query,
+/// so that the flow can be told from the id of that message.
+impl From<MessageId> for FlowId {
+    fn from(msg_id: MessageId) -> Self {
+        let mut id = [0; FLOW_ID_LEN];
+        id.copy_from_slice(&msg_id.0 .0[..FLOW_ID_LEN]);
+        Self(id)
+    }
+}
+
+impl fmt::Display for FlowId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{}", HexFmt(&self.0))
+        } else {
+            write!(f, "{}", self.short())
+        }
+    }
+}
+
+impl fmt::Debug for FlowId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FlowId({})", self.short())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn deterministic_ids() -> Result<()> {
+        let content = ("cmd", vec![1, 2, 3]);
+        let id = MessageId::from_content(&content)?;
+        assert_eq!(MessageId::from_content(&content)?, id);
+        assert_ne!(MessageId::from_content(&("cmd", vec![1, 2]))?, id);
+
+        let (a, b) = (XorName::random(), XorName::random());
+        assert_ne!(
+            MessageId::combine(vec![a, b]),
+            MessageId::combine(vec![b, a])
+        );
+        assert_eq!(
+            MessageId::combine_unordered(vec![a, b]),
+            MessageId::combine_unordered(vec![b, a])
+        );
+        assert_eq!(
+            MessageId::combine_unordered(vec![a, b]),
+            MessageId::combine(vec![a.min(b), a.max(b)])
+        );
+        assert_ne!(
+            MessageId::combine_unordered(vec![a, b]),
+            MessageId::combine_unordered(vec![a])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn text_forms() -> Result<()> {
+        let id = MessageId(XorName([0xab; 32]));
+        assert_eq!(id.short(), "abababab");
+        assert_eq!(format!("{}", id), "abababab");
+        assert_eq!(format!("{:?}", id), "MessageId(abababab)");
+        assert_eq!(format!("{:#}", id), "ab".repeat(32));
+
+        let id = MessageId::new();
+        assert_eq!(format!("{:#}", id).parse::<MessageId>()?, id);
+        assert!(id.short().parse::<MessageId>().is_err());
+        assert!("zz".repeat(32).parse::<MessageId>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn ordered_ids() {
+        let created_at = UNIX_EPOCH + Duration::from_millis(1_634_000_000_123);
+        let first = MessageId::new_ordered_at(created_at);
+        let second = MessageId::new_ordered_at(created_at + Duration::from_millis(1));
+        assert!(first < second);
+        assert_eq!(first.timestamp(), created_at);
+
+        let now = created_at + Duration::from_secs(5);
+        assert_eq!(first.age_at(now), Duration::from_secs(5));
+        assert_eq!(first.age_at(UNIX_EPOCH), Duration::default());
+    }
+}
@@ -0,0 +1,389 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::{limits::MAX_MESSAGE_SIZE, Error, MessageId, MessageType, Result, WireMsg};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// A part of a serialized message too large to be sent in a single frame,
+/// see `WireMsg::serialize_multipart`. All the parts of a message share its `MessageId`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Part {
+    /// Id shared by all the parts of the message.
+    pub msg_id: MessageId,
+    /// Index of this part, in the order the parts are to be reassembled.
+    pub index: u32,
+    /// Total number of parts of the message.
+    pub of: u32,
+    /// The bytes of the serialized message this part carries.
+    #[serde(with = "serde_bytes")]
+    pub payload: Vec<u8>,
+}
+
+impl Part {
+    /// Convinience function to deserialize a 'Part' from bytes received over the wire.
+    /// It returns an error if the bytes don't correspond to a part of a multipart message.
+    pub fn from(bytes: Bytes) -> Result<Self> {
+        if let MessageType::Multipart(part) = crate::parse(bytes)?.body {
+            Ok(part)
+        } else {
+            Err(Error::failed_to_parse(
+                "bytes as a part of a multipart message".to_string(),
+            ))
+        }
+    }
+
+    /// serialize this Part into bytes ready to be sent over the wire.
+    pub fn serialize(&self) -> Result<Bytes> {
+        WireMsg::serialize_multipart_part(self)
+    }
+}
+
+/// Default time the parts of a message are kept for, awaiting the rest of its parts,
+/// see `MultipartAssembler::with_ttl`.
+pub const DEFAULT_MULTIPART_TTL: Duration = Duration::from_secs(60);
+/// Default max number of messages for which only some of the parts were received,
+/// see `MultipartAssembler::with_max_pending`.
+pub const DEFAULT_MAX_PENDING_MSGS: usize = 64;
+/// Default max total size, in bytes, of the parts buffered across all pending messages,
+/// see `MultipartAssembler::with_max_buffered`.
+pub const DEFAULT_MAX_BUFFERED_SIZE: usize = 4 * MAX_MESSAGE_SIZE;
+
+/// Reassembles the messages split by `WireMsg::serialize_multipart`
+/// out of their parts, which can be received in any order. The parts buffered are bounded
+/// in number of messages and in total size, and dropped if the rest of the parts of their
+/// message aren't received within the time to live.
+#[derive(Debug)]
+pub struct MultipartAssembler {
+    ttl: Duration,
+    max_pending: usize,
+    max_buffered: usize,
+    // Total size of the parts of the pending messages.
+    buffered: usize,
+    pending: BTreeMap<MessageId, PendingMsg>,
+    // Ids of the pending messages, in the order their first part was received, to expire them.
+    // An id is removed along with its message, so there are at most `max_pending` of them.
+    expiry: VecDeque<(Instant, MessageId)>,
+}
+
+#[derive(Debug)]
+struct PendingMsg {
+    of: u32,
+    size: usize,
+    parts: BTreeMap<u32, Vec<u8>>,
+}
+
+impl Default for MultipartAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MultipartAssembler {
+    /// Creates an assembler with no pending messages, and the default limits.
+    pub fn new() -> Self {
+        Self {
+            ttl: DEFAULT_MULTIPART_TTL,
+            max_pending: DEFAULT_MAX_PENDING_MSGS,
+            max_buffered: DEFAULT_MAX_BUFFERED_SIZE,
+            buffered: 0,
+            pending: BTreeMap::new(),
+            expiry: VecDeque::new(),
+        }
+    }
+
+    /// Sets the time the parts of a message are kept for, from the receipt of its first part.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets the max number of messages for which only some of the parts were received.
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending;
+        self
+    }
+
+    /// Sets the max total size, in bytes, of the parts buffered across all pending messages.
+    pub fn with_max_buffered(mut self, max_buffered: usize) -> Self {
+        self.max_buffered = max_buffered;
+        self
+    }
+
+    /// Adds a received part. Once all the parts of its message have been added, this
+    /// returns the serialized message, which can then be deserialized as usual, e.g. with `parse`.
+    pub fn add(&mut self, part: Part) -> Result<Option<Bytes>> {
+        self.add_at(part, Instant::now())
+    }
+
+    /// Adds a part received at the given instant, see `add`. A part which is inconsistent
+    /// with the parts previously added for the same message is rejected, the parts received
+    /// so far are kept. A message exceeding `MAX_MESSAGE_SIZE` is dropped. A part of a new
+    /// message is rejected while the max number of pending messages is reached, and any part
+    /// is rejected while the max total size of the parts buffered is reached.
+    pub fn add_at(&mut self, part: Part, now: Instant) -> Result<Option<Bytes>> {
+        let Part {
+            msg_id,
+            index,
+            of,
+            payload,
+        } = part;
+        if index >= of {
+            return Err(Error::failed_to_parse(format!(
+                "part {} of a message of {} parts",
+                index, of
+            )));
+        }
+        self.prune_at(now);
+
+        if !self.pending.contains_key(&msg_id) && self.pending.len() >= self.max_pending {
+            return Err(Error::TooManyPendingMsgs {
+                limit: self.max_pending,
+            });
+        }
+        let buffered = self.buffered + payload.len();
+        if buffered > self.max_buffered {
+            return Err(Error::MessageTooLarge {
+                size: buffered,
+                limit: self.max_buffered,
+            });
+        }
+
+        let ttl = self.ttl;
+        let expiry = &mut self.expiry;
+        let pending = self.pending.entry(msg_id).or_insert_with(|| {
+            expiry.push_back((now + ttl, msg_id));
+            PendingMsg {
+                of,
+                size: 0,
+                parts: BTreeMap::new(),
+            }
+        });
+        if pending.of != of {
+            return Err(Error::failed_to_parse(format!(
+                "part of a message of {} parts, where {} parts were expected",
+                of, pending.of
+            )));
+        }
+        let size = pending.size + payload.len();
+        if size > MAX_MESSAGE_SIZE {
+            self.remove(&msg_id);
+            return Err(Error::MessageTooLarge {
+                size,
+                limit: MAX_MESSAGE_SIZE,
+            });
+        }
+        if pending.parts.contains_key(&index) {
+            // Duplicate of a part already received.
+            return Ok(None);
+        }
+        pending.size = size;
+        self.buffered = buffered;
+        let _ = pending.parts.insert(index, payload);
+
+        if pending.parts.len() < of as usize {
+            return Ok(None);
+        }
+        let msg = self
+            .take(&msg_id)
+            .map(|pending| pending.parts.into_values().flatten().collect::<Vec<_>>())
+            .unwrap_or_default();
+        Ok(Some(Bytes::from(msg)))
+    }
+
+    /// Drops the parts received so far for the message, e.g. once it's given up on.
+    pub fn remove(&mut self, msg_id: &MessageId) {
+        let _ = self.take(msg_id);
+    }
+
+    /// Drops the parts of the messages which expired as of the given instant, returning
+    /// the ids of those messages.
+    pub fn prune_at(&mut self, now: Instant) -> Vec<MessageId> {
+        let mut expired = vec![];
+        while let Some((expires_at, msg_id)) = self.expiry.front().copied() {
+            if expires_at > now {
+                break;
+            }
+            self.remove(&msg_id);
+            expired.push(msg_id);
+        }
+        expired
+    }
+
+    /// Returns the number of messages for which only some of the parts were received.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the total size, in bytes, of the parts buffered across all pending messages.
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    fn take(&mut self, msg_id: &MessageId) -> Option<PendingMsg> {
+        let pending = self.pending.remove(msg_id)?;
+        self.buffered -= pending.size;
+        self.expiry.retain(|(_, id)| id != msg_id);
+        Some(pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use threshold_crypto::SecretKey;
+    use xor_name::XorName;
+
+    #[test]
+    fn multipart() -> Result<()> {
+        let msg = crate::layered::LayeredDst::wrap(
+            Bytes::from(vec![7; 10 * 1024]),
+            XorName::random(),
+            &SecretKey::random().public_key(),
+        )?;
+        let wire_msg = WireMsg::new_layered_msg(&msg)?;
+        let serialized = wire_msg.serialize()?;
+
+        let mut parts = wire_msg.serialize_multipart(1024)?;
+        assert!(parts.len() > 1);
+        parts.reverse();
+
+        let mut assembler = MultipartAssembler::new();
+        let last = parts.pop().map(Part::from).transpose()?;
+        for part in parts {
+            let part = Part::from(part)?;
+            assert_eq!(assembler.add(part.clone())?, None);
+            assert_eq!(assembler.add(part)?, None);
+        }
+        assert_eq!(assembler.pending(), 1);
+
+        let reassembled = last.map(|part| assembler.add(part)).transpose()?.flatten();
+        assert_eq!(reassembled, Some(serialized));
+        assert_eq!(assembler.pending(), 0);
+        assert_eq!(
+            WireMsg::deserialize(reassembled.unwrap_or_default())?.to_message()?,
+            MessageType::Layered(msg)
+        );
+
+        let part = Part {
+            msg_id: MessageId::new(),
+            index: 0,
+            of: 2,
+            payload: vec![0; 8],
+        };
+        assert_eq!(assembler.add(part.clone())?, None);
+        assert!(assembler
+            .add(Part {
+                index: 1,
+                of: 3,
+                ..part.clone()
+            })
+            .is_err());
+        // The inconsistent part alone is rejected, not the parts received so far.
+        assert_eq!(assembler.pending(), 1);
+        assert_eq!(
+            assembler.add(Part { index: 1, ..part })?,
+            Some(Bytes::from(vec![0; 16]))
+        );
+        assert_eq!(assembler.pending(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn bounded() -> Result<()> {
+        let now = Instant::now();
+        let mut assembler = MultipartAssembler::new()
+            .with_ttl(Duration::from_secs(30))
+            .with_max_pending(2)
+            .with_max_buffered(64);
+        let part = |msg_id, payload| Part {
+            msg_id,
+            index: 0,
+            of: 2,
+            payload,
+        };
+        let (first, second, third) = (MessageId::new(), MessageId::new(), MessageId::new());
+
+        // Parts of a new message are rejected while the max number of messages is pending...
+        assert_eq!(assembler.add_at(part(first, vec![0; 16]), now)?, None);
+        assert_eq!(assembler.add_at(part(second, vec![0; 16]), now)?, None);
+        assert!(matches!(
+            assembler.add_at(part(third, vec![0; 16]), now),
+            Err(Error::TooManyPendingMsgs { limit: 2 })
+        ));
+        assert_eq!(assembler.pending(), 2);
+
+        // ...and any part past the max total size buffered.
+        assert!(matches!(
+            assembler.add_at(
+                Part {
+                    index: 1,
+                    ..part(first, vec![0; 48])
+                },
+                now
+            ),
+            Err(Error::MessageTooLarge { limit: 64, .. })
+        ));
+        assert_eq!(assembler.buffered(), 32);
+
+        // Pending messages expire, freeing room for new ones.
+        let later = now + Duration::from_secs(10);
+        assert_eq!(assembler.add_at(part(first, vec![0; 16]), later)?, None);
+        assert_eq!(
+            assembler.add_at(
+                Part {
+                    index: 1,
+                    ..part(first, vec![0; 16])
+                },
+                later
+            )?,
+            Some(Bytes::from(vec![0; 32]))
+        );
+        // The id is received again, after its parts completed the message.
+        assert_eq!(assembler.add_at(part(first, vec![0; 16]), later)?, None);
+        assert_eq!(
+            assembler.prune_at(now + Duration::from_secs(30)),
+            vec![second]
+        );
+        assert_eq!(assembler.pending(), 1);
+        assert_eq!(assembler.buffered(), 16);
+        assert_eq!(assembler.add_at(part(third, vec![0; 16]), later)?, None);
+        assert_eq!(assembler.prune_at(later + Duration::from_secs(30)).len(), 2);
+        assert_eq!((assembler.pending(), assembler.buffered()), (0, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn single_part_msgs() -> Result<()> {
+        let now = Instant::now();
+        let mut assembler = MultipartAssembler::new().with_max_pending(2);
+        for _ in 0..1000 {
+            let part = Part {
+                msg_id: MessageId::new(),
+                index: 0,
+                of: 1,
+                payload: vec![0; 16],
+            };
+            assert_eq!(assembler.add_at(part, now)?, Some(Bytes::from(vec![0; 16])));
+        }
+        // Messages completed at once leave nothing behind to expire.
+        assert_eq!((assembler.pending(), assembler.buffered()), (0, 0));
+        assert!(assembler.expiry.is_empty());
+        assert!(assembler.prune_at(now + DEFAULT_MULTIPART_TTL).is_empty());
+
+        Ok(())
+    }
+}
@@ -7,51 +7,339 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use crate::{Error, MessageType, Result, WireMsg};
+use crate::{
+    processing::{ProcessingError, ProcessingReason},
+    Result, WireMsg,
+};
 use bytes::Bytes;
+use hex_fmt::HexFmt;
 use serde::{Deserialize, Serialize};
-use std::fmt::{self, Debug, Formatter};
+use std::{
+    fmt::{self, Debug, Formatter},
+    ops::RangeInclusive,
+};
+use tiny_keccak::{Hasher, Sha3};
+use xor_name::XorName;
 
 /// Node message sent over the network.
 // TODO: this is currently holding just bytes as a placeholder, next step
 // is to move all actual node messages structs and definitions within it.
-#[derive(Clone, Eq, Serialize, Deserialize)]
-pub struct NodeMessage(#[serde(with = "serde_bytes")] pub Vec<u8>);
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NodeMessage {
+    /// The serialized node message.
+    #[serde(with = "serde_bytes")]
+    pub payload: Vec<u8>,
+    /// Hash of the previous message sent to the same peer, if any.
+    /// This forms a per-peer hash chain, which allows detecting
+    /// dropped or reordered messages between two nodes.
+    #[serde(default)]
+    pub prev_msg_hash: Option<MsgHash>,
+    /// Sequence number of the message among the ones sent over the same connection, if any,
+    /// which allows the recipient to detect dropped messages, see `AckRange`.
+    #[serde(default)]
+    pub seq: Option<u64>,
+    /// Acknowledgement of the messages received from the peer, if any.
+    #[serde(default)]
+    pub ack: Option<AckRange>,
+    /// Relay cost units accumulated by the message on its way to the recipient, each node
+    /// relaying it adding its own cost, see `NodeMessage::add_relay_cost`. It is left out of
+    /// the hash of the message, since it changes at every hop.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay_cost_units: Option<u64>,
+    /// The error returned for a message the peer couldn't process, if any,
+    /// see `NodeMessage::create_processing_error`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processing_error: Option<NodeProcessingError>,
+    /// Name of the node or section the message originates from, if set by the sender,
+    /// e.g. for tooling to classify routing traffic without parsing the payload.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub src: Option<XorName>,
+    /// Name of the node or section the message is destined to, if set by the sender.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dst: Option<XorName>,
+}
+
+/// The error returned to the node which sent a message the recipient couldn't process,
+/// along with the hash of the message, see `NodeMessage::create_processing_error`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NodeProcessingError {
+    /// Why the message couldn't be processed, and the message itself, if attached.
+    pub error: ProcessingError,
+    /// Hash of the message which couldn't be processed.
+    pub source_hash: MsgHash,
+}
+
+/// Acknowledgement of all the messages received over a connection, up to a sequence number.
+/// It can be piggybacked on a message, or sent on its own with `NodeMessage::ack`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AckRange {
+    /// Sequence number up to which, inclusive, all the messages were received.
+    pub up_to: u64,
+}
+
+impl AckRange {
+    /// Returns the sequence numbers of the messages sent, up to `last_sent`, which are not
+    /// covered by this acknowledgement, i.e. which were dropped or are still in flight.
+    pub fn unacked(&self, last_sent: u64) -> RangeInclusive<u64> {
+        self.up_to.saturating_add(1)..=last_sent
+    }
+}
+
+/// Hash of a `NodeMessage`, see `NodeMessage::hash`.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct MsgHash(pub [u8; 32]);
+
+impl Debug for MsgHash {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "MsgHash({:<8})", HexFmt(&self.0))
+    }
+}
 
 impl NodeMessage {
     /// Creates a new instance which wraps the provided node message bytes.
     pub fn new(bytes: Bytes) -> Self {
-        Self(bytes.to_vec())
+        Self {
+            payload: bytes.to_vec(),
+            prev_msg_hash: None,
+            seq: None,
+            ack: None,
+            relay_cost_units: None,
+            processing_error: None,
+            src: None,
+            dst: None,
+        }
+    }
+
+    /// Creates a new instance which wraps the provided node message bytes,
+    /// chained to the previous message sent to the same peer.
+    pub fn chained(bytes: Bytes, prev: &NodeMessage) -> Self {
+        Self {
+            prev_msg_hash: Some(prev.hash()),
+            ..Self::new(bytes)
+        }
+    }
+
+    /// Creates a control message, with no payload, acknowledging
+    /// all the messages received from the peer up to `up_to`.
+    pub fn ack(up_to: u64) -> Self {
+        Self {
+            ack: Some(AckRange { up_to }),
+            ..Self::new(Bytes::new())
+        }
+    }
+
+    /// Creates a control message, with no payload, returning to the sender of this message
+    /// the error the recipient failed to process it with, with this message attached.
+    pub fn create_processing_error(&self, reason: ProcessingReason) -> Result<Self> {
+        let error = ProcessingError::new()
+            .with_reason(reason)
+            .with_source_message(self.serialize()?);
+        Ok(Self {
+            processing_error: Some(NodeProcessingError {
+                error,
+                source_hash: self.hash(),
+            }),
+            ..Self::new(Bytes::new())
+        })
+    }
+
+    /// Sets the sequence number of this message among the ones sent over the connection.
+    pub fn with_seq(mut self, seq: u64) -> Self {
+        self.seq = Some(seq);
+        self
+    }
+
+    /// Sets the name of the node or section the message originates from.
+    pub fn with_src(mut self, src: XorName) -> Self {
+        self.src = Some(src);
+        self
+    }
+
+    /// Sets the name of the node or section the message is destined to.
+    pub fn with_dst(mut self, dst: XorName) -> Self {
+        self.dst = Some(dst);
+        self
+    }
+
+    /// Piggybacks the acknowledgement of the messages received from the peer up to `up_to`.
+    pub fn with_ack(mut self, up_to: u64) -> Self {
+        self.ack = Some(AckRange { up_to });
+        self
+    }
+
+    /// Adds the cost of relaying this message over one hop to the units accumulated so far.
+    pub fn add_relay_cost(&mut self, units: u64) {
+        self.relay_cost_units = Some(self.relay_cost_units.unwrap_or(0).saturating_add(units));
+    }
+
+    /// Returns the hash of this message, which covers its payload and sequence number,
+    /// as well as the hash of its predecessor, if any.
+    pub fn hash(&self) -> MsgHash {
+        let mut hasher = Sha3::v256();
+        let mut output = [0; 32];
+        match &self.prev_msg_hash {
+            Some(prev) => {
+                hasher.update(&[1]);
+                hasher.update(&prev.0);
+            }
+            None => hasher.update(&[0]),
+        }
+        if let Some(seq) = self.seq {
+            hasher.update(&seq.to_be_bytes());
+        }
+        hasher.update(&self.payload);
+        hasher.finalize(&mut output);
+
+        MsgHash(output)
+    }
+
+    /// Returns whether this message directly follows the provided one in the hash chain.
+    pub fn follows(&self, prev: &NodeMessage) -> bool {
+        self.prev_msg_hash == Some(prev.hash())
+    }
+
+    /// Verifies the hash chain of the messages received from a peer, in the order received.
+    /// Returns the index of the first message which doesn't follow its predecessor,
+    /// i.e. where messages were dropped or reordered, if any.
+    pub fn first_chain_break(msgs: &[NodeMessage]) -> Option<usize> {
+        msgs.windows(2)
+            .position(|pair| !pair[1].follows(&pair[0]))
+            .map(|index| index + 1)
     }
 
     /// Convinience function to deserialize a 'NodeMessage' from bytes received over the wire.
     /// It returns an error if the bytes don't correspond to a node message.
     pub fn from(bytes: Bytes) -> Result<Self> {
-        let deserialized = WireMsg::deserialize(bytes)?;
-        if let MessageType::NodeMessage(msg) = deserialized {
-            Ok(msg)
-        } else {
-            Err(Error::FailedToParse("bytes as a node message".to_string()))
-        }
+        WireMsg::deserialize(bytes)?.into_node_msg()
     }
 
     /// serialize this NodeMessage into bytes ready to be sent over the wire.
     pub fn serialize(&self) -> Result<Bytes> {
         WireMsg::serialize_node_msg(self)
     }
-}
 
-impl PartialEq for NodeMessage {
-    fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+    /// Returns the JSON representation of this NodeMessage, e.g. for tooling or logs.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<String> {
+        crate::serialisation::to_json(self, "node message")
+    }
+
+    /// Parses a NodeMessage from its JSON representation, see `to_json`.
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str) -> Result<Self> {
+        crate::serialisation::from_json(json, "node message")
     }
 }
 
+// Summarises the message rather than dumping its payload,
+// so that node traffic can be logged and told apart.
 impl Debug for NodeMessage {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         formatter
             .debug_struct("NodeMessage")
-            .field("bytes", &self.0)
+            .field("len", &self.payload.len())
+            .field("hash", &self.hash())
+            .field("prev_msg_hash", &self.prev_msg_hash)
+            .field("seq", &self.seq)
+            .field("ack", &self.ack)
+            .field("relay_cost_units", &self.relay_cost_units)
+            .field("processing_error", &self.processing_error)
+            .field("src", &self.src)
+            .field("dst", &self.dst)
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn hash_chain() -> Result<()> {
+        let first = NodeMessage::new(Bytes::from_static(b"first"));
+        let second = NodeMessage::chained(Bytes::from_static(b"second"), &first);
+        let third = NodeMessage::chained(Bytes::from_static(b"third"), &second);
+
+        let deserialized = NodeMessage::from(third.serialize()?)?;
+        assert!(deserialized.follows(&second));
+        assert_eq!(
+            NodeMessage::first_chain_break(&[first.clone(), second.clone(), third.clone()]),
+            None
+        );
+        assert_eq!(
+            NodeMessage::first_chain_break(&[first.clone(), third.clone()]),
+            Some(1)
+        );
+        assert_eq!(
+            NodeMessage::first_chain_break(&[second, first, third]),
+            Some(1)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn seq_and_ack() -> Result<()> {
+        let msg = NodeMessage::new(Bytes::from_static(b"payload")).with_seq(7);
+        let deserialized = NodeMessage::from(msg.serialize()?)?;
+        assert_eq!(deserialized.seq, Some(7));
+        assert_ne!(msg.hash(), msg.clone().with_seq(8).hash());
+
+        let ack = NodeMessage::from(NodeMessage::ack(4).serialize()?)?;
+        assert!(ack.payload.is_empty());
+        let ack = ack.ack.ok_or_else(|| anyhow::anyhow!("Missing ack"))?;
+        assert_eq!(ack.unacked(7), 5..=7);
+        assert!(ack.unacked(4).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn relay_cost() -> Result<()> {
+        let mut msg = NodeMessage::new(Bytes::from_static(b"payload")).with_seq(7);
+        let hash = msg.hash();
+        msg.add_relay_cost(3);
+        msg.add_relay_cost(u64::MAX);
+
+        let deserialized = NodeMessage::from(msg.serialize()?)?;
+        assert_eq!(deserialized.relay_cost_units, Some(u64::MAX));
+        assert_eq!(deserialized.hash(), hash);
+
+        Ok(())
+    }
+
+    #[test]
+    fn processing_error() -> Result<()> {
+        let msg = NodeMessage::new(Bytes::from_static(b"payload")).with_seq(7);
+        let reason = ProcessingReason::NotResponsible {
+            current_responsible: vec![(XorName::random(), ([127, 0, 0, 1], 12000).into())],
+        };
+        let reply = msg.create_processing_error(reason.clone())?;
+        assert!(reply.payload.is_empty());
+
+        let reply = NodeMessage::from(reply.serialize()?)?;
+        let error = reply
+            .processing_error
+            .ok_or_else(|| anyhow::anyhow!("Missing processing error"))?;
+        assert_eq!(error.source_hash, msg.hash());
+        assert_eq!(error.error.reason, Some(reason));
+        let source = error
+            .error
+            .source_message
+            .ok_or_else(|| anyhow::anyhow!("Missing source message"))?;
+        assert_eq!(NodeMessage::from(Bytes::from(source))?, msg);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json() -> Result<()> {
+        let first = NodeMessage::new(Bytes::from_static(b"first"));
+        let second = NodeMessage::chained(Bytes::from_static(b"second"), &first);
+        assert_eq!(NodeMessage::from_json(&second.to_json()?)?, second);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,417 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! A queue of serialized messages awaiting to be sent, independent of the transport used.
+
+use crate::{MessageId, MessageKind, Result, WireMsg};
+use bytes::Bytes;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    time::{Duration, SystemTime},
+};
+
+/// Default number of messages which can be queued per destination, see `Outbox::new`.
+pub const DEFAULT_OUTBOX_CAPACITY: usize = 1024;
+/// Default number of times a message is sent before it is given up on, see `Outbox::retry_at`.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+/// Default delay before the first retry of a message, doubled for each further retry.
+pub const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Priority of a queued message. Messages of a higher priority are sent first,
+/// messages of the same priority in the order they were queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// E.g. replication or other background traffic.
+    Low,
+    /// Most messages.
+    Normal,
+    /// E.g. responses, or messages related to the running of the network.
+    High,
+}
+
+/// Whether a message was queued, see `Outbox::push`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pushed {
+    /// The message was queued.
+    Queued,
+    /// A message with the same id is already queued for the destination, it was dropped.
+    Duplicate,
+    /// The queue of the destination is full, the message was dropped.
+    /// The sender is to slow down, e.g. stop reading from the peer it relays messages for.
+    Full,
+}
+
+/// A serialized message queued for a destination, see `Outbox`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outgoing {
+    /// The serialized `WireMsg`.
+    pub bytes: Bytes,
+    /// Id of the message, if carried by its header.
+    pub msg_id: Option<MessageId>,
+    /// Priority the message was queued with.
+    pub priority: Priority,
+    /// Time after which the message is dropped instead of sent, if set in its header.
+    pub expires_at: Option<SystemTime>,
+    /// Number of times the message was sent so far.
+    pub attempts: u32,
+    kind: MessageKind,
+    // Time before which the message isn't to be sent again, once retried.
+    not_before: Option<SystemTime>,
+}
+
+impl Outgoing {
+    // Returns the id the message is deduplicated by, if any. The parts of a multipart
+    // message share the id of the message, so they aren't deduplicated.
+    fn dedup_id(&self) -> Option<MessageId> {
+        match self.kind {
+            MessageKind::Multipart => None,
+            _ => self.msg_id,
+        }
+    }
+
+    fn is_expired_at(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    fn is_ready_at(&self, now: SystemTime) -> bool {
+        self.not_before.is_none_or(|not_before| not_before <= now)
+    }
+}
+
+/// Queues the serialized messages awaiting to be sent, per destination, e.g. the name or
+/// address of a peer, so that the transport can send them as it sees fit: by priority,
+/// dropping duplicates and expired messages, retrying failed sends with a backoff, and
+/// bounding the number of messages queued per destination.
+/// It is created with `Outbox::new` and the `with_` functions.
+#[derive(Debug)]
+pub struct Outbox<D> {
+    capacity: usize,
+    max_attempts: u32,
+    retry_backoff: Duration,
+    queues: BTreeMap<D, Queue>,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    lanes: BTreeMap<Priority, VecDeque<Outgoing>>,
+    ids: BTreeSet<MessageId>,
+}
+
+impl Queue {
+    fn len(&self) -> usize {
+        self.lanes.values().map(VecDeque::len).sum()
+    }
+
+    fn push_back(&mut self, outgoing: Outgoing) {
+        self.lanes
+            .entry(outgoing.priority)
+            .or_default()
+            .push_back(outgoing);
+    }
+
+    fn forget(&mut self, outgoing: &Outgoing) {
+        if let Some(msg_id) = outgoing.dedup_id() {
+            let _ = self.ids.remove(&msg_id);
+        }
+    }
+
+    // Drops the expired messages of all the lanes, so that they don't count towards the capacity.
+    fn purge_at(&mut self, now: SystemTime) {
+        let ids = &mut self.ids;
+        for lane in self.lanes.values_mut() {
+            lane.retain(|outgoing| {
+                let expired = outgoing.is_expired_at(now);
+                if let (true, Some(msg_id)) = (expired, outgoing.dedup_id()) {
+                    let _ = ids.remove(&msg_id);
+                }
+                !expired
+            });
+        }
+        self.lanes.retain(|_, lane| !lane.is_empty());
+    }
+
+    fn pop_at(&mut self, now: SystemTime) -> Option<Outgoing> {
+        self.purge_at(now);
+        let outgoing = self.lanes.values_mut().rev().find_map(|lane| {
+            let index = lane.iter().position(|outgoing| outgoing.is_ready_at(now))?;
+            lane.remove(index)
+        })?;
+        self.forget(&outgoing);
+        Some(outgoing)
+    }
+}
+
+impl<D: Ord + Clone> Outbox<D> {
+    /// Creates a new instance, queuing at most `capacity` messages per destination.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            queues: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the number of times a message is sent before it is given up on.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets the delay before the first retry of a message, doubled for each further retry.
+    pub fn with_retry_backoff(mut self, retry_backoff: Duration) -> Self {
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// Queues the serialized `WireMsg` for the destination. Only its header is read,
+    /// for its id and expiry, an error is returned if it can't be.
+    pub fn push(&mut self, dst: D, bytes: Bytes, priority: Priority) -> Result<Pushed> {
+        self.push_at(dst, bytes, priority, SystemTime::now())
+    }
+
+    /// Queues the serialized `WireMsg` for the destination as of the given time, see `push`.
+    /// The expired messages of the destination are dropped first, so that only the messages
+    /// still to be sent count towards the capacity.
+    pub fn push_at(
+        &mut self,
+        dst: D,
+        bytes: Bytes,
+        priority: Priority,
+        now: SystemTime,
+    ) -> Result<Pushed> {
+        let header = WireMsg::from(bytes.clone())?.header();
+        let outgoing = Outgoing {
+            bytes,
+            msg_id: header.msg_id,
+            priority,
+            expires_at: header.expires_at,
+            attempts: 0,
+            kind: header.kind,
+            not_before: None,
+        };
+
+        let queue = self.queues.entry(dst).or_default();
+        queue.purge_at(now);
+        if queue.len() >= self.capacity {
+            return Ok(Pushed::Full);
+        }
+        if let Some(msg_id) = outgoing.dedup_id() {
+            if !queue.ids.insert(msg_id) {
+                return Ok(Pushed::Duplicate);
+            }
+        }
+        queue.push_back(outgoing);
+        Ok(Pushed::Queued)
+    }
+
+    /// Takes the next message to send to the destination, of the highest priority.
+    pub fn pop(&mut self, dst: &D) -> Option<Outgoing> {
+        self.pop_at(dst, SystemTime::now())
+    }
+
+    /// Takes the next message to send to the destination as of the given time,
+    /// dropping the expired messages on the way. Messages retried less than their
+    /// backoff ago are left in the queue.
+    pub fn pop_at(&mut self, dst: &D, now: SystemTime) -> Option<Outgoing> {
+        let queue = self.queues.get_mut(dst)?;
+        let next = queue.pop_at(now);
+        if queue.len() == 0 {
+            let _ = self.queues.remove(dst);
+        }
+        next
+    }
+
+    /// Queues the message again after a failed send, see `retry_at`.
+    pub fn retry(&mut self, dst: D, outgoing: Outgoing) -> bool {
+        self.retry_at(dst, outgoing, SystemTime::now())
+    }
+
+    /// Queues the message again after a failed send at the given time, to be sent once
+    /// the backoff has passed. Returns false, dropping the message, if it was sent the max
+    /// number of times already, has expired, or if the backoff is too long to be represented.
+    /// Retries are queued even if the queue is full.
+    pub fn retry_at(&mut self, dst: D, mut outgoing: Outgoing, now: SystemTime) -> bool {
+        outgoing.attempts = outgoing.attempts.saturating_add(1);
+        if outgoing.attempts >= self.max_attempts || outgoing.is_expired_at(now) {
+            return false;
+        }
+        let not_before = self
+            .retry_backoff
+            .checked_mul(2u32.saturating_pow(outgoing.attempts - 1))
+            .and_then(|backoff| now.checked_add(backoff));
+        if not_before.is_none() {
+            return false;
+        }
+        outgoing.not_before = not_before;
+
+        let queue = self.queues.entry(dst).or_default();
+        if let Some(msg_id) = outgoing.dedup_id() {
+            let _ = queue.ids.insert(msg_id);
+        }
+        queue.push_back(outgoing);
+        true
+    }
+
+    /// Returns the number of messages queued for the destination.
+    pub fn len(&self, dst: &D) -> usize {
+        self.queues.get(dst).map_or(0, Queue::len)
+    }
+
+    /// Returns whether the queue of the destination is full, in which case new messages
+    /// for it are dropped until some are sent.
+    pub fn is_full(&self, dst: &D) -> bool {
+        self.len(dst) >= self.capacity
+    }
+
+    /// Returns the destinations which have messages queued.
+    pub fn destinations(&self) -> impl Iterator<Item = &D> {
+        self.queues.keys()
+    }
+}
+
+impl<D: Ord + Clone> Default for Outbox<D> {
+    fn default() -> Self {
+        Self::new(DEFAULT_OUTBOX_CAPACITY)
+    }
+}
+
+#[cfg(all(test, feature = "client-msgs"))]
+mod tests {
+    use super::*;
+    use crate::client::{Cmd, Message};
+    use anyhow::Result;
+    use xor_name::XorName;
+
+    fn echo(id: MessageId) -> Result<Bytes> {
+        let msg = Message::Cmd {
+            cmd: Cmd::Echo {
+                client: XorName::default(),
+                payload: vec![],
+            },
+            id,
+            app_id: None,
+            idempotency_key: None,
+            depends_on: vec![],
+        };
+        Ok(msg.serialize()?)
+    }
+
+    #[test]
+    fn outbox() -> Result<()> {
+        let peer = XorName::random();
+        let now = SystemTime::now();
+        let mut outbox = Outbox::new(3)
+            .with_max_attempts(2)
+            .with_retry_backoff(Duration::from_secs(1));
+
+        let (low, normal, high) = (MessageId::new(), MessageId::new(), MessageId::new());
+        assert_eq!(
+            outbox.push(peer, echo(low)?, Priority::Low)?,
+            Pushed::Queued
+        );
+        assert_eq!(
+            outbox.push(peer, echo(normal)?, Priority::Normal)?,
+            Pushed::Queued
+        );
+        assert_eq!(
+            outbox.push(peer, echo(low)?, Priority::High)?,
+            Pushed::Duplicate
+        );
+        assert_eq!(
+            outbox.push(peer, echo(high)?, Priority::High)?,
+            Pushed::Queued
+        );
+        assert!(outbox.is_full(&peer));
+        assert_eq!(
+            outbox.push(peer, echo(MessageId::new())?, Priority::High)?,
+            Pushed::Full
+        );
+
+        let next = |outbox: &mut Outbox<_>, now| outbox.pop_at(&peer, now).and_then(|o| o.msg_id);
+        assert_eq!(next(&mut outbox, now), Some(high));
+        assert_eq!(next(&mut outbox, now), Some(normal));
+
+        let outgoing = outbox
+            .pop_at(&peer, now)
+            .ok_or_else(|| anyhow::anyhow!("empty"))?;
+        assert!(outbox.retry_at(peer, outgoing.clone(), now));
+        assert_eq!(next(&mut outbox, now), None);
+        let retried = outbox
+            .pop_at(&peer, now + Duration::from_secs(1))
+            .ok_or_else(|| anyhow::anyhow!("empty"))?;
+        assert_eq!(retried.msg_id, Some(low));
+        assert!(!outbox.retry_at(peer, retried, now));
+        assert_eq!(outbox.len(&peer), 0);
+
+        let mut msg = WireMsg::from(echo(MessageId::new())?)?;
+        msg.set_expires_at(now + Duration::from_secs(10));
+        assert_eq!(
+            outbox.push(peer, msg.serialize()?, Priority::Normal)?,
+            Pushed::Queued
+        );
+        assert_eq!(outbox.pop_at(&peer, now + Duration::from_secs(10)), None);
+        assert_eq!(outbox.destinations().count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn expired_and_overflowing() -> Result<()> {
+        let peer = XorName::random();
+        let now = SystemTime::now();
+        let mut outbox = Outbox::new(2).with_max_attempts(u32::MAX);
+
+        // An expired message of a lower priority doesn't count towards the capacity.
+        let mut msg = WireMsg::from(echo(MessageId::new())?)?;
+        msg.set_expires_at(now + Duration::from_secs(10));
+        let expiring = msg.serialize()?;
+        assert_eq!(
+            outbox.push_at(peer, expiring.clone(), Priority::Low, now)?,
+            Pushed::Queued
+        );
+        let high = MessageId::new();
+        assert_eq!(
+            outbox.push_at(peer, echo(high)?, Priority::High, now)?,
+            Pushed::Queued
+        );
+        let later = now + Duration::from_secs(10);
+        assert_eq!(
+            outbox.push_at(peer, echo(MessageId::new())?, Priority::High, later)?,
+            Pushed::Queued
+        );
+        assert_eq!(outbox.len(&peer), 2);
+        let outgoing = outbox
+            .pop_at(&peer, later)
+            .ok_or_else(|| anyhow::anyhow!("empty"))?;
+        assert_eq!(outgoing.msg_id, Some(high));
+        assert_eq!(outbox.len(&peer), 1);
+
+        // The expired message was forgotten, it can be queued again.
+        assert_eq!(
+            outbox.push_at(peer, expiring, Priority::Low, now)?,
+            Pushed::Queued
+        );
+
+        // A backoff too long to be represented drops the message rather than overflowing.
+        let mut outbox = Outbox::new(2)
+            .with_max_attempts(u32::MAX)
+            .with_retry_backoff(Duration::MAX);
+        assert!(!outbox.retry_at(peer, outgoing.clone(), now));
+        let mut outbox = Outbox::new(2)
+            .with_max_attempts(u32::MAX)
+            .with_retry_backoff(Duration::from_secs(u64::MAX / 2));
+        let mut outgoing = outgoing;
+        outgoing.attempts = 2;
+        assert!(!outbox.retry_at(peer, outgoing, now));
+        assert_eq!(outbox.len(&peer), 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, time::Duration};
+use threshold_crypto::PublicKey as BlsPublicKey;
+use xor_name::{Prefix, XorName};
+
+/// Advice to send a failed request again, see `client::Error::retry_advice`
+/// and `ProcessingError::retry_advice`.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub struct RetryAdvice {
+    /// How long to wait before sending the request again, if known,
+    /// else it's up to the sender's backoff policy.
+    /// Only the `ProcessingReason::Overloaded` of a `ProcessingError` sets it,
+    /// the advice for a `client::Error` never does.
+    pub after: Option<Duration>,
+}
+
+/// The error returned to the sender of a message which couldn't be processed,
+/// see `client::Message::ProcessingError` and `node::NodeProcessingError`.
+/// The message can be attached, so that the sender can correct it and send it again
+/// without having to keep it around.
+/// It is built with `ProcessingError::new` and the `with_` functions.
+#[derive(Debug, Default, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ProcessingError {
+    /// Why the message couldn't be processed, if known.
+    pub reason: Option<ProcessingReason>,
+    /// The serialized message which couldn't be processed, if attached.
+    #[serde(default, with = "serde_bytes", skip_serializing_if = "Option::is_none")]
+    pub source_message: Option<Vec<u8>>,
+}
+
+/// Why a message couldn't be processed, see `ProcessingError`. The reasons carry
+/// what the sender needs to correct the message, instead of sending it again as is.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub enum ProcessingReason {
+    /// The message was sent to, or signed with, a section key the recipient doesn't know,
+    /// e.g. an outdated one. The sender is to update its knowledge of the section
+    /// up to the latest key, and send the message again.
+    MissingSectionKey {
+        /// The latest key of the recipient's section.
+        latest: BlsPublicKey,
+    },
+    /// The recipient is overloaded, the message can be sent again later.
+    Overloaded {
+        /// How long to wait before sending the message again, if known.
+        retry_after: Option<Duration>,
+    },
+    /// The recipient isn't responsible for the destination of the message.
+    /// The sender is to send the message again to the Elders currently responsible.
+    NotResponsible {
+        /// Names and addresses of the Elders currently responsible,
+        /// as in `client::Error::NotResponsible`.
+        current_responsible: Vec<(XorName, SocketAddr)>,
+    },
+    /// The message couldn't be deserialized by the recipient.
+    Deserialization,
+    /// The recipient failed to process the message, for the given reason.
+    Internal(String),
+    /// The message was sent to a section under an outdated prefix, e.g. before it split.
+    /// The sender is to send the message again to the section now covering its destination.
+    OutdatedPrefix {
+        /// The current prefix of the recipient's section.
+        current: Prefix,
+        /// The current key of the recipient's section.
+        section_key: BlsPublicKey,
+    },
+    /// The message was built with a version of the messaging protocol the recipient
+    /// doesn't support, see `WireMsg::supports_version`.
+    UnsupportedVersion {
+        /// The version the message was built with.
+        version: u16,
+        /// The versions the recipient supports.
+        supported: Vec<u16>,
+    },
+    /// A reason introduced by a newer release of the protocol, which this release
+    /// can't decode. It is never sent to the wire as such: serializing it fails.
+    #[serde(skip)]
+    Unknown {
+        /// Index of the unknown variant, as encoded on the wire.
+        kind: u16,
+        /// The undecoded payload of the message carrying the reason.
+        bytes: Bytes,
+    },
+}
+
+impl ProcessingError {
+    /// Creates a new instance, with no reason nor message attached.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets why the message couldn't be processed.
+    pub fn with_reason(mut self, reason: ProcessingReason) -> Self {
+        self.reason = Some(reason);
+        self
+    }
+
+    /// Attaches the serialized message which couldn't be processed.
+    pub fn with_source_message(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.source_message = Some(bytes.into());
+        self
+    }
+
+    /// Returns whether the same message may be processed if sent again later,
+    /// e.g. once the recipient isn't overloaded anymore, see `retry_advice`.
+    pub fn is_retryable(&self) -> bool {
+        self.retry_advice().is_some()
+    }
+
+    /// Returns whether the message can't be processed, neither if sent again nor once
+    /// corrected, e.g. sent to the Elders responsible for it, or signed with a trusted key.
+    /// Errors without a reason are neither retryable nor fatal.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self.reason, Some(ProcessingReason::Deserialization))
+    }
+
+    /// Returns the advice to send the same message again, if it may be processed later.
+    pub fn retry_advice(&self) -> Option<RetryAdvice> {
+        match self.reason {
+            Some(ProcessingReason::Overloaded { retry_after }) => {
+                Some(RetryAdvice { after: retry_after })
+            }
+            Some(ProcessingReason::Internal(_)) => Some(RetryAdvice::default()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::WireMsg;
+use crate::{limits::MAX_MESSAGE_SIZE, Error, Result};
+use bytes::{Buf, BufMut, BytesMut};
+use std::convert::TryFrom;
+use tokio_util::codec::{Decoder, Encoder};
+
+// Size of the length prefix of each frame.
+const LEN_PREFIX_SIZE: usize = 4;
+
+/// Frames wire messages over a byte stream, e.g. a TCP or QUIC stream, each serialized message
+/// being prefixed with its length as a big-endian u32. Frames larger than the max message size
+/// are rejected as soon as their length prefix is read, before their content is buffered.
+#[derive(Debug, Clone)]
+pub struct WireMsgCodec {
+    max_msg_size: usize,
+}
+
+impl WireMsgCodec {
+    /// Creates a codec enforcing `MAX_MESSAGE_SIZE`.
+    pub fn new() -> Self {
+        Self::with_max_msg_size(MAX_MESSAGE_SIZE)
+    }
+
+    /// Creates a codec enforcing the given max size of a serialized message.
+    pub fn with_max_msg_size(max_msg_size: usize) -> Self {
+        Self { max_msg_size }
+    }
+
+    /// Returns the max size of a serialized message enforced by this codec.
+    pub fn max_msg_size(&self) -> usize {
+        self.max_msg_size
+    }
+}
+
+impl Default for WireMsgCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for WireMsgCodec {
+    type Item = WireMsg;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<WireMsg>> {
+        if src.len() < LEN_PREFIX_SIZE {
+            return Ok(None);
+        }
+        let mut len_bytes = [0; LEN_PREFIX_SIZE];
+        len_bytes.copy_from_slice(&src[..LEN_PREFIX_SIZE]);
+        let len = u32::from_be_bytes(len_bytes) as usize;
+        if len > self.max_msg_size {
+            return Err(Error::MessageTooLarge {
+                size: len,
+                limit: self.max_msg_size,
+            });
+        }
+        if src.len() < LEN_PREFIX_SIZE + len {
+            src.reserve(LEN_PREFIX_SIZE + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(LEN_PREFIX_SIZE);
+        let bytes = src.split_to(len).freeze();
+        WireMsg::deserialize_with_limit(bytes, self.max_msg_size).map(Some)
+    }
+}
+
+impl Encoder<WireMsg> for WireMsgCodec {
+    type Error = Error;
+
+    fn encode(&mut self, msg: WireMsg, dst: &mut BytesMut) -> Result<()> {
+        self.encode(&msg, dst)
+    }
+}
+
+impl Encoder<&WireMsg> for WireMsgCodec {
+    type Error = Error;
+
+    fn encode(&mut self, msg: &WireMsg, dst: &mut BytesMut) -> Result<()> {
+        let bytes = msg.serialize_with_limit(self.max_msg_size)?;
+        let len = u32::try_from(bytes.len()).map_err(|_| Error::MessageTooLarge {
+            size: bytes.len(),
+            limit: u32::MAX as usize,
+        })?;
+        dst.reserve(LEN_PREFIX_SIZE + bytes.len());
+        dst.put_u32(len);
+        dst.extend_from_slice(&bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn framing() -> Result<()> {
+        let mut codec = WireMsgCodec::new();
+        let mut buffer = BytesMut::new();
+        codec.encode(WireMsg::new_ping_msg(), &mut buffer)?;
+        codec.encode(WireMsg::new_ping_msg(), &mut buffer)?;
+        let frame_len = buffer.len() / 2;
+
+        // Partial reads, of the length prefix and then of the message.
+        let mut stream = BytesMut::new();
+        stream.extend_from_slice(&buffer[..2]);
+        assert_eq!(codec.decode(&mut stream)?, None);
+        stream.extend_from_slice(&buffer[2..frame_len - 1]);
+        assert_eq!(codec.decode(&mut stream)?, None);
+        stream.extend_from_slice(&buffer[frame_len - 1..]);
+        assert_eq!(codec.decode(&mut stream)?, Some(WireMsg::new_ping_msg()));
+        assert_eq!(codec.decode(&mut stream)?, Some(WireMsg::new_ping_msg()));
+        assert_eq!(codec.decode(&mut stream)?, None);
+        assert!(stream.is_empty());
+
+        // A frame exceeding the limit is rejected before being buffered.
+        let mut codec = WireMsgCodec::with_max_msg_size(frame_len - LEN_PREFIX_SIZE - 1);
+        assert!(codec.decode(&mut buffer).is_err());
+        assert!(codec.encode(WireMsg::new_ping_msg(), &mut buffer).is_err());
+
+        Ok(())
+    }
+}
@@ -7,25 +7,54 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use super::{Error, Result};
+use super::{Error, MsgHeader, Result};
+use crate::{
+    limits::{MAX_MEDIUM_MSG_SIZE, MAX_SMALL_MSG_SIZE},
+    FlowId, MessageId, FLOW_ID_LEN,
+};
 use bytes::Bytes;
 use cookie_factory::{
-    bytes::{be_u16, be_u8},
+    bytes::{be_u16, be_u64, be_u8},
+    combinator::slice,
     gen,
+    sequence::tuple,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryFrom,
+    fmt::Debug,
+    mem::size_of,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use std::{convert::TryFrom, fmt::Debug, mem::size_of};
+use threshold_crypto::{PublicKey as BlsPublicKey, PK_SIZE};
+use xor_name::{XorName, XOR_NAME_LEN};
 
 // Current version of the messaging protocol.
 // At this point this implementation supports only this version.
-const MESSAGING_PROTO_VERSION: u16 = 1u16;
+const MESSAGING_PROTO_VERSION: u16 = 7u16;
 
 // Header to be serialisied at the front of the wire message.
-// This header contains the information needed to deserialize the payload.
-#[derive(Debug, PartialEq)]
+// This header contains the information needed to deserialize the payload,
+// as well as the information needed to route the message without deserializing it.
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) struct WireMsgHeader {
     header_size: u16,
     version: u16,
     kind: MessageKind,
+    size_class: SizeClass,
+    msg_id: Option<MessageId>,
+    dst: Option<XorName>,
+    dst_section_pk: Option<BlsPublicKey>,
+    compression: Compression,
+    encoding: Encoding,
+    encrypted: bool,
+    signed: bool,
+    // Seconds since the UNIX epoch after which the message is to be dropped, if set.
+    expires_at: Option<u64>,
+    // Number of times the message was forwarded, see `WireMsg::increment_hops`.
+    hops: u8,
+    // Id of the operation the message is part of, see `WireMsg::set_flow_id`.
+    flow_id: Option<FlowId>,
 }
 
 // Bytes length in the header for the 'header_size' field
@@ -39,13 +68,75 @@ const HDR_VERSION_BYTES_LEN: usize = size_of::<u16>();
 // Bytes index in the header for the 'kind' field
 const HDR_KIND_BYTES_START: usize = 4;
 
+// Bytes index in the header for the 'size_class' field
+const HDR_SIZE_CLASS_BYTES_START: usize = 5;
+
+// Bytes index in the header for the flags signaling which of the optional fields are set
+const HDR_FLAGS_BYTES_START: usize = 6;
+const HDR_FLAG_MSG_ID: u8 = 0b001;
+const HDR_FLAG_DST: u8 = 0b010;
+const HDR_FLAG_DST_SECTION_PK: u8 = 0b100;
+// Flag signaling that the payload is sealed to the destination section key.
+const HDR_FLAG_ENCRYPTED: u8 = 0b1000;
+// Flag signaling that the payload is followed by a signature over the header and payload.
+const HDR_FLAG_SIGNED: u8 = 0b1_0000;
+const HDR_FLAG_EXPIRES: u8 = 0b10_0000;
+const HDR_FLAG_HOPS: u8 = 0b100_0000;
+// Flag signaling that a byte of extended flags follows, see `HDR_EXTENSIONS_BYTES_START`.
+// The last bit of each flags byte is reserved for this, so the flags never run out.
+const HDR_FLAG_EXTENDED: u8 = 0b1000_0000;
+// Extended flags, the last bit of which is likewise reserved.
+const HDR_EXT_FLAG_FLOW_ID: u8 = 0b001;
+
+// Bytes index in the header for the optional fields. These are always present,
+// zeroed when not set, so that each field can be read, or patched, at a fixed offset.
+const HDR_MSG_ID_BYTES_START: usize = 7;
+const HDR_MSG_ID_BYTES_END: usize = HDR_MSG_ID_BYTES_START + XOR_NAME_LEN;
+const HDR_DST_BYTES_START: usize = HDR_MSG_ID_BYTES_END;
+const HDR_DST_BYTES_END: usize = HDR_DST_BYTES_START + XOR_NAME_LEN;
+const HDR_DST_SECTION_PK_BYTES_START: usize = HDR_DST_BYTES_END;
+const HDR_DST_SECTION_PK_BYTES_END: usize = HDR_DST_SECTION_PK_BYTES_START + PK_SIZE;
+
+// Bytes index in the header for the 'compression' field
+const HDR_COMPRESSION_BYTES_START: usize = HDR_DST_SECTION_PK_BYTES_END;
+
+// Bytes index in the header for the 'encoding' field
+const HDR_ENCODING_BYTES_START: usize = HDR_COMPRESSION_BYTES_START + 1;
+
+// Bytes index of the fields which extend the header only when flagged, so that headers
+// without them keep the same size and layout. They follow each other in this order:
+// the extended flags (`HDR_FLAG_EXTENDED`), the expiry (`HDR_FLAG_EXPIRES`), the number of
+// hops (`HDR_FLAG_HOPS`), and the flow id (`HDR_EXT_FLAG_FLOW_ID`). Fields flagged by newer
+// versions follow these, and are accounted for in the header size, so they can be skipped.
+const HDR_EXTENSIONS_BYTES_START: usize = HDR_ENCODING_BYTES_START + 1;
+const HDR_EXT_FLAGS_BYTES_LEN: usize = size_of::<u8>();
+const HDR_EXPIRES_AT_BYTES_LEN: usize = size_of::<u64>();
+const HDR_HOPS_BYTES_LEN: usize = size_of::<u8>();
+const HDR_FLOW_ID_BYTES_LEN: usize = FLOW_ID_LEN;
+
 impl WireMsgHeader {
     // Instantiate a WireMsgHeader as per current supported version.
-    pub fn new(kind: MessageKind) -> Self {
+    pub fn new(
+        kind: MessageKind,
+        size_class: SizeClass,
+        msg_id: Option<MessageId>,
+        dst: Option<XorName>,
+    ) -> Self {
         Self {
             header_size: Self::size() as u16,
             version: MESSAGING_PROTO_VERSION,
             kind,
+            size_class,
+            msg_id,
+            dst,
+            dst_section_pk: None,
+            compression: Compression::None,
+            encoding: Encoding::MsgPack,
+            encrypted: false,
+            signed: false,
+            expires_at: None,
+            hops: 0,
+            flow_id: None,
         }
     }
 
@@ -53,17 +144,175 @@ impl WireMsgHeader {
         self.kind
     }
 
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    pub fn set_encrypted(&mut self, encrypted: bool) {
+        self.encrypted = encrypted;
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.signed
+    }
+
+    #[cfg(any(feature = "client-msgs", feature = "infrastructure"))]
+    pub fn set_signed(&mut self, signed: bool) {
+        self.signed = signed;
+    }
+
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    pub fn set_expires_at(&mut self, expires_at: SystemTime) {
+        let secs = expires_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.expires_at = Some(secs);
+        self.update_header_size();
+    }
+
+    pub fn hops(&self) -> u8 {
+        self.hops
+    }
+
+    pub fn set_hops(&mut self, hops: u8) {
+        self.hops = hops;
+        self.update_header_size();
+    }
+
+    pub fn flow_id(&self) -> Option<FlowId> {
+        self.flow_id
+    }
+
+    pub fn set_flow_id(&mut self, flow_id: FlowId) {
+        self.flow_id = Some(flow_id);
+        self.update_header_size();
+    }
+
+    // Sets the header size according to the optional fields extending the header.
+    fn update_header_size(&mut self) {
+        let mut size = Self::size();
+        if self.ext_flags() != 0 {
+            size += HDR_EXT_FLAGS_BYTES_LEN;
+        }
+        if self.expires_at.is_some() {
+            size += HDR_EXPIRES_AT_BYTES_LEN;
+        }
+        if self.hops > 0 {
+            size += HDR_HOPS_BYTES_LEN;
+        }
+        if self.flow_id.is_some() {
+            size += HDR_FLOW_ID_BYTES_LEN;
+        }
+        self.header_size = size as u16;
+    }
+
+    // Size in bytes of this WireMsgHeader when serialized, including the optional extensions.
+    pub fn len(&self) -> usize {
+        self.header_size.into()
+    }
+
+    pub fn dst_section_pk(&self) -> Option<BlsPublicKey> {
+        self.dst_section_pk
+    }
+
+    pub fn set_dst(&mut self, dst: XorName) {
+        self.dst = Some(dst);
+    }
+
+    pub fn set_dst_section_pk(&mut self, dst_section_pk: BlsPublicKey) {
+        self.dst_section_pk = Some(dst_section_pk);
+    }
+
+    pub fn to_msg_header(&self) -> MsgHeader {
+        MsgHeader {
+            version: self.version,
+            kind: self.kind,
+            size_class: self.size_class,
+            msg_id: self.msg_id,
+            dst: self.dst,
+            dst_section_pk: self.dst_section_pk,
+            compression: self.compression,
+            encoding: self.encoding,
+            encrypted: self.encrypted,
+            signed: self.signed,
+            expires_at: self.expires_at(),
+            hops: self.hops,
+            flow_id: self.flow_id,
+        }
+    }
+
+    // Returns whether the given version of the messaging protocol is supported.
+    pub fn supports_version(version: u16) -> bool {
+        version == MESSAGING_PROTO_VERSION
+    }
+
+    // Reads the version of the messaging protocol from the provided bytes,
+    // without parsing the rest of the header, whose layout depends on the version.
+    pub fn peek_version(bytes: &[u8]) -> Result<u16> {
+        if bytes.len() < HDR_VERSION_BYTES_END {
+            return Err(Error::failed_to_parse(format!(
+                "not enough bytes received ({}) to deserialize wire message version",
+                bytes.len()
+            )));
+        }
+        let mut version_bytes = [0; HDR_VERSION_BYTES_LEN];
+        version_bytes[0..].copy_from_slice(&bytes[HDR_VERSION_BYTES_START..HDR_VERSION_BYTES_END]);
+        Ok(u16::from_be_bytes(version_bytes))
+    }
+
     // Parses the provided bytes to deserialize a WireMsgHeader,
     // returning the created WireMsgHeader, as well as the remaining bytes which
     // correspond to the message payload. The caller shall then take care of
     // deserializing the payload using the information provided in the WireMsgHeader.
     pub fn from(mut bytes: Bytes) -> Result<(Self, Bytes)> {
-        // We need at least 4 bytes as current version 1 only has
-        // a header size field and the protocol version. Thus, let's
-        // make sure there is this number of bytes as a minimum.
+        let header = Self::read(&bytes)?;
+
+        // Get a slice for the payload bytes, i.e. the bytes after the header bytes
+        let payload_bytes = bytes.split_off(header.header_size.into());
+
+        Ok((header, payload_bytes))
+    }
+
+    // Parses the provided bytes to deserialize a WireMsgHeader,
+    // without touching the payload bytes which follow it.
+    pub fn read(bytes: &[u8]) -> Result<Self> {
+        // Let's first read the serialization protocol version, and make sure we
+        // support it, since the layout of the rest of the header depends on it.
+        let version = Self::peek_version(bytes)?;
+        if !Self::supports_version(version) {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        // We need at least as many bytes as the current version's header
+        // size, thus let's make sure there is this number of bytes as a minimum.
         let length = bytes.len();
         if length < Self::size() {
-            return Err(Error::FailedToParse(format!(
+            return Err(Error::failed_to_parse(format!(
                 "not enough bytes received ({}) to deserialize wire message header",
                 length
             )));
@@ -73,35 +322,131 @@ impl WireMsgHeader {
         let mut header_size_bytes = [0; HDR_SIZE_BYTES_LEN];
         header_size_bytes[0..].copy_from_slice(&bytes[0..HDR_SIZE_BYTES_LEN]);
         let header_size = u16::from_be_bytes(header_size_bytes);
-
-        // ...now let's read the serialization protocol version bytes
-        let mut version_bytes = [0; HDR_VERSION_BYTES_LEN];
-        version_bytes[0..].copy_from_slice(&bytes[HDR_VERSION_BYTES_START..HDR_VERSION_BYTES_END]);
-        let version = u16::from_be_bytes(version_bytes);
-        // Make sure we support this version
-        if version != MESSAGING_PROTO_VERSION {
-            return Err(Error::UnsupportedVersion(version));
+        if usize::from(header_size) < Self::size() {
+            return Err(Error::failed_to_parse(format!(
+                "wire message header size ({}) is smaller than expected",
+                header_size
+            )));
+        }
+        if usize::from(header_size) > length {
+            return Err(Error::failed_to_parse(format!(
+                "wire message header size ({}) is larger than the bytes received ({})",
+                header_size, length
+            )));
         }
 
-        // ...and finally let's read the message kind value (only 1 byte)
+        // ...then let's read the message kind value (only 1 byte)
         let kind = MessageKind::try_from(bytes[HDR_KIND_BYTES_START])?;
 
-        let header = Self {
-            header_size,
-            version,
-            kind,
+        // ...then let's read the message size class value (only 1 byte)
+        let size_class = SizeClass::try_from(bytes[HDR_SIZE_CLASS_BYTES_START])?;
+
+        // ...and finally let's read the optional fields which are flagged as set
+        let flags = bytes[HDR_FLAGS_BYTES_START];
+        let msg_id = if flags & HDR_FLAG_MSG_ID != 0 {
+            Some(MessageId(read_xor_name(
+                &bytes[HDR_MSG_ID_BYTES_START..HDR_MSG_ID_BYTES_END],
+            )))
+        } else {
+            None
+        };
+        let dst = if flags & HDR_FLAG_DST != 0 {
+            Some(read_xor_name(
+                &bytes[HDR_DST_BYTES_START..HDR_DST_BYTES_END],
+            ))
+        } else {
+            None
+        };
+        let dst_section_pk = if flags & HDR_FLAG_DST_SECTION_PK != 0 {
+            let mut pk_bytes = [0; PK_SIZE];
+            pk_bytes[0..].copy_from_slice(
+                &bytes[HDR_DST_SECTION_PK_BYTES_START..HDR_DST_SECTION_PK_BYTES_END],
+            );
+            Some(BlsPublicKey::from_bytes(pk_bytes).map_err(|err| {
+                Error::failed_to_parse(format!(
+                    "destination section key in wire message header: {}",
+                    err
+                ))
+            })?)
+        } else {
+            None
         };
 
-        // Get a slice for the payload bytes, i.e. the bytes after the header bytes
-        let payload_bytes = bytes.split_off(header_size.into());
+        let encrypted = flags & HDR_FLAG_ENCRYPTED != 0;
+        let signed = flags & HDR_FLAG_SIGNED != 0;
 
-        Ok((header, payload_bytes))
+        // ...and the compression the payload was serialized with (only 1 byte)
+        let compression = Compression::try_from(bytes[HDR_COMPRESSION_BYTES_START])?;
+
+        // ...and the encoding the payload was serialized with (only 1 byte)
+        let encoding = Encoding::try_from(bytes[HDR_ENCODING_BYTES_START])?;
+
+        // ...and the optional fields the header is extended with, if any,
+        // which are read from the header bytes only, never past the bytes received.
+        let header_end = usize::from(header_size).min(length);
+        let mut extensions = Extensions {
+            bytes: &bytes[..header_end],
+            start: HDR_EXTENSIONS_BYTES_START,
+        };
+        let ext_flags = if flags & HDR_FLAG_EXTENDED != 0 {
+            extensions.next(HDR_EXT_FLAGS_BYTES_LEN, "extended flags")?[0]
+        } else {
+            0
+        };
+        let expires_at = if flags & HDR_FLAG_EXPIRES != 0 {
+            let mut expires_at_bytes = [0; HDR_EXPIRES_AT_BYTES_LEN];
+            expires_at_bytes[0..]
+                .copy_from_slice(extensions.next(HDR_EXPIRES_AT_BYTES_LEN, "expiry")?);
+            Some(u64::from_be_bytes(expires_at_bytes))
+        } else {
+            None
+        };
+        let hops = if flags & HDR_FLAG_HOPS != 0 {
+            extensions.next(HDR_HOPS_BYTES_LEN, "hops")?[0]
+        } else {
+            0
+        };
+        let flow_id = if ext_flags & HDR_EXT_FLAG_FLOW_ID != 0 {
+            let mut flow_id = [0; HDR_FLOW_ID_BYTES_LEN];
+            flow_id[0..].copy_from_slice(extensions.next(HDR_FLOW_ID_BYTES_LEN, "flow id")?);
+            Some(FlowId(flow_id))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            header_size,
+            version,
+            kind,
+            size_class,
+            msg_id,
+            dst,
+            dst_section_pk,
+            compression,
+            encoding,
+            encrypted,
+            signed,
+            expires_at,
+            hops,
+            flow_id,
+        })
     }
 
     pub fn write<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a mut [u8]> {
+        // The payload starts right after the header, whose size accounts for any extension
+        // we may not know how to write, e.g. of a header read from a newer peer.
+        if buffer.len() < self.len() {
+            return Err(Error::Serialisation(format!(
+                "buffer ({} bytes) too small to serialize header of {} bytes",
+                buffer.len(),
+                self.len()
+            )));
+        }
+        let (header_buf, payload_buf) = buffer.split_at_mut(self.len());
+
         // Let's write the header size first
         let (buf_at_version, _) =
-            gen(be_u16(self.header_size), &mut buffer[..]).map_err(|err| {
+            gen(be_u16(self.header_size), &mut header_buf[..]).map_err(|err| {
                 Error::Serialisation(format!(
                     "header size value couldn't be serialized in header: {}",
                     err
@@ -117,36 +462,219 @@ impl WireMsgHeader {
                 ))
             })?;
 
-        // ...and finally, let's write the value signaling the message kind
-        let (buf_at_payload, _) =
-            gen(be_u8(self.kind.into()), &mut buf_at_msg_kind[..]).map_err(|err| {
+        // ...then, let's write the value signaling the message kind
+        let (buf_at_size_class, _) = gen(be_u8(self.kind.into()), &mut buf_at_msg_kind[..])
+            .map_err(|err| {
                 Error::Serialisation(format!(
                     "message kind field couldn't be serialized in header: {}",
                     err
                 ))
             })?;
 
-        Ok(buf_at_payload)
+        // ...then, let's write the value signaling the message size class
+        let (buf_at_flags, _) = gen(be_u8(self.size_class.into()), &mut buf_at_size_class[..])
+            .map_err(|err| {
+                Error::Serialisation(format!(
+                    "message size class field couldn't be serialized in header: {}",
+                    err
+                ))
+            })?;
+
+        // ...then, let's write the flags signaling which optional fields are set
+        let (buf_at_optional_fields, _) =
+            gen(be_u8(self.flags()), &mut buf_at_flags[..]).map_err(|err| {
+                Error::Serialisation(format!(
+                    "flags field couldn't be serialized in header: {}",
+                    err
+                ))
+            })?;
+
+        // ...then, let's write the optional fields, zeroed when not set
+        let msg_id = self.msg_id.map(|id| (id.0).0).unwrap_or_default();
+        let dst = self.dst.map(|name| name.0).unwrap_or_default();
+        let dst_section_pk = self
+            .dst_section_pk
+            .map(|pk| pk.to_bytes())
+            .unwrap_or([0; PK_SIZE]);
+        let (buf_at_compression, _) = gen(
+            tuple((slice(msg_id), slice(dst), slice(&dst_section_pk[..]))),
+            &mut buf_at_optional_fields[..],
+        )
+        .map_err(|err| {
+            Error::Serialisation(format!(
+                "optional fields couldn't be serialized in header: {}",
+                err
+            ))
+        })?;
+
+        // ...then, let's write the value signaling the payload compression
+        let (buf_at_encoding, _) = gen(be_u8(self.compression.into()), &mut buf_at_compression[..])
+            .map_err(|err| {
+                Error::Serialisation(format!(
+                    "compression field couldn't be serialized in header: {}",
+                    err
+                ))
+            })?;
+
+        // ...then, let's write the value signaling the payload encoding
+        let (buf_at_extensions, _) = gen(be_u8(self.encoding.into()), &mut buf_at_encoding[..])
+            .map_err(|err| {
+                Error::Serialisation(format!(
+                    "encoding field couldn't be serialized in header: {}",
+                    err
+                ))
+            })?;
+
+        // ...and finally, let's write the optional fields extending the header, if set
+        let ext_flags = self.ext_flags();
+        let buf_at_expires_at = if ext_flags != 0 {
+            gen(be_u8(ext_flags), &mut buf_at_extensions[..])
+                .map_err(|err| {
+                    Error::Serialisation(format!(
+                        "extended flags field couldn't be serialized in header: {}",
+                        err
+                    ))
+                })?
+                .0
+        } else {
+            buf_at_extensions
+        };
+        let buf_at_hops = match self.expires_at {
+            Some(secs) => {
+                gen(be_u64(secs), &mut buf_at_expires_at[..])
+                    .map_err(|err| {
+                        Error::Serialisation(format!(
+                            "expiry field couldn't be serialized in header: {}",
+                            err
+                        ))
+                    })?
+                    .0
+            }
+            None => buf_at_expires_at,
+        };
+        let buf_at_flow_id = if self.hops > 0 {
+            gen(be_u8(self.hops), &mut buf_at_hops[..])
+                .map_err(|err| {
+                    Error::Serialisation(format!(
+                        "hops field couldn't be serialized in header: {}",
+                        err
+                    ))
+                })?
+                .0
+        } else {
+            buf_at_hops
+        };
+        if let Some(flow_id) = self.flow_id {
+            let _ = gen(slice(flow_id.0), &mut buf_at_flow_id[..]).map_err(|err| {
+                Error::Serialisation(format!(
+                    "flow id field couldn't be serialized in header: {}",
+                    err
+                ))
+            })?;
+        }
+
+        Ok(payload_buf)
     }
 
     // Size in bytes of WireMsgHeader when serialized.
     pub fn size() -> usize {
         // We don't use 'std::mem::size_of' since for the
-        // 'MessageKind' enum it reports 2 bytes mem size,
-        // and we want to serialize that field using 1 byte only.
-        // HDR_SIZE_BYTES_LEN + HDR_VERSION_BYTES_LEN + 1 == 5
-        5
+        // 'MessageKind' and 'SizeClass' enums it reports 2 bytes mem size,
+        // and we want to serialize each of those fields using 1 byte only.
+        // The optional fields are always present.
+        HDR_ENCODING_BYTES_START + 1
+    }
+
+    // Flags signaling which of the optional fields are set.
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.msg_id.is_some() {
+            flags |= HDR_FLAG_MSG_ID;
+        }
+        if self.dst.is_some() {
+            flags |= HDR_FLAG_DST;
+        }
+        if self.dst_section_pk.is_some() {
+            flags |= HDR_FLAG_DST_SECTION_PK;
+        }
+        if self.encrypted {
+            flags |= HDR_FLAG_ENCRYPTED;
+        }
+        if self.signed {
+            flags |= HDR_FLAG_SIGNED;
+        }
+        if self.expires_at.is_some() {
+            flags |= HDR_FLAG_EXPIRES;
+        }
+        if self.hops > 0 {
+            flags |= HDR_FLAG_HOPS;
+        }
+        if self.ext_flags() != 0 {
+            flags |= HDR_FLAG_EXTENDED;
+        }
+        flags
     }
+
+    // Extended flags signaling which of the optional fields are set, see `HDR_FLAG_EXTENDED`.
+    fn ext_flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.flow_id.is_some() {
+            flags |= HDR_EXT_FLAG_FLOW_ID;
+        }
+        flags
+    }
+}
+
+// Reader of the optional fields extending the header, which follow each other.
+struct Extensions<'a> {
+    // The header bytes, bounded by both the header size and the bytes received.
+    bytes: &'a [u8],
+    start: usize,
 }
 
-// Type of message being sent over the wire, this type
-// is part of the WireMsgHeader.
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub(crate) enum MessageKind {
+impl<'a> Extensions<'a> {
+    // Returns the bytes of the next field, of the given length.
+    fn next(&mut self, len: usize, field: &str) -> Result<&'a [u8]> {
+        let end = self.start + len;
+        if self.bytes.len() < end {
+            return Err(Error::failed_to_parse(format!(
+                "not enough bytes received ({}) to deserialize wire message {}",
+                self.bytes.len(),
+                field
+            )));
+        }
+        let bytes = &self.bytes[self.start..end];
+        self.start = end;
+        Ok(bytes)
+    }
+}
+
+fn read_xor_name(bytes: &[u8]) -> XorName {
+    let mut name = [0; XOR_NAME_LEN];
+    name[0..].copy_from_slice(bytes);
+    XorName(name)
+}
+
+/// Type of message being sent over the wire, this type
+/// is part of the header of the wire message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MessageKind {
+    /// A `MessageType::Ping`.
     Ping,
+    /// A `MessageType::InfrastructureQuery`.
     InfrastructureQuery,
+    /// A `MessageType::ClientMessage`.
     ClientMessage,
+    /// A `MessageType::NodeMessage`.
     NodeMessage,
+    /// A `MessageType::Layered`.
+    LayeredMessage,
+    /// A `MessageType::Multipart`.
+    Multipart,
+    /// A `MessageType::Batch`.
+    Batch,
+    /// A `MessageType::FlowCredit`.
+    FlowCredit,
 }
 
 impl TryFrom<u8> for MessageKind {
@@ -158,6 +686,10 @@ impl TryFrom<u8> for MessageKind {
             1 => Ok(Self::InfrastructureQuery),
             2 => Ok(Self::ClientMessage),
             3 => Ok(Self::NodeMessage),
+            4 => Ok(Self::LayeredMessage),
+            5 => Ok(Self::Multipart),
+            6 => Ok(Self::Batch),
+            7 => Ok(Self::FlowCredit),
             other => Err(Error::UnsupportedMessageKind(other)),
         }
     }
@@ -170,6 +702,129 @@ impl From<MessageKind> for u8 {
             MessageKind::InfrastructureQuery => 1,
             MessageKind::ClientMessage => 2,
             MessageKind::NodeMessage => 3,
+            MessageKind::LayeredMessage => 4,
+            MessageKind::Multipart => 5,
+            MessageKind::Batch => 6,
+            MessageKind::FlowCredit => 7,
+        }
+    }
+}
+
+/// Size class of a message, which is part of the header of the wire message.
+/// It allows the transport layer to choose how to send a message
+/// (e.g. uni vs bi streams, and timeouts) without inspecting its payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum SizeClass {
+    /// A message with a small payload, e.g. a query or an event.
+    Small,
+    /// A message with a medium payload, e.g. a Map or Sequence write.
+    Medium,
+    /// A message with a large payload, e.g. a chunk.
+    Large,
+}
+
+impl SizeClass {
+    /// Returns the size class of an opaque payload of the given length.
+    pub fn from_len(len: usize) -> Self {
+        if len <= MAX_SMALL_MSG_SIZE {
+            Self::Small
+        } else if len <= MAX_MEDIUM_MSG_SIZE {
+            Self::Medium
+        } else {
+            Self::Large
+        }
+    }
+}
+
+impl TryFrom<u8> for SizeClass {
+    type Error = super::Error;
+
+    fn try_from(input: u8) -> Result<Self, Self::Error> {
+        match input {
+            0 => Ok(Self::Small),
+            1 => Ok(Self::Medium),
+            2 => Ok(Self::Large),
+            other => Err(Error::failed_to_parse(format!(
+                "unsupported message size class: {}",
+                other
+            ))),
+        }
+    }
+}
+
+impl From<SizeClass> for u8 {
+    fn from(size_class: SizeClass) -> u8 {
+        match size_class {
+            SizeClass::Small => 0,
+            SizeClass::Medium => 1,
+            SizeClass::Large => 2,
+        }
+    }
+}
+
+/// Compression of the payload of a wire message, which is part of its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Compression {
+    /// The payload is not compressed.
+    None,
+    /// The payload is compressed with LZ4, prefixed with its decompressed length.
+    Lz4,
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = super::Error;
+
+    fn try_from(input: u8) -> Result<Self, Self::Error> {
+        match input {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Lz4),
+            other => Err(Error::failed_to_parse(format!(
+                "unsupported payload compression: {}",
+                other
+            ))),
+        }
+    }
+}
+
+impl From<Compression> for u8 {
+    fn from(compression: Compression) -> u8 {
+        match compression {
+            Compression::None => 0,
+            Compression::Lz4 => 1,
+        }
+    }
+}
+
+/// Encoding of the payload of a wire message, which is part of its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Encoding {
+    /// The payload is encoded with MessagePack.
+    MsgPack,
+    /// The payload is encoded with CBOR, which can be parsed by off-the-shelf libraries
+    /// of most languages. Supported only with the `cbor` feature.
+    Cbor,
+}
+
+impl TryFrom<u8> for Encoding {
+    type Error = super::Error;
+
+    fn try_from(input: u8) -> Result<Self, Self::Error> {
+        match input {
+            0 => Ok(Self::MsgPack),
+            1 => Ok(Self::Cbor),
+            other => Err(Error::failed_to_parse(format!(
+                "unsupported payload encoding: {}",
+                other
+            ))),
+        }
+    }
+}
+
+impl From<Encoding> for u8 {
+    fn from(encoding: Encoding) -> u8 {
+        match encoding {
+            Encoding::MsgPack => 0,
+            Encoding::Cbor => 1,
         }
     }
 }
@@ -186,15 +841,103 @@ mod tests {
             (MessageKind::InfrastructureQuery, 1),
             (MessageKind::ClientMessage, 2),
             (MessageKind::NodeMessage, 3),
+            (MessageKind::LayeredMessage, 4),
+            (MessageKind::Multipart, 5),
+            (MessageKind::Batch, 6),
+            (MessageKind::FlowCredit, 7),
         ] {
             assert_eq!(kind as u8, byte);
             assert_eq!(MessageKind::try_from(byte)?, kind);
         }
 
-        for byte in 4..u8::MAX {
+        for byte in 8..=u8::MAX {
             assert!(MessageKind::try_from(byte).is_err());
         }
 
         Ok(())
     }
+
+    #[test]
+    fn unsupported_version() -> Result<()> {
+        // A header of the previous version, which had no size class field.
+        let bytes = Bytes::from_static(&[0, 5, 0, 1, 0]);
+        assert!(!WireMsgHeader::supports_version(1));
+        assert_eq!(WireMsgHeader::peek_version(&bytes)?, 1);
+        assert!(matches!(
+            WireMsgHeader::from(bytes),
+            Err(Error::UnsupportedVersion(1))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn header_size_larger_than_bytes() -> Result<()> {
+        let header = WireMsgHeader::new(MessageKind::Ping, SizeClass::Small, None, None);
+        let mut buffer = vec![0u8; header.len()];
+        let _ = header.write(&mut buffer)?;
+
+        // A header claiming to be larger than the bytes received must not be sliced past them.
+        buffer[..HDR_SIZE_BYTES_LEN].copy_from_slice(&256u16.to_be_bytes());
+        assert!(matches!(
+            WireMsgHeader::from(Bytes::from(buffer)),
+            Err(Error::FailedToParse { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn extensions_past_header_size() -> Result<()> {
+        let mut header = WireMsgHeader::new(MessageKind::Ping, SizeClass::Small, None, None);
+        header.set_flow_id(FlowId([1; FLOW_ID_LEN]));
+        let mut buffer = vec![0u8; header.len()];
+        let _ = header.write(&mut buffer)?;
+
+        // The flow id is flagged, but the header size doesn't make room for it.
+        let size = WireMsgHeader::size() as u16;
+        buffer[..HDR_SIZE_BYTES_LEN].copy_from_slice(&size.to_be_bytes());
+        assert!(matches!(
+            WireMsgHeader::read(&buffer),
+            Err(Error::FailedToParse { .. })
+        ));
+
+        // Nor do the bytes received, even though the header size does.
+        buffer[..HDR_SIZE_BYTES_LEN].copy_from_slice(&(header.len() as u16).to_be_bytes());
+        assert!(matches!(
+            WireMsgHeader::read(&buffer[..header.len() - 1]),
+            Err(Error::FailedToParse { .. })
+        ));
+        assert_eq!(WireMsgHeader::read(&buffer)?, header);
+
+        Ok(())
+    }
+
+    #[test]
+    fn size_class_from_u8() -> Result<()> {
+        for &(size_class, byte) in &[
+            (SizeClass::Small, 0),
+            (SizeClass::Medium, 1),
+            (SizeClass::Large, 2),
+        ] {
+            assert_eq!(u8::from(size_class), byte);
+            assert_eq!(SizeClass::try_from(byte)?, size_class);
+        }
+
+        for byte in 3..=u8::MAX {
+            assert!(SizeClass::try_from(byte).is_err());
+        }
+
+        assert_eq!(SizeClass::from_len(0), SizeClass::Small);
+        assert_eq!(
+            SizeClass::from_len(MAX_SMALL_MSG_SIZE + 1),
+            SizeClass::Medium
+        );
+        assert_eq!(
+            SizeClass::from_len(MAX_MEDIUM_MSG_SIZE + 1),
+            SizeClass::Large
+        );
+
+        Ok(())
+    }
 }
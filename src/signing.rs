@@ -0,0 +1,135 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Domain separation for the signatures produced over messages.
+//!
+//! Every signature verified by this crate is computed over the payload prefixed with
+//! a domain tag, so that a signature produced for a message can never be replayed
+//! as a signature over any other SAFE structure (or a message of another domain).
+//! The only exception are the links of section key chains, which are signed when each key
+//! is created, over the raw key, see `GetKeyChainResponse::signable_bytes`.
+
+/// Domain of signatures made by clients over their messages.
+pub const CLIENT_MSG_DOMAIN: &[u8] = b"SN_MSG_CLIENT_V1";
+/// Domain of signatures made by individual nodes over their messages.
+pub const NODE_MSG_DOMAIN: &[u8] = b"SN_MSG_NODE_V1";
+/// Domain of signatures made by sections (or Elders' shares thereof) over their messages.
+pub const SECTION_MSG_DOMAIN: &[u8] = b"SN_MSG_SECTION_V1";
+/// Domain of signatures by sections over quotas and prepayments issued to clients.
+pub const QUOTA_DOMAIN: &[u8] = b"SN_MSG_QUOTA_V1";
+/// Domain of signatures over infrastructure responses, e.g. section statistics.
+pub const INFRASTRUCTURE_DOMAIN: &[u8] = b"SN_MSG_INFRASTRUCTURE_V1";
+/// Domain of signatures over whole wire messages, header and payload, see `WireMsg::sign`.
+pub const WIRE_MSG_DOMAIN: &[u8] = b"SN_MSG_WIRE_V1";
+/// Domain of signatures by sections over the storage receipts issued to data owners.
+pub const RECEIPT_DOMAIN: &[u8] = b"SN_MSG_RECEIPT_V1";
+/// Domain of signatures by sections over the current holders of a chunk, see `ChunkHolders`.
+pub const CHUNK_HOLDERS_DOMAIN: &[u8] = b"SN_MSG_CHUNK_HOLDERS_V1";
+/// Domain of signatures by replicas over the balance of a key as of an era, see `HistoricalBalance`.
+pub const HISTORICAL_BALANCE_DOMAIN: &[u8] = b"SN_MSG_HISTORICAL_BALANCE_V1";
+/// Domain of signatures by sections over the reward state of a node, see `RewardState`.
+pub const REWARD_STATE_DOMAIN: &[u8] = b"SN_MSG_REWARD_STATE_V1";
+/// Domain of signatures by sections over the outcome of a data audit, see `AuditReport`.
+pub const AUDIT_REPORT_DOMAIN: &[u8] = b"SN_MSG_AUDIT_REPORT_V1";
+/// Domain of signatures by sections over the replication status of data, see `ReplicationStatus`.
+pub const REPLICATION_STATUS_DOMAIN: &[u8] = b"SN_MSG_REPLICATION_STATUS_V1";
+/// Domain of signatures by sections over merkle roots, e.g. of paged responses, see `SignedRoot`.
+pub const SIGNED_ROOT_DOMAIN: &[u8] = b"SN_MSG_SIGNED_ROOT_V1";
+
+/// Returns the bytes to be signed, or verified, for the given payload in the given domain.
+/// The domain tag is length-prefixed so that no two domains can ever produce the same bytes.
+pub fn signable_bytes(domain: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(1 + domain.len() + payload.len());
+    bytes.push(domain.len() as u8);
+    bytes.extend_from_slice(domain);
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+// Returns the bytes to be signed, or verified, for the given value serialized with bincode,
+// in the given domain. `what` names the value in the error, if it can't be serialized.
+#[cfg(feature = "client-msgs")]
+pub(crate) fn signable_value<T: serde::Serialize>(
+    domain: &[u8],
+    value: &T,
+    what: &str,
+) -> crate::Result<Vec<u8>> {
+    let bytes = bincode::serialize(value).map_err(|err| {
+        crate::Error::Serialisation(format!("could not serialize {}: {}", what, err))
+    })?;
+    Ok(signable_bytes(domain, &bytes))
+}
+
+// Verifies a section signature over the given signable bytes, see `signable_value`.
+#[cfg(feature = "client-msgs")]
+pub(crate) fn verify_section_signature(
+    section_key: &threshold_crypto::PublicKey,
+    signature: &threshold_crypto::Signature,
+    signable_bytes: crate::Result<Vec<u8>>,
+) -> bool {
+    match signable_bytes {
+        Ok(bytes) => section_key.verify(signature, bytes),
+        Err(_) => false,
+    }
+}
+
+#[cfg(all(test, feature = "client-msgs"))]
+mod tests {
+    use super::*;
+    use crate::client::{ChunkHolders, MsgSender};
+    use anyhow::Result;
+    use sn_data_types::{BlobAddress, Keypair};
+    use std::collections::BTreeMap;
+    use xor_name::XorName;
+
+    #[test]
+    fn client_signature_is_domain_separated() -> Result<()> {
+        let keypair = Keypair::new_ed25519(&mut rand::thread_rng());
+        let payload = b"payload";
+
+        let sig = keypair.sign(&signable_bytes(CLIENT_MSG_DOMAIN, payload));
+        let sender = MsgSender::client(keypair.public_key(), sig)?;
+        assert!(sender.verify(payload));
+
+        let sig = keypair.sign(payload);
+        let sender = MsgSender::client(keypair.public_key(), sig)?;
+        assert!(!sender.verify(payload));
+
+        let sig = keypair.sign(&signable_bytes(NODE_MSG_DOMAIN, payload));
+        let sender = MsgSender::client(keypair.public_key(), sig)?;
+        assert!(!sender.verify(payload));
+
+        Ok(())
+    }
+
+    #[test]
+    fn section_signatures_are_domain_separated() -> Result<()> {
+        let section_key = threshold_crypto::SecretKey::random();
+        let address = BlobAddress::Public(XorName::random());
+        let holders: BTreeMap<_, _> = vec![(XorName::random(), 5)].into_iter().collect();
+        let bytes = signable_value(CHUNK_HOLDERS_DOMAIN, &(address, &holders), "chunk holders")?;
+        let chunk_holders = ChunkHolders {
+            address,
+            holders: holders.clone(),
+            section_key: section_key.public_key(),
+            signature: section_key.sign(bytes),
+        };
+        assert!(chunk_holders.verify());
+
+        // The same fields signed as a raw section message don't pass for chunk holders.
+        let bytes = signable_value(SECTION_MSG_DOMAIN, &(address, &holders), "chunk holders")?;
+        let chunk_holders = ChunkHolders {
+            signature: section_key.sign(bytes),
+            ..chunk_holders
+        };
+        assert!(!chunk_holders.verify());
+
+        Ok(())
+    }
+}
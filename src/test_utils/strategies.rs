@@ -0,0 +1,101 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Proptest strategies generating valid messages.
+//!
+//! The messages are built by the `Arbitrary` implementations used for fuzzing, out of
+//! bytes generated by proptest, so that both share the same generators.
+
+#[cfg(feature = "client-msgs")]
+use crate::client;
+#[cfg(feature = "infrastructure")]
+use crate::infrastructure;
+#[cfg(feature = "node-msgs")]
+use crate::node;
+use crate::{MessageId, WireMsg};
+use arbitrary::{Arbitrary, Unstructured};
+use proptest::{collection::vec, prelude::*, strategy::BoxedStrategy};
+use std::fmt::Debug;
+
+// Max number of bytes the values are built out of.
+const MAX_INPUT_LEN: usize = 4096;
+
+/// Generates message ids.
+pub fn msg_id() -> impl Strategy<Value = MessageId> {
+    arbitrary()
+}
+
+/// Generates client messages, of the variants covered by their `Arbitrary` implementation.
+#[cfg(feature = "client-msgs")]
+pub fn client_msg() -> impl Strategy<Value = client::Message> {
+    arbitrary()
+}
+
+/// Generates node messages.
+#[cfg(feature = "node-msgs")]
+pub fn node_msg() -> impl Strategy<Value = node::NodeMessage> {
+    arbitrary()
+}
+
+/// Generates infrastructure queries, of the variants covered by their `Arbitrary` implementation.
+#[cfg(feature = "infrastructure")]
+pub fn infrastructure_query() -> impl Strategy<Value = infrastructure::Query> {
+    arbitrary()
+}
+
+/// Generates wire messages, of any of the kinds of messages enabled by the features of this crate.
+pub fn wire_msg() -> impl Strategy<Value = WireMsg> {
+    let strategies: Vec<BoxedStrategy<WireMsg>> = vec![
+        Just(()).prop_map(|_| WireMsg::new_ping_msg()).boxed(),
+        #[cfg(feature = "client-msgs")]
+        client_msg()
+            .prop_filter_map("client message exceeds the limits", |msg| {
+                WireMsg::new_client_msg(&msg).ok()
+            })
+            .boxed(),
+        #[cfg(feature = "node-msgs")]
+        node_msg()
+            .prop_filter_map("node message exceeds the limits", |msg| {
+                WireMsg::new_node_msg(&msg).ok()
+            })
+            .boxed(),
+        #[cfg(feature = "infrastructure")]
+        infrastructure_query()
+            .prop_filter_map("infrastructure query exceeds the limits", |query| {
+                WireMsg::new_infrastructure_query(&query).ok()
+            })
+            .boxed(),
+    ];
+    proptest::strategy::Union::new(strategies)
+}
+
+// Generates values out of their `Arbitrary` implementation.
+fn arbitrary<T>() -> impl Strategy<Value = T>
+where
+    T: for<'a> Arbitrary<'a> + Debug,
+{
+    vec(any::<u8>(), 0..MAX_INPUT_LEN).prop_filter_map("not enough input bytes", |bytes| {
+        Unstructured::new(&bytes).arbitrary().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn wire_msgs_roundtrip(wire_msg in wire_msg()) {
+            let fail = |err: crate::Error| TestCaseError::fail(err.to_string());
+            let msg = wire_msg.to_message().map_err(fail)?;
+            let deserialized = WireMsg::from(wire_msg.serialize().map_err(fail)?).map_err(fail)?;
+            prop_assert_eq!(deserialized.to_message().map_err(fail)?, msg);
+        }
+    }
+}
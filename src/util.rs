@@ -0,0 +1,169 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Helpers for the consumers of the messages, e.g. to track the requests awaiting a response.
+
+use crate::MessageId;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// Maps the ids of the requests sent and awaiting a response to some state of the sender,
+/// e.g. a channel to pass the response on, so that the responses can be matched by the id of
+/// the request they respond to. Requests not responded to within the time to live expire.
+#[derive(Debug)]
+pub struct CorrelationMap<T> {
+    ttl: Duration,
+    // The state of each request, along with the instant it expires at.
+    pending: BTreeMap<MessageId, (Instant, T)>,
+    // Ids in the order they were inserted, to expire them.
+    expiry: VecDeque<(Instant, MessageId)>,
+}
+
+impl<T> CorrelationMap<T> {
+    /// Creates a new instance, keeping requests for the given time.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            pending: BTreeMap::new(),
+            expiry: VecDeque::new(),
+        }
+    }
+
+    /// Records the state of the request with the given id, replacing any previous one.
+    pub fn insert(&mut self, msg_id: MessageId, state: T) -> Option<T> {
+        self.insert_at(msg_id, state, Instant::now())
+    }
+
+    /// Records the state of the request, as sent at the given instant, see `insert`.
+    pub fn insert_at(&mut self, msg_id: MessageId, state: T, now: Instant) -> Option<T> {
+        // The id may be pending already, its first expiry then applies.
+        if let Some((_, previous)) = self.pending.get_mut(&msg_id) {
+            return Some(std::mem::replace(previous, state));
+        }
+        let expires_at = now + self.ttl;
+        let _ = self.pending.insert(msg_id, (expires_at, state));
+        self.expiry.push_back((expires_at, msg_id));
+        None
+    }
+
+    /// Removes and returns the state of the request with the given id, if still pending.
+    pub fn remove(&mut self, msg_id: &MessageId) -> Option<T> {
+        self.pending.remove(msg_id).map(|(_, state)| state)
+    }
+
+    /// Removes and returns the state of the request the message responds to, if still pending,
+    /// see `client::Message::response_to`.
+    #[cfg(feature = "client-msgs")]
+    pub fn match_response(&mut self, msg: &crate::client::Message) -> Option<T> {
+        self.remove(&msg.response_to()?)
+    }
+
+    /// Removes and returns the requests which expired as of the given instant,
+    /// e.g. to report their time out to the sender.
+    pub fn prune_at(&mut self, now: Instant) -> Vec<(MessageId, T)> {
+        let mut expired = vec![];
+        while let Some((expires_at, msg_id)) = self.expiry.front().copied() {
+            if expires_at > now {
+                break;
+            }
+            let _ = self.expiry.pop_front();
+            // The id may have been removed since, and inserted again with a later expiry.
+            if self
+                .pending
+                .get(&msg_id)
+                .is_some_and(|(pending_expiry, _)| *pending_expiry == expires_at)
+            {
+                if let Some(state) = self.remove(&msg_id) {
+                    expired.push((msg_id, state));
+                }
+            }
+        }
+        expired
+    }
+
+    /// Returns the number of requests pending.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns whether no request is pending.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(all(test, feature = "client-msgs"))]
+mod tests {
+    use super::*;
+    use crate::client::{Address, Error, Message, ProcessingReason, Query, QueryResponse};
+    use anyhow::Result;
+    use xor_name::XorName;
+
+    #[test]
+    fn correlation_map() -> Result<()> {
+        let now = Instant::now();
+        let mut pending = CorrelationMap::new(Duration::from_secs(30));
+        let (answered, failed, expiring) = (MessageId::new(), MessageId::new(), MessageId::new());
+        assert_eq!(pending.insert_at(answered, "answered", now), None);
+        assert_eq!(pending.insert_at(failed, "failed", now), None);
+        assert_eq!(pending.insert_at(expiring, "expiring", now), None);
+
+        let response = Message::query_response(
+            QueryResponse::GetBalance(Err(Error::NoSuchBalance)),
+            answered,
+            Address::Client(XorName::random()),
+        );
+        assert_eq!(pending.match_response(&response), Some("answered"));
+        assert_eq!(pending.match_response(&response), None);
+
+        let query = Message::Query {
+            query: Query::GetMyRegistrations(sn_data_types::PublicKey::Bls(
+                threshold_crypto::SecretKey::random().public_key(),
+            )),
+            id: failed,
+            app_id: None,
+            reply_to: None,
+        };
+        assert_eq!(pending.match_response(&query), None);
+        let error =
+            query.create_processing_error(ProcessingReason::Overloaded { retry_after: None })?;
+        assert_eq!(pending.match_response(&error), Some("failed"));
+
+        assert!(pending.prune_at(now).is_empty());
+        assert_eq!(
+            pending.prune_at(now + Duration::from_secs(30)),
+            vec![(expiring, "expiring")]
+        );
+        assert!(pending.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reinserted_after_removal() {
+        let now = Instant::now();
+        let later = now + Duration::from_secs(20);
+        let mut pending = CorrelationMap::new(Duration::from_secs(30));
+        let msg_id = MessageId::new();
+        assert_eq!(pending.insert_at(msg_id, "first", now), None);
+        assert_eq!(pending.remove(&msg_id), Some("first"));
+        assert_eq!(pending.insert_at(msg_id, "again", later), None);
+
+        // The expiry of the removed request doesn't apply to the one inserted again.
+        assert!(pending.prune_at(now + Duration::from_secs(30)).is_empty());
+        assert_eq!(pending.len(), 1);
+        assert_eq!(
+            pending.prune_at(later + Duration::from_secs(30)),
+            vec![(msg_id, "again")]
+        );
+        assert!(pending.is_empty());
+    }
+}